@@ -2,28 +2,59 @@ grammar Thor;
 
 statements: '\n'* statement ('\n'+ statement)* '\n'*;
 
-statement: ('let' | 'return')? expr;
+statement:
+	('let' IDENTIFIER (':' type)? '=' expr)
+	| ('break' LABEL? expr?)
+	| ('continue' LABEL?)
+	| 'return'? expr;
 
 expr:
-	| (IDENTIFIER (('+' | '-' | '*' | '/')? '=') expr)
+	| (
+		IDENTIFIER (
+			('+' | '-' | '*' | '/' | '%' | '^' | '&' | '|' | '<<' | '>>')? '='
+		) expr
+	)
 	| or_expr;
 
-or_expr: and_expr ('or' or_expr)*;
+or_expr: and_expr ('or' and_expr)*;
 
-and_expr: not_expr ('and' and_expr)*;
+and_expr: not_expr ('and' not_expr)*;
 
 not_expr: ('not' not_expr) | comp_expr;
 
 comp_expr:
-	arith_expr ('==' | '!=' | '>' | '>=' | '<' | '<=' comp_expr)*;
+	range_expr (
+		(
+			'=='
+			| '!='
+			| '>'
+			| '>='
+			| '<'
+			| '<='
+			| 'is'
+			| 'is' 'not'
+			| 'in'
+			| 'not' 'in'
+		) range_expr
+	)*;
 
-arith_expr: term (('+' | '-') arith_expr)*;
+range_expr: bit_or_expr ('..' bit_or_expr)?;
 
-term: factor (('*' | '/') term)*;
+bit_or_expr: bit_and_expr ('|' bit_and_expr)*;
 
-factor: ('+' | '-') factor | call;
+bit_and_expr: shift_expr ('&' shift_expr)*;
 
-call: ((IDENTIFIER | TYPE) '(' expr? (',' expr)* ')') | atom;
+shift_expr: arith_expr (('<<' | '>>') arith_expr)*;
+
+arith_expr: term (('+' | '-') term)*;
+
+term: factor (('*' | '/' | '%') factor)*;
+
+factor: ('+' | '-' | '~') factor | power;
+
+power: call ('^' factor)?;
+
+call: ((IDENTIFIER | type) '(' expr? (',' expr)* ')') | atom;
 
 atom:
 	INT
@@ -35,10 +66,12 @@ atom:
 	| '(' expr ')'
 	| array_expr
 	| if_expr
-	| while_expr
-	| for_expr
+	| (LABEL ':')? (while_expr | for_expr | loop_expr)
 	| fn_expr
-	| atom '[' INT ']';
+	| struct_expr
+	| struct_literal
+	| atom '[' INT ']'
+	| atom '.' IDENTIFIER;
 
 array_expr: '[' expr? (',' expr)* ']';
 
@@ -50,12 +83,24 @@ while_expr: 'while' expr (':' statement | block);
 
 for_expr: 'for' IDENTIFIER 'in' expr (':' statement | block);
 
+loop_expr: 'loop' (':' statement | block);
+
 fn_expr: (
-		'fn' IDENTIFIER '(' (IDENTIFIER ':' TYPE)? (
-			',' IDENTIFIER ':' TYPE
-		)* ')' (':' TYPE)? block
+		'fn' IDENTIFIER '(' (IDENTIFIER ':' type)? (
+			',' IDENTIFIER ':' type
+		)* ')' (':' type)? block
 	);
 
+struct_expr:
+	'struct' IDENTIFIER '{' '\n'* (
+		IDENTIFIER ':' type (',' | '\n'+)?
+	)* '}';
+
+struct_literal:
+	IDENTIFIER '{' '\n'* (IDENTIFIER ':' expr (',' | '\n'+)?)* '}';
+
+type: (TYPE ('[' INT? ']')?) | IDENTIFIER;
+
 block: '{' statements '}';
 
 INT: [0-9]+;
@@ -64,4 +109,6 @@ BOOLEAN: 'true' | 'false';
 STRING: '"' .*? '"';
 CHAR: '\'' . '\'';
 TYPE: 'int' | 'float' | 'bool' | 'str' | 'char';
-IDENTIFIER: [a-zA-Z] [a-zA-Z0-9_]*;
\ No newline at end of file
+IDENTIFIER: [a-zA-Z] [a-zA-Z0-9_]*;
+
+LABEL: '\'' [a-zA-Z_] [a-zA-Z0-9_]*;
\ No newline at end of file
@@ -1,3 +1,3 @@
 #!/bin/sh
 clear
-cargo run -- test.thor test $1 && ./test
\ No newline at end of file
+cargo run -- run test.thor "$@"
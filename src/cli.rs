@@ -0,0 +1,224 @@
+#[cfg(feature = "llvm")]
+use inkwell::OptimizationLevel;
+
+pub const USAGE: &str = "\
+Usage: thor <command> [options] <file>
+
+Commands:
+  build    Compile a file to an executable
+  run      Compile and run a file in memory, passing any arguments after `--` to it
+  check    Check a file for errors without generating code
+  emit     Output one of --tokens, --ast, --ir, --asm or --obj
+  repl     Start an interactive session
+
+Options:
+  -o <path>            Write the output to <path>
+  -O0, -O1, -O2, -O3   Set the optimization level (default: -O3)
+  -Os                  Optimize for size
+  --target <triple>    Compile for <triple> instead of the host
+  --linker <path>      Link with <path> instead of clang
+  --debug              Check arithmetic for overflow
+  --no-bounds-checks   Skip the length comparison on every index
+  --interp             Make `run` use the interpreter instead of the JIT
+  -h, --help           Print this message
+  -V, --version        Print the version
+";
+
+pub enum Invocation {
+    Compile(Cli),
+    Repl(Options),
+    Help,
+    Version,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Build,
+    Run,
+    Check,
+    Emit(Emit),
+    Repl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    Ir,
+    Asm,
+    Obj,
+}
+
+// Builds without LLVM only use what the interpreter needs
+#[cfg_attr(not(feature = "llvm"), allow(dead_code))]
+pub struct Cli {
+    pub command: Command,
+    pub path: String,
+    pub output: Option<String>,
+    pub target: Option<String>,
+    pub linker: String,
+    pub options: Options,
+    // Passed through to the program by `thor run`
+    pub program_args: Vec<String>,
+    // Whether `thor run` walks the syntax tree rather than compiling it
+    pub interpret: bool,
+}
+
+// Settings for the generated code
+#[cfg_attr(not(feature = "llvm"), allow(dead_code))]
+pub struct Options {
+    pub bounds_checks: bool,
+    pub overflow_checks: bool,
+    pub opt_level: OptLevel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+    O3,
+    Os,
+}
+
+impl OptLevel {
+    fn from_flag(flag: &str) -> Option<Self> {
+        match flag {
+            "-O0" => Some(Self::O0),
+            "-O1" => Some(Self::O1),
+            "-O2" => Some(Self::O2),
+            "-O3" => Some(Self::O3),
+            "-Os" => Some(Self::Os),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "llvm")]
+    pub fn llvm(self) -> OptimizationLevel {
+        match self {
+            Self::O0 => OptimizationLevel::None,
+            Self::O1 => OptimizationLevel::Less,
+            Self::O2 | Self::Os => OptimizationLevel::Default,
+            Self::O3 => OptimizationLevel::Aggressive,
+        }
+    }
+
+    // Same thresholds clang uses for each level
+    #[cfg(feature = "llvm")]
+    pub fn inline_threshold(self) -> Option<u32> {
+        match self {
+            Self::O0 | Self::O1 => None,
+            Self::O2 => Some(225),
+            Self::O3 => Some(275),
+            Self::Os => Some(75),
+        }
+    }
+}
+
+// Parses the arguments after the program name
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Invocation, String> {
+    let mut args = args.into_iter();
+    let command = match args.next().as_deref() {
+        Some("build") => Command::Build,
+        Some("run") => Command::Run,
+        Some("check") => Command::Check,
+        Some("repl") => Command::Repl,
+        // The format is picked by a flag below
+        Some("emit") => Command::Emit(Emit::Ir),
+        Some("-h" | "--help" | "help") | None => return Ok(Invocation::Help),
+        Some("-V" | "--version") => return Ok(Invocation::Version),
+        Some(other) => return Err(format!("unknown command `{}`", other)),
+    };
+
+    let mut path = None;
+    let mut output = None;
+    let mut target = None;
+    let mut linker = None;
+    let mut emit = None;
+    let mut options = Options {
+        bounds_checks: true,
+        overflow_checks: false,
+        opt_level: OptLevel::O3,
+    };
+    let mut program_args = vec![];
+    let mut interpret = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Invocation::Help),
+            "-V" | "--version" => return Ok(Invocation::Version),
+            "-o" => output = Some(value(&mut args, "-o")?),
+            "--target" => target = Some(value(&mut args, "--target")?),
+            "--linker" => linker = Some(value(&mut args, "--linker")?),
+            "--debug" => options.overflow_checks = true,
+            "--no-bounds-checks" => options.bounds_checks = false,
+            "--interp" => interpret = true,
+            "--tokens" => emit = Some(Emit::Tokens),
+            "--ast" => emit = Some(Emit::Ast),
+            "--ir" => emit = Some(Emit::Ir),
+            "--asm" => emit = Some(Emit::Asm),
+            "--obj" => emit = Some(Emit::Obj),
+            "--" if command == Command::Run => {
+                program_args.extend(args.by_ref());
+            }
+            flag if flag.starts_with("-O") => {
+                options.opt_level = OptLevel::from_flag(flag).ok_or_else(|| {
+                    format!(
+                        "unknown optimization level `{}`, expected one of -O0, -O1, -O2, -O3 or -Os",
+                        flag
+                    )
+                })?;
+            }
+            flag if flag.starts_with('-') => return Err(format!("unknown option `{}`", flag)),
+            _ if path.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => path = Some(arg),
+        }
+    }
+
+    let command = match (command, emit) {
+        (Command::Emit(_), Some(emit)) => Command::Emit(emit),
+        (Command::Emit(_), None) => {
+            return Err(String::from(
+                "`emit` needs one of --tokens, --ast, --ir, --asm or --obj",
+            ))
+        }
+        (_, Some(_)) => return Err(String::from("output formats can only be given to `emit`")),
+        (command, None) => command,
+    };
+    if interpret && command != Command::Run {
+        return Err(String::from("only `run` can use the interpreter"));
+    }
+    if command == Command::Repl {
+        if let Some(path) = path {
+            return Err(format!("unexpected argument `{}`", path));
+        }
+        if output.is_some() || target.is_some() || linker.is_some() {
+            return Err(String::from(
+                "`repl` runs in this process, so it can't take -o, --target or --linker",
+            ));
+        }
+        return Ok(Invocation::Repl(options));
+    }
+    if command == Command::Run && (target.is_some() || linker.is_some()) {
+        return Err(String::from(
+            "`run` executes in this process, so it can't take --target or --linker",
+        ));
+    }
+    let path = path.ok_or_else(|| String::from("no input file given"))?;
+
+    Ok(Invocation::Compile(Cli {
+        command,
+        path,
+        output,
+        target,
+        linker: linker.unwrap_or_else(|| String::from("clang")),
+        options,
+        program_args,
+        interpret,
+    }))
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("`{}` needs a value", flag))
+}
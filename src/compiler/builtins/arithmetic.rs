@@ -0,0 +1,338 @@
+use inkwell::{
+    values::{FloatValue, IntValue},
+    IntPredicate,
+};
+
+use crate::{
+    compiler::{Codegen, Value},
+    BinaryOp, Span,
+};
+
+impl<'a, 'ctx> Codegen<'a, 'ctx> {
+    // Integer +, - and *, which abort on overflow when overflow checks are on
+    pub fn int_arithmetic(
+        &self,
+        op: BinaryOp,
+        l: IntValue<'ctx>,
+        r: IntValue<'ctx>,
+        span: Span,
+    ) -> IntValue<'ctx> {
+        if self.overflow_checks {
+            self.checked_int_arithmetic(op, l, r, span)
+        } else {
+            self.wrapping_int_arithmetic(op, l, r)
+        }
+    }
+
+    fn wrapping_int_arithmetic(
+        &self,
+        op: BinaryOp,
+        l: IntValue<'ctx>,
+        r: IntValue<'ctx>,
+    ) -> IntValue<'ctx> {
+        match op {
+            BinaryOp::Add => self.builder.build_int_add(l, r, "add"),
+            BinaryOp::Sub => self.builder.build_int_sub(l, r, "sub"),
+            BinaryOp::Mul => self.builder.build_int_mul(l, r, "mul"),
+            _ => unreachable!("{} can't overflow", op),
+        }
+    }
+
+    fn checked_int_arithmetic(
+        &self,
+        op: BinaryOp,
+        l: IntValue<'ctx>,
+        r: IntValue<'ctx>,
+        span: Span,
+    ) -> IntValue<'ctx> {
+        let (intrinsic, message) = match op {
+            BinaryOp::Add => (
+                "llvm.sadd.with.overflow.i32",
+                "attempt to add with overflow",
+            ),
+            BinaryOp::Sub => (
+                "llvm.ssub.with.overflow.i32",
+                "attempt to subtract with overflow",
+            ),
+            BinaryOp::Mul => (
+                "llvm.smul.with.overflow.i32",
+                "attempt to multiply with overflow",
+            ),
+            _ => unreachable!("{} can't overflow", op),
+        };
+        self.with_overflow(intrinsic, l, r, span, message)
+    }
+
+    // Calls one of the `llvm.*.with.overflow` intrinsics, panicking if the
+    // overflow bit is set
+    fn with_overflow(
+        &self,
+        intrinsic: &str,
+        l: IntValue<'ctx>,
+        r: IntValue<'ctx>,
+        span: Span,
+        message: &str,
+    ) -> IntValue<'ctx> {
+        let result_type = self
+            .context
+            .struct_type(&[self.int_type.into(), self.bool_type.into()], false);
+        let fn_type = result_type.fn_type(&[self.int_type.into(), self.int_type.into()], false);
+        let result = self
+            .builder
+            .build_call(
+                self.extern_function(intrinsic, fn_type),
+                &[l.into(), r.into()],
+                "result",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_struct_value();
+
+        let value = self
+            .builder
+            .build_extract_value(result, 0, "value")
+            .unwrap();
+        let overflow = self
+            .builder
+            .build_extract_value(result, 1, "overflow")
+            .unwrap()
+            .into_int_value();
+        let no_overflow = self.builder.build_not(overflow, "no_overflow");
+        self.check(no_overflow, span, message, &[]);
+        value.into_int_value()
+    }
+
+    pub fn int_neg(&self, value: IntValue<'ctx>, span: Span) -> IntValue<'ctx> {
+        if self.overflow_checks {
+            let zero = self.int_type.const_zero();
+            self.with_overflow(
+                "llvm.ssub.with.overflow.i32",
+                zero,
+                value,
+                span,
+                "attempt to negate with overflow",
+            )
+        } else {
+            self.builder.build_int_neg(value, "neg")
+        }
+    }
+
+    // Signed / and %, which always abort on a zero divisor and on the one
+    // quotient that doesn't fit, the minimum int divided by -1
+    pub fn int_division(
+        &self,
+        op: BinaryOp,
+        l: IntValue<'ctx>,
+        r: IntValue<'ctx>,
+        span: Span,
+    ) -> IntValue<'ctx> {
+        let (zero_message, overflow_message) = match op {
+            BinaryOp::Div => (
+                "attempt to divide by zero",
+                "attempt to divide with overflow",
+            ),
+            BinaryOp::Rem => (
+                "attempt to calculate the remainder with a divisor of zero",
+                "attempt to calculate the remainder with overflow",
+            ),
+            _ => unreachable!("{} isn't a division", op),
+        };
+
+        let zero = self.int_type.const_zero();
+        let nonzero = self
+            .builder
+            .build_int_compare(IntPredicate::NE, r, zero, "nonzero");
+        self.check(nonzero, span, zero_message, &[]);
+
+        let min = self.int_type.const_int(i32::MIN as u64, true);
+        let minus_one = self.int_type.const_all_ones();
+        let l_min = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, l, min, "min");
+        let r_minus_one =
+            self.builder
+                .build_int_compare(IntPredicate::EQ, r, minus_one, "minus_one");
+        let overflow = self.builder.build_and(l_min, r_minus_one, "overflow");
+        let no_overflow = self.builder.build_not(overflow, "no_overflow");
+        self.check(no_overflow, span, overflow_message, &[]);
+
+        match op {
+            BinaryOp::Div => self.builder.build_int_signed_div(l, r, "div"),
+            _ => self.builder.build_int_signed_rem(l, r, "rem"),
+        }
+    }
+
+    // Ints are raised by squaring, multiplying like `*` does so overflow
+    // wraps or aborts the same way. Negative exponents always abort
+    pub fn int_pow(&self, l: IntValue<'ctx>, r: IntValue<'ctx>, span: Span) -> IntValue<'ctx> {
+        let zero = self.int_type.const_zero();
+        let one = self.int_type.const_int(1, false);
+        let non_negative =
+            self.builder
+                .build_int_compare(IntPredicate::SGE, r, zero, "non_negative");
+        self.check(
+            non_negative,
+            span,
+            "attempt to raise an int to a negative power",
+            &[],
+        );
+        let start_block = self.builder.get_insert_block().unwrap();
+
+        let loop_block = self.context.append_basic_block(self.function, "pow_loop");
+        let body_block = self.context.append_basic_block(self.function, "pow_body");
+        let end_block = self.context.append_basic_block(self.function, "pow_end");
+        self.builder.build_unconditional_branch(loop_block);
+
+        self.builder.position_at_end(loop_block);
+        let result = self.builder.build_phi(self.int_type, "result");
+        let base = self.builder.build_phi(self.int_type, "base");
+        let exponent = self.builder.build_phi(self.int_type, "exponent");
+        let (result_value, base_value, exponent_value) = (
+            result.as_basic_value().into_int_value(),
+            base.as_basic_value().into_int_value(),
+            exponent.as_basic_value().into_int_value(),
+        );
+        let done = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, exponent_value, zero, "done");
+        self.builder
+            .build_conditional_branch(done, end_block, body_block);
+
+        // Multiplying by one instead of branching keeps the loop to one block,
+        // apart from the overflow checks'
+        self.builder.position_at_end(body_block);
+        let odd = self.builder.build_int_compare(
+            IntPredicate::NE,
+            self.builder.build_and(exponent_value, one, "low_bit"),
+            zero,
+            "odd",
+        );
+        let factor = self
+            .builder
+            .build_select(odd, base_value, one, "factor")
+            .into_int_value();
+        let next_result = self.int_arithmetic(BinaryOp::Mul, result_value, factor, span);
+        let next_exponent = self
+            .builder
+            .build_right_shift(exponent_value, one, false, "exponent");
+        // The base is only squared if it's used again, so the last square
+        // can't overflow when the result doesn't
+        let more = self
+            .builder
+            .build_int_compare(IntPredicate::NE, next_exponent, zero, "more");
+        let factor = self
+            .builder
+            .build_select(more, base_value, one, "factor")
+            .into_int_value();
+        let next_base = self.int_arithmetic(BinaryOp::Mul, base_value, factor, span);
+        let body_end = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(loop_block);
+
+        result.add_incoming(&[(&one, start_block), (&next_result, body_end)]);
+        base.add_incoming(&[(&l, start_block), (&next_base, body_end)]);
+        exponent.add_incoming(&[(&r, start_block), (&next_exponent, body_end)]);
+
+        self.builder.position_at_end(end_block);
+        result_value
+    }
+
+    pub fn powi(&self, l: FloatValue<'ctx>, r: IntValue<'ctx>) -> FloatValue<'ctx> {
+        let fn_type = self
+            .float_type
+            .fn_type(&[self.float_type.into(), self.int_type.into()], false);
+        self.builder
+            .build_call(
+                self.extern_function("llvm.powi.f64.i32", fn_type),
+                &[l.into(), r.into()],
+                "pow",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_float_value()
+    }
+
+    pub fn pow(&self, l: FloatValue<'ctx>, r: FloatValue<'ctx>) -> FloatValue<'ctx> {
+        let fn_type = self
+            .float_type
+            .fn_type(&[self.float_type.into(), self.float_type.into()], false);
+        self.builder
+            .build_call(
+                self.extern_function("llvm.pow.f64", fn_type),
+                &[l.into(), r.into()],
+                "pow",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_float_value()
+    }
+
+    // &, |, << and >> on ints or chars. The right side is resized to match the
+    // left, and `signed` picks an arithmetic right shift over a logical one
+    pub fn bitwise(
+        &self,
+        op: BinaryOp,
+        l: IntValue<'ctx>,
+        r: IntValue<'ctx>,
+        signed: bool,
+        span: Span,
+    ) -> IntValue<'ctx> {
+        let r = self.builder.build_int_cast(r, l.get_type(), "right");
+        match op {
+            BinaryOp::BitAnd => self.builder.build_and(l, r, "bit_and"),
+            BinaryOp::BitOr => self.builder.build_or(l, r, "bit_or"),
+            BinaryOp::Shl => {
+                let r = self.shift_amount(op, r, span);
+                self.builder.build_left_shift(l, r, "shl")
+            }
+            BinaryOp::Shr => {
+                let r = self.shift_amount(op, r, span);
+                self.builder.build_right_shift(l, r, signed, "shr")
+            }
+            _ => unreachable!("{} isn't a bitwise operator", op),
+        }
+    }
+
+    // LLVM's shifts are poison past the bit width, so an amount outside it
+    // aborts when overflow checks are on and otherwise wraps around it
+    fn shift_amount(&self, op: BinaryOp, r: IntValue<'ctx>, span: Span) -> IntValue<'ctx> {
+        let bits = r.get_type().get_bit_width() as u64;
+        if self.overflow_checks {
+            let message = match op {
+                BinaryOp::Shl => "attempt to shift left with overflow",
+                _ => "attempt to shift right with overflow",
+            };
+            // Negative amounts are huge unsigned ones
+            let in_range = self.builder.build_int_compare(
+                IntPredicate::ULT,
+                r,
+                r.get_type().const_int(bits, false),
+                "in_range",
+            );
+            self.check(in_range, span, message, &[]);
+            r
+        } else {
+            let mask = r.get_type().const_int(bits - 1, false);
+            self.builder.build_and(r, mask, "amount")
+        }
+    }
+
+    // wrapping_add and checked_add ignore whether overflow checks are on
+    pub fn arithmetic_builtin(
+        &self,
+        name: &str,
+        args: Vec<Value<'ctx>>,
+        span: Span,
+    ) -> Value<'ctx> {
+        let (l, r) = match args[..] {
+            [Value::Int(l), Value::Int(r)] => (l, r),
+            _ => unreachable!("`{}` takes two ints", name),
+        };
+        Value::Int(match name {
+            "wrapping_add" => self.wrapping_int_arithmetic(BinaryOp::Add, l, r),
+            _ => self.checked_int_arithmetic(BinaryOp::Add, l, r, span),
+        })
+    }
+}
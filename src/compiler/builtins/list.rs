@@ -0,0 +1,285 @@
+use inkwell::{
+    types::BasicType,
+    values::{BasicValueEnum, FunctionValue, IntValue, PointerValue, StructValue},
+    AddressSpace, IntPredicate,
+};
+
+use crate::{
+    compiler::{Codegen, Value},
+    Span, Type,
+};
+
+// The fields of a list's header
+const DATA: u32 = 0;
+const LEN: u32 = 1;
+const CAP: u32 = 2;
+
+impl<'a, 'ctx> Codegen<'a, 'ctx> {
+    pub fn list_data(&self, header: PointerValue<'ctx>) -> PointerValue<'ctx> {
+        let data_ptr = self.builder.build_struct_gep(header, DATA, "data").unwrap();
+        self.builder
+            .build_load(data_ptr, "data")
+            .into_pointer_value()
+    }
+
+    pub fn list_len(&self, header: PointerValue<'ctx>) -> IntValue<'ctx> {
+        let len_ptr = self.builder.build_struct_gep(header, LEN, "len").unwrap();
+        self.builder.build_load(len_ptr, "len").into_int_value()
+    }
+
+    fn list_cap(&self, header: PointerValue<'ctx>) -> IntValue<'ctx> {
+        let cap_ptr = self.builder.build_struct_gep(header, CAP, "cap").unwrap();
+        self.builder.build_load(cap_ptr, "cap").into_int_value()
+    }
+
+    fn store_list(
+        &self,
+        header: PointerValue<'ctx>,
+        data: PointerValue<'ctx>,
+        len: IntValue<'ctx>,
+        cap: IntValue<'ctx>,
+    ) {
+        let fields: [(u32, BasicValueEnum); 3] =
+            [(DATA, data.into()), (LEN, len.into()), (CAP, cap.into())];
+        for (field, value) in fields {
+            let field_ptr = self
+                .builder
+                .build_struct_gep(header, field, "field")
+                .unwrap();
+            self.builder.build_store(field_ptr, value);
+        }
+    }
+
+    // Every list header belongs to the scope it's created in, which frees the
+    // items when it ends
+    fn list_header(&mut self, item: &Type) -> PointerValue<'ctx> {
+        let list_type = Type::list_type(item, self.context);
+        let header = self.literal_slot(list_type.into(), "list");
+        self.scope.lists.push(header);
+        header
+    }
+
+    // Copies `len` items into a new heap allocation with no spare capacity
+    fn copy_items(
+        &self,
+        item: &Type,
+        items: PointerValue<'ctx>,
+        len: IntValue<'ctx>,
+    ) -> PointerValue<'ctx> {
+        let item_type = item.get_type(self.context);
+        let data = self
+            .builder
+            .build_array_malloc(item_type, len, "items")
+            .unwrap();
+        let size = self.builder.build_int_mul(
+            self.builder
+                .build_int_z_extend(len, self.context.i64_type(), "len"),
+            item_type.size_of().unwrap(),
+            "size",
+        );
+        self.builder.build_memcpy(data, 1, items, 1, size).unwrap();
+        data
+    }
+
+    pub fn new_list(
+        &mut self,
+        item: &Type,
+        items: PointerValue<'ctx>,
+        len: IntValue<'ctx>,
+    ) -> Value<'ctx> {
+        let data = self.copy_items(item, items, len);
+        let header = self.list_header(item);
+        self.store_list(header, data, len, len);
+        Value::List(header, item.clone())
+    }
+
+    // A copy of a list as a bare header value, owned by nothing yet
+    pub fn list_value(&self, header: PointerValue<'ctx>, item: &Type) -> StructValue<'ctx> {
+        let len = self.list_len(header);
+        let data = self.copy_items(item, self.list_data(header), len);
+
+        let mut value = Type::list_type(item, self.context).get_undef();
+        let fields: [(u32, BasicValueEnum); 3] =
+            [(DATA, data.into()), (LEN, len.into()), (CAP, len.into())];
+        for (field, field_value) in fields {
+            value = self
+                .builder
+                .build_insert_value(value, field_value, field, "list")
+                .unwrap()
+                .into_struct_value();
+        }
+        value
+    }
+
+    // Gives a header value returned from a function to the current scope
+    pub fn own_list(&mut self, value: StructValue<'ctx>, item: &Type) -> Value<'ctx> {
+        let header = self.list_header(item);
+        self.builder.build_store(header, value);
+        Value::List(header, item.clone())
+    }
+
+    // Replaces the items of `header` with a copy of the items of `source`
+    pub fn assign_list(&self, header: PointerValue<'ctx>, source: PointerValue<'ctx>, item: &Type) {
+        // Copied before the old items are freed in case the list is assigned to itself
+        let value = self.list_value(source, item);
+        self.builder.build_free(self.list_data(header));
+        self.builder.build_store(header, value);
+    }
+
+    pub fn slice_list(
+        &mut self,
+        items: PointerValue<'ctx>,
+        item: &Type,
+        (start, end): (IntValue<'ctx>, IntValue<'ctx>),
+        len: IntValue<'ctx>,
+        span: Span,
+    ) -> Value<'ctx> {
+        self.check_slice(start, end, len, span);
+        let start_ptr = unsafe { self.builder.build_gep(items, &[start], "slice") };
+        let len = self.builder.build_int_sub(end, start, "len");
+        self.new_list(item, start_ptr, len)
+    }
+
+    pub fn free_lists(&self, headers: Vec<PointerValue<'ctx>>) {
+        for header in headers {
+            self.builder.build_free(self.list_data(header));
+        }
+    }
+
+    fn realloc(&self) -> FunctionValue<'ctx> {
+        let fn_type = self.str_type.fn_type(
+            &[self.str_type.into(), self.context.i64_type().into()],
+            false,
+        );
+        self.extern_function("realloc", fn_type)
+    }
+
+    pub fn strlen(&self, ptr: PointerValue<'ctx>) -> IntValue<'ctx> {
+        let fn_type = self
+            .context
+            .i64_type()
+            .fn_type(&[self.str_type.into()], false);
+        let len = self
+            .builder
+            .build_call(
+                self.extern_function("strlen", fn_type),
+                &[ptr.into()],
+                "len",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        self.builder.build_int_truncate(len, self.int_type, "len")
+    }
+
+    fn push(&self, header: PointerValue<'ctx>, item: &Type, value: Value<'ctx>) {
+        let len = self.list_len(header);
+        let cap = self.list_cap(header);
+
+        let grow_block = self.context.append_basic_block(self.function, "push_grow");
+        let store_block = self.context.append_basic_block(self.function, "push_store");
+        let full = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, len, cap, "full");
+        self.builder
+            .build_conditional_branch(full, grow_block, store_block);
+
+        // Doubles the capacity, starting at 4
+        self.builder.position_at_end(grow_block);
+        let empty = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            cap,
+            self.int_type.const_zero(),
+            "empty",
+        );
+        let doubled = self
+            .builder
+            .build_int_mul(cap, self.int_type.const_int(2, false), "doubled");
+        let new_cap = self
+            .builder
+            .build_select(empty, self.int_type.const_int(4, false), doubled, "cap")
+            .into_int_value();
+
+        let item_type = item.get_type(self.context);
+        let size = self.builder.build_int_mul(
+            self.builder
+                .build_int_z_extend(new_cap, self.context.i64_type(), "cap"),
+            item_type.size_of().unwrap(),
+            "size",
+        );
+        let old_data =
+            self.builder
+                .build_pointer_cast(self.list_data(header), self.str_type, "data");
+        let new_data = self
+            .builder
+            .build_call(self.realloc(), &[old_data.into(), size.into()], "data")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value();
+        let new_data = self.builder.build_pointer_cast(
+            new_data,
+            item_type.ptr_type(AddressSpace::Generic),
+            "data",
+        );
+        self.store_list(header, new_data, len, new_cap);
+        self.builder.build_unconditional_branch(store_block);
+
+        self.builder.position_at_end(store_block);
+        let data = self.list_data(header);
+        let item_ptr = unsafe { self.builder.build_gep(data, &[len], "item") };
+        self.builder.build_store(item_ptr, value.get_value());
+        let next = self
+            .builder
+            .build_int_add(len, self.int_type.const_int(1, false), "len");
+        let len_ptr = self.builder.build_struct_gep(header, LEN, "len").unwrap();
+        self.builder.build_store(len_ptr, next);
+    }
+
+    fn pop(&self, header: PointerValue<'ctx>, item: &Type, span: Span) -> Value<'ctx> {
+        let len = self.list_len(header);
+        if self.bounds_checks {
+            let not_empty = self.builder.build_int_compare(
+                IntPredicate::NE,
+                len,
+                self.int_type.const_zero(),
+                "not_empty",
+            );
+            self.check(not_empty, span, "pop from an empty list", &[]);
+        }
+        let last = self
+            .builder
+            .build_int_sub(len, self.int_type.const_int(1, false), "last");
+        let len_ptr = self.builder.build_struct_gep(header, LEN, "len").unwrap();
+        self.builder.build_store(len_ptr, last);
+
+        let item_ptr = unsafe {
+            self.builder
+                .build_gep(self.list_data(header), &[last], "item")
+        };
+        Value::new(self.builder.build_load(item_ptr, "item"), item)
+    }
+
+    pub fn list_builtin(
+        &mut self,
+        name: &str,
+        mut args: Vec<Value<'ctx>>,
+        span: Span,
+    ) -> Value<'ctx> {
+        let list = args.remove(0);
+        match (name, list) {
+            ("len", Value::List(header, _)) => Value::Int(self.list_len(header)),
+            ("len", Value::Array(_, _, size)) => {
+                Value::Int(self.int_type.const_int(size as u64, false))
+            }
+            ("len", Value::Str(ptr)) => Value::Int(self.strlen(ptr)),
+            ("push", Value::List(header, item)) => {
+                self.push(header, &item, args.remove(0));
+                Value::Void
+            }
+            ("pop", Value::List(header, item)) => self.pop(header, &item, span),
+            _ => unreachable!("the type checker only passes lists to `{}`", name),
+        }
+    }
+}
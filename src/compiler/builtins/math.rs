@@ -1,14 +1,20 @@
+use inkwell::module::Linkage;
+
 use crate::{
-    compiler::{Codegen, Function, Value},
+    compiler::{Codegen, Function},
     Type,
 };
 
 impl<'a, 'ctx> Codegen<'a, 'ctx> {
     pub fn math(&mut self) {
-        self.add_var(
-            "PI",
-            Value::Float(self.float_type.const_float(std::f64::consts::PI)),
-        );
+        // A global rather than a stack slot, so every function can read it
+        let pi = self.module.add_global(self.float_type, None, "PI");
+        pi.set_initializer(&self.float_type.const_float(std::f64::consts::PI));
+        pi.set_constant(true);
+        pi.set_linkage(Linkage::Private);
+        self.scope
+            .variables
+            .insert("PI".to_string(), (pi.as_pointer_value(), Type::Float));
 
         self.abs();
         self.round();
@@ -1,5 +1,7 @@
+mod arithmetic;
+mod list;
 mod math;
+mod panic;
 mod print;
 
-pub use math::*;
-pub use print::*;
+pub use panic::{jump_buffer_type, PANIC_JUMP};
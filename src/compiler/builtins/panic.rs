@@ -0,0 +1,249 @@
+use inkwell::{
+    attributes::{Attribute, AttributeLoc},
+    context::Context,
+    module::Linkage,
+    types::ArrayType,
+    values::{BasicMetadataValueEnum, FunctionValue, IntValue, PointerValue},
+    IntPredicate,
+};
+
+use crate::{compiler::Codegen, Span};
+
+// Enough for any message with a few numbers in it
+const MESSAGE_SIZE: u64 = 128;
+
+// Where a REPL entry saves its registers for a panic to jump back to
+pub const PANIC_JUMP: &str = "thor_panic_jump";
+
+// Big enough for a jmp_buf on any platform LLVM JITs for
+pub fn jump_buffer_type<'ctx>(context: &'ctx Context) -> ArrayType<'ctx> {
+    context.i64_type().array_type(64)
+}
+
+impl<'a, 'ctx> Codegen<'a, 'ctx> {
+    // thor_panic(file, line, column, msg) prints "<msg> at <file>:<line>:<column>"
+    // to stderr and exits with code 101. In the REPL it jumps back to the
+    // entry instead, which returns 101 and leaves the session running
+    fn thor_panic(&self) -> FunctionValue<'ctx> {
+        if let Some(function) = self.module.get_function("thor_panic") {
+            return function;
+        }
+
+        let fn_type = self.context.void_type().fn_type(
+            &[
+                self.str_type.into(),
+                self.int_type.into(),
+                self.int_type.into(),
+                self.str_type.into(),
+            ],
+            false,
+        );
+        let function = self
+            .module
+            .add_function("thor_panic", fn_type, Some(Linkage::Private));
+
+        let dprintf = self.extern_function(
+            "dprintf",
+            self.int_type
+                .fn_type(&[self.int_type.into(), self.str_type.into()], true),
+        );
+        let parent_block = self.builder.get_insert_block();
+        let block = self.context.append_basic_block(function, "body");
+        self.builder.position_at_end(block);
+
+        let format = self
+            .builder
+            .build_global_string_ptr("%s at %s:%d:%d\n", "panic_format")
+            .as_pointer_value();
+        let params = function.get_params();
+        let stderr = self.int_type.const_int(2, false);
+        self.builder.build_call(
+            dprintf,
+            &[
+                stderr.into(),
+                format.into(),
+                params[3].into(),
+                params[0].into(),
+                params[1].into(),
+                params[2].into(),
+            ],
+            "print",
+        );
+        if self.repl {
+            let longjmp = self.extern_function(
+                "longjmp",
+                self.context
+                    .void_type()
+                    .fn_type(&[self.str_type.into(), self.int_type.into()], false),
+            );
+            let jump = self.panic_jump();
+            let value = self.int_type.const_int(1, false);
+            self.builder
+                .build_call(longjmp, &[jump.into(), value.into()], "unwind");
+        } else {
+            let exit = self.extern_function(
+                "exit",
+                self.context
+                    .void_type()
+                    .fn_type(&[self.int_type.into()], false),
+            );
+            let code = self.int_type.const_int(101, false);
+            self.builder.build_call(exit, &[code.into()], "exit");
+        }
+        self.builder.build_unreachable();
+
+        if let Some(block) = parent_block {
+            self.builder.position_at_end(block);
+        }
+        function
+    }
+
+    // The session defines the jump buffer once, so functions from earlier entries
+    // jump back to whichever entry is running
+    fn panic_jump(&self) -> PointerValue<'ctx> {
+        let global = self.module.get_global(PANIC_JUMP).unwrap_or_else(|| {
+            self.module
+                .add_global(jump_buffer_type(self.context), None, PANIC_JUMP)
+        });
+        self.builder
+            .build_pointer_cast(global.as_pointer_value(), self.str_type, "jump")
+    }
+
+    // Makes the REPL entry being generated return 101 when anything it runs
+    // panics, so the session can carry on without what it defined
+    pub fn catch_panics(&self) {
+        let fn_type = self.int_type.fn_type(&[self.str_type.into()], false);
+        let setjmp = self.module.get_function("setjmp").unwrap_or_else(|| {
+            let function = self.extern_function("setjmp", fn_type);
+            let kind = Attribute::get_named_enum_kind_id("returns_twice");
+            let returns_twice = self.context.create_enum_attribute(kind, 0);
+            function.add_attribute(AttributeLoc::Function, returns_twice);
+            function
+        });
+
+        let jump = self.panic_jump();
+        let jumped = self
+            .builder
+            .build_call(setjmp, &[jump.into()], "jumped")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let panicked = self.builder.build_int_compare(
+            IntPredicate::NE,
+            jumped,
+            self.int_type.const_zero(),
+            "panicked",
+        );
+        let panic_block = self.context.append_basic_block(self.function, "panicked");
+        let body_block = self.context.append_basic_block(self.function, "body");
+        self.builder
+            .build_conditional_branch(panicked, panic_block, body_block);
+
+        self.builder.position_at_end(panic_block);
+        let code = self.int_type.const_int(101, false);
+        self.builder.build_return(Some(&code));
+
+        self.builder.position_at_end(body_block);
+    }
+
+    // Calls thor_panic with a printf-style message, pointing at `span`
+    pub fn panic(&self, span: Span, format: &str, args: &[BasicMetadataValueEnum<'ctx>]) {
+        let snprintf = self.extern_function(
+            "snprintf",
+            self.int_type.fn_type(
+                &[
+                    self.str_type.into(),
+                    self.context.i64_type().into(),
+                    self.str_type.into(),
+                ],
+                true,
+            ),
+        );
+
+        let size = self.context.i64_type().const_int(MESSAGE_SIZE, false);
+        let message = self
+            .entry_builder()
+            .build_array_alloca(self.char_type, size, "message");
+        let format = self
+            .builder
+            .build_global_string_ptr(format, "message_format")
+            .as_pointer_value();
+        let mut snprintf_args = vec![message.into(), size.into(), format.into()];
+        snprintf_args.extend_from_slice(args);
+        self.builder.build_call(snprintf, &snprintf_args, "message");
+
+        let file = self
+            .builder
+            .build_global_string_ptr(&self.filename, "file")
+            .as_pointer_value();
+        let line = self.int_type.const_int(span.line as u64, false);
+        let column = self.int_type.const_int(span.column as u64, false);
+        self.builder.build_call(
+            self.thor_panic(),
+            &[file.into(), line.into(), column.into(), message.into()],
+            "panic",
+        );
+        self.builder.build_unreachable();
+    }
+
+    // Panics unless `condition` holds, continuing in a new block
+    pub fn check(
+        &self,
+        condition: IntValue<'ctx>,
+        span: Span,
+        format: &str,
+        args: &[BasicMetadataValueEnum<'ctx>],
+    ) {
+        let panic_block = self.context.append_basic_block(self.function, "panic");
+        let ok_block = self.context.append_basic_block(self.function, "ok");
+        self.builder
+            .build_conditional_branch(condition, ok_block, panic_block);
+
+        self.builder.position_at_end(panic_block);
+        self.panic(span, format, args);
+
+        self.builder.position_at_end(ok_block);
+    }
+
+    pub fn check_bounds(&self, index: IntValue<'ctx>, len: IntValue<'ctx>, span: Span) {
+        if !self.bounds_checks {
+            return;
+        }
+        // Negative indices wrap around to huge unsigned ones
+        let in_bounds = self
+            .builder
+            .build_int_compare(IntPredicate::ULT, index, len, "in_bounds");
+        self.check(
+            in_bounds,
+            span,
+            "index out of bounds: index %d, len %d",
+            &[index.into(), len.into()],
+        );
+    }
+
+    pub fn check_slice(
+        &self,
+        start: IntValue<'ctx>,
+        end: IntValue<'ctx>,
+        len: IntValue<'ctx>,
+        span: Span,
+    ) {
+        if !self.bounds_checks {
+            return;
+        }
+        let ordered = self
+            .builder
+            .build_int_compare(IntPredicate::ULE, start, end, "ordered");
+        let in_bounds = self
+            .builder
+            .build_int_compare(IntPredicate::ULE, end, len, "in_bounds");
+        let valid = self.builder.build_and(ordered, in_bounds, "valid");
+        self.check(
+            valid,
+            span,
+            "slice index out of bounds: %d..%d, len %d",
+            &[start.into(), end.into(), len.into()],
+        );
+    }
+}
@@ -1,5 +1,3 @@
-use inkwell::values::BasicValueEnum;
-
 use crate::{
     compiler::{Codegen, Function, Value},
     Type,
@@ -10,31 +8,59 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         Function::new_llvm("print", "printf", &[Type::Str], true, Type::Int, self);
     }
 
-    pub fn generate_printf_format_string(
-        &self,
-        compiled_args: &Vec<Value<'ctx>>,
-    ) -> BasicValueEnum<'ctx> {
+    // Turns print's arguments into printf's: a format string followed by
+    // every value, with struct fields passed one by one
+    pub fn print_args(&self, compiled_args: Vec<Value<'ctx>>) -> Vec<Value<'ctx>> {
         let mut format_string = String::from("");
+        let mut values = vec![];
 
         for arg in compiled_args {
-            let format_arg = match arg {
-                Value::Int(_) => "%i ",
-                Value::Float(_) => "%f ",
-                Value::Bool(_) => "%i ",
-                Value::Str(_) => "%s ",
-                Value::Char(_) => "%c ",
-                Value::Array(_, _, _) => "%p ",
-                Value::Void => "%p ",
-            };
-
-            format_string.push_str(format_arg);
+            self.format_value(arg, &mut format_string, &mut values);
+            format_string.push(' ');
         }
         format_string.push('\n');
 
-        BasicValueEnum::PointerValue(
-            self.builder
-                .build_global_string_ptr(format_string.as_str(), "printf_format_string")
-                .as_pointer_value(),
-        )
+        let format_ptr = self
+            .builder
+            .build_global_string_ptr(format_string.as_str(), "printf_format_string")
+            .as_pointer_value();
+        values.insert(0, Value::Str(format_ptr));
+        values
+    }
+
+    fn format_value(
+        &self,
+        value: Value<'ctx>,
+        format_string: &mut String,
+        values: &mut Vec<Value<'ctx>>,
+    ) {
+        let format_arg = match value {
+            Value::Int(_) => "%i",
+            Value::Float(_) => "%f",
+            Value::Bool(_) => "%i",
+            Value::Str(_) => "%s",
+            Value::Char(_) => "%c",
+            Value::Array(_, _, _) | Value::List(_, _) => "%p",
+            Value::Struct(struct_value, name) => {
+                format_string.push_str(&format!("{} {{ ", name));
+                for (i, (field, ty)) in self.structs[&name].iter().enumerate() {
+                    if i > 0 {
+                        format_string.push_str(", ");
+                    }
+                    format_string.push_str(&format!("{}: ", field));
+                    let field_value = self
+                        .builder
+                        .build_extract_value(struct_value, i as u32, field)
+                        .unwrap();
+                    self.format_value(Value::new(field_value, ty), format_string, values);
+                }
+                format_string.push_str(" }");
+                return;
+            }
+            Value::Void => "%p",
+        };
+
+        format_string.push_str(format_arg);
+        values.push(value);
     }
 }
@@ -1,15 +1,19 @@
+use std::{collections::HashMap, mem};
+
 use inkwell::{
+    basic_block::BasicBlock,
     builder::Builder,
     context::Context,
-    module::Module,
-    types::{FloatType, IntType, PointerType},
-    values::{BasicValueEnum, FloatValue, FunctionValue, IntValue, PointerValue},
+    module::{Linkage, Module},
+    types::{BasicType, BasicTypeEnum, FloatType, FunctionType, IntType, PointerType},
+    values::{BasicValue, BasicValueEnum, FunctionValue, IntValue, PointerValue},
     AddressSpace, FloatPredicate, IntPredicate,
 };
 
 use crate::{
     compiler::{Function, Scope, Value},
-    BinaryOp, IdentifierOp, Node, Type, TypeLiteral, UnaryOp,
+    typeck::LIST_BUILTINS,
+    BinaryOp, Diagnostic, IdentifierOp, Node, NodeKind, Span, Type, UnaryOp,
 };
 
 pub struct Codegen<'a, 'ctx> {
@@ -17,13 +21,38 @@ pub struct Codegen<'a, 'ctx> {
     pub module: &'a Module<'ctx>,
     pub builder: Builder<'ctx>,
     pub function: FunctionValue<'ctx>,
-    pub scope: Scope<'a, 'ctx>,
+    pub scope: Scope<'ctx>,
+    pub structs: HashMap<String, Vec<(String, Type)>>,
+    pub filename: String,
+    pub bounds_checks: bool,
+    pub overflow_checks: bool,
+    // Set for REPL entries, whose top-level values outlive the call that makes them
+    pub repl: bool,
 
     pub int_type: IntType<'ctx>,
     pub float_type: FloatType<'ctx>,
     pub bool_type: IntType<'ctx>,
     pub char_type: IntType<'ctx>,
     pub str_type: PointerType<'ctx>,
+
+    main: FunctionValue<'ctx>,
+    // What the function being generated returns
+    return_type: Type,
+    // The loops being generated, innermost last
+    loops: Vec<Loop<'ctx>>,
+}
+
+// Where `break` and `continue` jump to from inside a loop
+struct Loop<'ctx> {
+    label: Option<String>,
+    continue_block: BasicBlock<'ctx>,
+    end_block: BasicBlock<'ctx>,
+    // How many scopes deep the loop is, so a jump out frees the lists of the
+    // scopes it leaves
+    depth: usize,
+    // Each `break` value and the block it breaks from
+    breaks: Vec<(BasicValueEnum<'ctx>, BasicBlock<'ctx>)>,
+    ty: Option<Type>,
 }
 
 impl<'a, 'ctx> Codegen<'a, 'ctx> {
@@ -44,159 +73,1018 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         builder.position_at_end(block);
 
         let mut codegen = Self {
-            context: &context,
-            module: &module,
+            context,
+            module,
             builder,
             function,
-            scope: Scope::new(None),
+            scope: Scope::new(),
+            structs: HashMap::new(),
+            filename: filename.to_string(),
+            bounds_checks: true,
+            overflow_checks: false,
+            repl: false,
 
             int_type,
             float_type: context.f64_type(),
             bool_type: context.bool_type(),
             char_type: context.i8_type(),
             str_type,
+
+            main: function,
+            return_type: Type::Int,
+            loops: vec![],
         };
         codegen.print();
         codegen.math();
+        // User code gets its own scope so it can shadow builtins
+        codegen.scope.push();
+        codegen.scope.function = true;
         codegen
     }
 
-    pub fn create_child(&'a self, function: FunctionValue<'ctx>) -> Self {
-        Self {
-            context: self.context,
-            module: self.module,
-            builder: self.context.create_builder(),
-            function,
-            scope: Scope::new(Some(&self.scope)),
+    pub fn generate_llvm_ir(&mut self, mut ast: Node) -> Result<(), Diagnostic> {
+        self.declare_structs(&mut ast);
+        if self.repl {
+            self.catch_panics();
+        }
+        self.visit(ast)?;
+        // The REPL's variables keep their lists for later entries
+        if !self.repl {
+            self.free_lists(self.scope.function_lists());
+        }
+        self.builder.build_return(Some(&self.int_type.const_zero()));
+        Ok(())
+    }
+
+    fn push_loop(
+        &mut self,
+        label: Option<String>,
+        continue_block: BasicBlock<'ctx>,
+        end_block: BasicBlock<'ctx>,
+    ) {
+        self.loops.push(Loop {
+            label,
+            continue_block,
+            end_block,
+            depth: self.scope.depth(),
+            breaks: vec![],
+            ty: None,
+        });
+    }
 
-            int_type: self.int_type,
-            float_type: self.float_type,
-            bool_type: self.bool_type,
-            char_type: self.char_type,
-            str_type: self.str_type,
+    // The innermost loop with the label, or the innermost loop without one
+    fn find_loop(&self, label: &Option<String>) -> usize {
+        let index = match label {
+            Some(label) => self
+                .loops
+                .iter()
+                .rposition(|l| l.label.as_ref() == Some(label)),
+            None => self.loops.len().checked_sub(1),
+        };
+        index.expect("jumps are checked by the type checker")
+    }
+
+    // Code after a jump or return goes in a block nothing branches to, so
+    // every block keeps a single terminator
+    fn unreachable_block(&self, name: &str) {
+        let block = self.context.append_basic_block(self.function, name);
+        self.builder.position_at_end(block);
+    }
+
+    // Leaves the current block for `block`, freeing the lists of the scopes
+    // jumped out of
+    fn jump_out(&mut self, block: BasicBlock<'ctx>, depth: usize) {
+        self.free_lists(self.scope.lists_above(depth));
+        self.builder.build_unconditional_branch(block);
+        self.unreachable_block("after_jump");
+    }
+
+    // A loop's value is whichever `break` left it, or a `while` loop's else
+    fn loop_value(&mut self, l: Loop<'ctx>) -> Value<'ctx> {
+        let ty = match l.ty {
+            Some(ty) if !l.breaks.is_empty() => ty,
+            _ => return Value::Int(self.int_type.const_zero()),
+        };
+        let phi = self.builder.build_phi(l.breaks[0].0.get_type(), "loop");
+        let incoming: Vec<(&dyn BasicValue, _)> = l
+            .breaks
+            .iter()
+            .map(|(value, block)| (value as &dyn BasicValue, *block))
+            .collect();
+        phi.add_incoming(&incoming);
+        match ty {
+            Type::List(item) => self.own_list(phi.as_basic_value().into_struct_value(), &item),
+            ty => Value::new(phi.as_basic_value(), &ty),
         }
     }
 
-    pub fn generate_llvm_ir(&mut self, ast: Node) {
-        self.visit(ast);
-        self.builder.build_return(Some(&self.int_type.const_zero()));
+    // Frees the scope's lists, unless its block already returned
+    fn pop_scope(&mut self) {
+        let block = self.builder.get_insert_block().unwrap();
+        if block.get_terminator().is_none() {
+            self.free_lists(self.scope.lists.clone());
+        }
+        self.scope.pop();
+    }
+
+    // Declares a C library function the first time it's used
+    pub fn extern_function(&self, name: &str, fn_type: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+        self.module.get_function(name).unwrap_or_else(|| {
+            self.module
+                .add_function(name, fn_type, Some(Linkage::External))
+        })
     }
 
     pub fn add_var(&mut self, name: &str, value: Value<'ctx>) {
-        self.scope
-            .set(name.to_string(), value, &self.context, &self.builder);
+        let entry_builder = self.entry_builder();
+        self.scope.set(
+            name.to_string(),
+            value,
+            self.context,
+            &self.builder,
+            &entry_builder,
+        );
+    }
+
+    // Where a string, array or list literal keeps its data. The REPL's top-level
+    // code uses a global, since variables in later entries can still point to it
+    pub fn literal_slot(&self, ty: BasicTypeEnum<'ctx>, name: &str) -> PointerValue<'ctx> {
+        if self.repl && self.function == self.main {
+            let global = self.module.add_global(ty, None, name);
+            global.set_linkage(Linkage::Private);
+            global.set_initializer(&ty.const_zero());
+            global.as_pointer_value()
+        } else {
+            self.entry_builder().build_alloca(ty, name)
+        }
+    }
+
+    // Stack slots all go at the start of the function, so a slot in a loop is
+    // reused by every iteration and mem2reg can promote it to a register
+    pub fn entry_builder(&self) -> Builder<'ctx> {
+        let builder = self.context.create_builder();
+        let entry = self.function.get_first_basic_block().unwrap();
+        match entry.get_first_instruction() {
+            Some(instruction) => builder.position_before(&instruction),
+            None => builder.position_at_end(entry),
+        }
+        builder
+    }
+
+    // Struct types are visible everywhere, so they're all declared up front
+    fn declare_structs(&mut self, ast: &mut Node) {
+        fn definitions(node: &mut Node, structs: &mut Vec<(String, Vec<(String, Type)>)>) {
+            if let NodeKind::Struct(name, fields) = &node.kind {
+                structs.push((name.clone(), fields.clone()));
+            }
+            for child in node.children_mut() {
+                definitions(child, structs);
+            }
+        }
+
+        let mut structs = vec![];
+        definitions(ast, &mut structs);
+
+        // Every type has a name before any bodies are set, so fields can refer to any struct
+        for (name, _) in &structs {
+            self.context.opaque_struct_type(name);
+        }
+        for (name, fields) in structs {
+            let field_types = fields
+                .iter()
+                .map(|(_, ty)| ty.get_type(self.context))
+                .collect::<Vec<BasicTypeEnum<'ctx>>>();
+            let struct_type = self.context.get_struct_type(&name).unwrap();
+            struct_type.set_body(&field_types, false);
+            self.structs.insert(name, fields);
+        }
+    }
+
+    fn field_index(&self, ty: &Type, field: &str) -> (u32, Type) {
+        let name = match ty {
+            Type::Struct(name) => name,
+            _ => unreachable!("fields are only accessed on structs"),
+        };
+        self.structs[name]
+            .iter()
+            .enumerate()
+            .find(|(_, (other, _))| other == field)
+            .map(|(i, (_, ty))| (i as u32, ty.clone()))
+            .expect("fields are checked by the type checker")
+    }
+
+    fn declare_function(&mut self, name: &str, args: &[(String, Type)], return_type: &Type) {
+        let arg_types = args.iter().map(|(_, ty)| ty.clone()).collect::<Vec<Type>>();
+        let function = Function::new_user(name, arg_types, return_type.clone(), self);
+        self.scope.add_function(name.to_string(), function);
+    }
+
+    // Returns `value`. Arrays and structs are written through the pointer the
+    // caller passed instead
+    fn build_return(&self, value: BasicValueEnum<'ctx>) {
+        let result = match self.return_type.sret_type(self.context) {
+            Some(_) => self
+                .function
+                .get_first_param()
+                .unwrap()
+                .into_pointer_value(),
+            None => {
+                self.builder.build_return(Some(&value));
+                return;
+            }
+        };
+
+        match &self.return_type {
+            Type::Array(item, size) => {
+                let array_type = item.get_type(self.context).array_type(*size);
+                let size = array_type.size_of().unwrap();
+                self.builder
+                    .build_memcpy(result, 1, value.into_pointer_value(), 1, size)
+                    .unwrap();
+            }
+            _ => {
+                self.builder.build_store(result, value);
+            }
+        }
+        self.builder.build_return(None);
+    }
+
+    // Declares every function in a block before any bodies are generated, so
+    // functions can call each other regardless of the order they're defined in
+    fn declare_functions(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            if let NodeKind::Fn(name, args, return_type, _) = &node.kind {
+                let return_type = return_type.clone().unwrap_or(Type::Void);
+                self.declare_function(name, args, &return_type);
+            }
+        }
     }
 
-    fn visit(&mut self, node: Node) -> Value<'ctx> {
-        match node {
-            Node::Int(value) => Value::Int(self.int_type.const_int(value as u64, true)),
-            Node::Float(value) => Value::Float(self.float_type.const_float(value)),
-            Node::Bool(value) => {
+    fn visit_value(&mut self, node: Node) -> Result<Value<'ctx>, Diagnostic> {
+        match self.visit(node)? {
+            Value::Void => unreachable!("the type checker only uses values where there are some"),
+            value => Ok(value),
+        }
+    }
+
+    fn condition(&mut self, node: Node) -> Result<IntValue<'ctx>, Diagnostic> {
+        match self.visit(node)? {
+            Value::Bool(value) => Ok(value),
+            _ => unreachable!("conditions are bools"),
+        }
+    }
+
+    // An index or range bound, which are ints
+    fn int_value(&mut self, node: Node) -> Result<IntValue<'ctx>, Diagnostic> {
+        match self.visit(node)? {
+            Value::Int(value) => Ok(value),
+            _ => unreachable!("indices and range bounds are ints"),
+        }
+    }
+
+    // A pointer to the item at `index` of a string, array or list, checked
+    // against its length unless bounds checks are off
+    fn item_ptr(
+        &self,
+        ptr: PointerValue<'ctx>,
+        ty: &Type,
+        index: IntValue<'ctx>,
+        span: Span,
+    ) -> PointerValue<'ctx> {
+        let items = match ty {
+            Type::List(_) => self.list_data(ptr),
+            _ => ptr,
+        };
+        if self.bounds_checks {
+            let len = match ty {
+                Type::Str => self.strlen(ptr),
+                Type::Array(_, size) => self.int_type.const_int(*size as u64, false),
+                Type::List(_) => self.list_len(ptr),
+                _ => unreachable!("only strings, arrays and lists can be indexed"),
+            };
+            self.check_bounds(index, len, span);
+        }
+        unsafe { self.builder.build_gep(items, &[index], "index") }
+    }
+
+    fn assignment_ptr(&mut self, node: Node) -> Result<(PointerValue<'ctx>, Type), Diagnostic> {
+        let span = node.span;
+        match node.kind {
+            NodeKind::Identifier(name) => Ok(self
+                .scope
+                .get_variable(&name)
+                .expect("variables are checked by the type checker")),
+            NodeKind::Field(node, field) => {
+                let (ptr, ty) = self.assignment_ptr(*node)?;
+                let (index, field_ty) = self.field_index(&ty, &field);
+                let field_ptr = self.builder.build_struct_gep(ptr, index, &field).unwrap();
+                Ok((field_ptr, field_ty))
+            }
+            NodeKind::Index(node, index) => {
+                let (ptr, ty) = self.assignment_ptr(*node)?;
+                // Strings and arrays are held by reference, so the slot holds a pointer to the items
+                let ptr = match ty {
+                    Type::Str | Type::Array(..) => {
+                        self.builder.build_load(ptr, "items").into_pointer_value()
+                    }
+                    _ => ptr,
+                };
+                let item_ty = match ty {
+                    Type::Str => Type::Char,
+                    Type::Array(ref ty, _) | Type::List(ref ty) => *ty.clone(),
+                    _ => unreachable!("only strings, arrays and lists can be indexed"),
+                };
+                let index = self.int_value(*index)?;
+                let index_ptr = self.item_ptr(ptr, &ty, index, span);
+                Ok((index_ptr, item_ty))
+            }
+            _ => unreachable!("the type checker only assigns to variables, fields and items"),
+        }
+    }
+
+    // The right side of `and` and `or` only runs when the left doesn't decide the result
+    fn short_circuit(
+        &mut self,
+        op: BinaryOp,
+        left: Node,
+        right: Node,
+    ) -> Result<Value<'ctx>, Diagnostic> {
+        let l_value = self.condition(left)?;
+        let l_block = self.builder.get_insert_block().unwrap();
+
+        let right_block = self.context.append_basic_block(self.function, op_name(op));
+        let end_block = self
+            .context
+            .append_basic_block(self.function, &format!("{}_end", op_name(op)));
+        match op {
+            BinaryOp::And => self
+                .builder
+                .build_conditional_branch(l_value, right_block, end_block),
+            _ => self
+                .builder
+                .build_conditional_branch(l_value, end_block, right_block),
+        };
+
+        // The right side gets its own scope, so its lists are only freed if it ran
+        self.builder.position_at_end(right_block);
+        self.scope.push();
+        let r_value = self.condition(right)?;
+        self.pop_scope();
+        self.builder.build_unconditional_branch(end_block);
+        let r_block = self.builder.get_insert_block().unwrap();
+
+        self.builder.position_at_end(end_block);
+        let phi = self.builder.build_phi(self.bool_type, op_name(op));
+        phi.add_incoming(&[(&l_value, l_block), (&r_value, r_block)]);
+        Ok(Value::Bool(phi.as_basic_value().into_int_value()))
+    }
+
+    // Each comparison runs only if the ones before it held, and every operand
+    // is evaluated at most once
+    fn compare(
+        &mut self,
+        nodes: Vec<Node>,
+        ops: Vec<BinaryOp>,
+        span: Span,
+    ) -> Result<IntValue<'ctx>, Diagnostic> {
+        let last = ops.len() - 1;
+        let end_block = self
+            .context
+            .append_basic_block(self.function, "compare_end");
+        let mut incoming = vec![];
+
+        let mut nodes = nodes.into_iter();
+        let mut l_value = self.visit_value(nodes.next().unwrap())?;
+        // Each later operand gets its own scope, kept until the comparisons are
+        // done since the next comparison uses it too
+        let depth = self.scope.depth();
+        for (i, (op, right)) in ops.into_iter().zip(nodes).enumerate() {
+            self.scope.push();
+            let result = match right.kind {
+                NodeKind::Range(start, end) => {
+                    let found = self.in_range(l_value.clone(), *start, *end)?;
+                    match op {
+                        BinaryOp::NotIn => self.builder.build_not(found, "not_in"),
+                        _ => found,
+                    }
+                }
+                _ => {
+                    let r_value = self.visit_value(right)?;
+                    let result = self.binary(op, l_value, r_value.clone(), span)?;
+                    l_value = r_value;
+                    result.get_value().into_int_value()
+                }
+            };
+
+            if i == last {
+                for _ in depth..self.scope.depth() {
+                    self.pop_scope();
+                }
+                self.builder.build_unconditional_branch(end_block);
+                incoming.push((result, self.builder.get_insert_block().unwrap()));
+            } else {
+                let block = self.builder.get_insert_block().unwrap();
+                let next_block = self.context.insert_basic_block_after(block, "compare_next");
+                let fail_block = self.context.insert_basic_block_after(block, "compare_fail");
+                self.builder
+                    .build_conditional_branch(result, next_block, fail_block);
+                self.builder.position_at_end(fail_block);
+                self.free_lists(self.scope.lists_above(depth));
+                self.builder.build_unconditional_branch(end_block);
+                incoming.push((self.bool_type.const_zero(), fail_block));
+                self.builder.position_at_end(next_block);
+            }
+        }
+
+        self.builder.position_at_end(end_block);
+        let phi = self.builder.build_phi(self.bool_type, "compare");
+        let incoming: Vec<(&dyn BasicValue, _)> = incoming
+            .iter()
+            .map(|(value, block)| (value as &dyn BasicValue, *block))
+            .collect();
+        phi.add_incoming(&incoming);
+        Ok(phi.as_basic_value().into_int_value())
+    }
+
+    // What `is` compares: values directly, and strings, arrays and lists by address
+    fn identity(
+        &self,
+        l_value: &Value<'ctx>,
+        r_value: &Value<'ctx>,
+    ) -> Option<(IntValue<'ctx>, IntValue<'ctx>)> {
+        let address = |ptr| {
+            self.builder
+                .build_ptr_to_int(ptr, self.context.i64_type(), "address")
+        };
+        match (l_value, r_value) {
+            (Value::Int(l), Value::Int(r))
+            | (Value::Bool(l), Value::Bool(r))
+            | (Value::Char(l), Value::Char(r)) => Some((*l, *r)),
+            (Value::Float(l), Value::Float(r)) => {
+                let int_type = self.context.i64_type();
+                Some((
+                    self.builder
+                        .build_bitcast(*l, int_type, "bits")
+                        .into_int_value(),
+                    self.builder
+                        .build_bitcast(*r, int_type, "bits")
+                        .into_int_value(),
+                ))
+            }
+            (Value::Str(l), Value::Str(r))
+            | (Value::Array(l, _, _), Value::Array(r, _, _))
+            | (Value::List(l, _), Value::List(r, _)) => Some((address(*l), address(*r))),
+            _ => None,
+        }
+    }
+
+    // Whether `value` is in a range, which is never built
+    fn in_range(
+        &mut self,
+        value: Value<'ctx>,
+        start: Node,
+        end: Node,
+    ) -> Result<IntValue<'ctx>, Diagnostic> {
+        let value = match value {
+            Value::Int(value) => value,
+            _ => unreachable!("only ints can be in a range"),
+        };
+        let start = self.int_value(start)?;
+        let end = self.int_value(end)?;
+        let after_start =
+            self.builder
+                .build_int_compare(IntPredicate::SGE, value, start, "after_start");
+        let before_end =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, value, end, "before_end");
+        Ok(self.builder.build_and(after_start, before_end, "in"))
+    }
+
+    // Whether `value` is in the string, array or list `items`. Searching stops
+    // at the first match
+    fn contains(&self, value: Value<'ctx>, items: Value<'ctx>) -> IntValue<'ctx> {
+        let (ptr, len) = match items {
+            Value::Str(ptr) => (ptr, self.strlen(ptr)),
+            Value::Array(ptr, _, size) => (ptr, self.int_type.const_int(size as u64, false)),
+            Value::List(header, _) => (self.list_data(header), self.list_len(header)),
+            _ => unreachable!("only strings, arrays and lists can be searched"),
+        };
+
+        let entry_block = self.builder.get_insert_block().unwrap();
+        let condition_block = self.context.append_basic_block(self.function, "in_cond");
+        let body_block = self.context.append_basic_block(self.function, "in_body");
+        let step_block = self.context.append_basic_block(self.function, "in_step");
+        let end_block = self.context.append_basic_block(self.function, "in_end");
+        self.builder.build_unconditional_branch(condition_block);
+
+        self.builder.position_at_end(condition_block);
+        let index = self.builder.build_phi(self.int_type, "index");
+        let index_value = index.as_basic_value().into_int_value();
+        let more = self
+            .builder
+            .build_int_compare(IntPredicate::SLT, index_value, len, "more");
+        self.builder
+            .build_conditional_branch(more, body_block, end_block);
+
+        self.builder.position_at_end(body_block);
+        let item_ptr = unsafe { self.builder.build_gep(ptr, &[index_value], "item") };
+        let item = self.builder.build_load(item_ptr, "item");
+        let found = match value {
+            Value::Float(value) => self.builder.build_float_compare(
+                FloatPredicate::OEQ,
+                value,
+                item.into_float_value(),
+                "found",
+            ),
+            _ => self.builder.build_int_compare(
+                IntPredicate::EQ,
+                value.get_value().into_int_value(),
+                item.into_int_value(),
+                "found",
+            ),
+        };
+        self.builder
+            .build_conditional_branch(found, end_block, step_block);
+
+        self.builder.position_at_end(step_block);
+        let next =
+            self.builder
+                .build_int_add(index_value, self.int_type.const_int(1, false), "next");
+        self.builder.build_unconditional_branch(condition_block);
+        index.add_incoming(&[
+            (&self.int_type.const_zero(), entry_block),
+            (&next, step_block),
+        ]);
+
+        self.builder.position_at_end(end_block);
+        let result = self.builder.build_phi(self.bool_type, "in");
+        result.add_incoming(&[
+            (&self.bool_type.const_zero(), condition_block),
+            (&self.bool_type.const_int(1, false), body_block),
+        ]);
+        result.as_basic_value().into_int_value()
+    }
+
+    fn binary(
+        &mut self,
+        op: BinaryOp,
+        l_value: Value<'ctx>,
+        r_value: Value<'ctx>,
+        span: Span,
+    ) -> Result<Value<'ctx>, Diagnostic> {
+        let (l_ty, r_ty) = (l_value.ty(), r_value.ty());
+        let invalid =
+            || -> ! { unreachable!("the type checker rejects {} on {} and {}", op, l_ty, r_ty) };
+
+        let f64_type = self.float_type;
+
+        use BinaryOp::*;
+        // A char compared with an int is sign extended to one, like the interpreter reads it
+        let (l_value, r_value) = match (l_value, r_value) {
+            (Value::Char(l), Value::Int(r)) if matches!(op, EqEq | Neq | Lt | Lte | Gt | Gte) => {
+                let l = self.builder.build_int_cast(l, self.int_type, "left");
+                (Value::Int(l), Value::Int(r))
+            }
+            (Value::Int(l), Value::Char(r)) if matches!(op, EqEq | Neq | Lt | Lte | Gt | Gte) => {
+                let r = self.builder.build_int_cast(r, self.int_type, "right");
+                (Value::Int(l), Value::Int(r))
+            }
+            values => values,
+        };
+        Ok(match op {
+            Add => match l_value {
+                Value::Int(l) => match r_value {
+                    Value::Int(r) => Value::Int(self.int_arithmetic(op, l, r, span)),
+                    Value::Float(r) => Value::Float(self.builder.build_float_add(
+                        self.builder.build_signed_int_to_float(l, f64_type, "left"),
+                        r,
+                        "add",
+                    )),
+                    _ => invalid(),
+                },
+                Value::Float(l) => match r_value {
+                    Value::Int(r) => Value::Float(self.builder.build_float_add(
+                        l,
+                        self.builder.build_signed_int_to_float(r, f64_type, "right"),
+                        "add",
+                    )),
+                    Value::Float(r) => Value::Float(self.builder.build_float_add(l, r, "add")),
+                    _ => invalid(),
+                },
+                _ => invalid(),
+            },
+            Sub => match l_value {
+                Value::Int(l) => match r_value {
+                    Value::Int(r) => Value::Int(self.int_arithmetic(op, l, r, span)),
+                    Value::Float(r) => Value::Float(self.builder.build_float_sub(
+                        self.builder.build_signed_int_to_float(l, f64_type, "left"),
+                        r,
+                        "sub",
+                    )),
+                    _ => invalid(),
+                },
+                Value::Float(l) => match r_value {
+                    Value::Int(r) => Value::Float(self.builder.build_float_sub(
+                        l,
+                        self.builder.build_signed_int_to_float(r, f64_type, "right"),
+                        "sub",
+                    )),
+                    Value::Float(r) => Value::Float(self.builder.build_float_sub(l, r, "sub")),
+                    _ => invalid(),
+                },
+                _ => invalid(),
+            },
+            Mul => match l_value {
+                Value::Int(l) => match r_value {
+                    Value::Int(r) => Value::Int(self.int_arithmetic(op, l, r, span)),
+                    Value::Float(r) => Value::Float(self.builder.build_float_mul(
+                        self.builder.build_signed_int_to_float(l, f64_type, "left"),
+                        r,
+                        "mul",
+                    )),
+                    _ => invalid(),
+                },
+                Value::Float(l) => match r_value {
+                    Value::Int(r) => Value::Float(self.builder.build_float_mul(
+                        l,
+                        self.builder.build_signed_int_to_float(r, f64_type, "right"),
+                        "mul",
+                    )),
+                    Value::Float(r) => Value::Float(self.builder.build_float_mul(l, r, "mul")),
+                    _ => invalid(),
+                },
+                _ => invalid(),
+            },
+            Div => match l_value {
+                Value::Int(l) => match r_value {
+                    Value::Int(r) => Value::Int(self.int_division(op, l, r, span)),
+                    Value::Float(r) => Value::Float(self.builder.build_float_div(
+                        self.builder.build_signed_int_to_float(l, f64_type, "left"),
+                        r,
+                        "div",
+                    )),
+                    _ => invalid(),
+                },
+                Value::Float(l) => match r_value {
+                    Value::Int(r) => Value::Float(self.builder.build_float_div(
+                        l,
+                        self.builder.build_signed_int_to_float(r, f64_type, "right"),
+                        "div",
+                    )),
+                    Value::Float(r) => Value::Float(self.builder.build_float_div(l, r, "div")),
+                    _ => invalid(),
+                },
+                _ => invalid(),
+            },
+            Rem => match l_value {
+                Value::Int(l) => match r_value {
+                    Value::Int(r) => Value::Int(self.int_division(op, l, r, span)),
+                    Value::Float(r) => Value::Float(self.builder.build_float_rem(
+                        self.builder.build_signed_int_to_float(l, f64_type, "left"),
+                        r,
+                        "rem",
+                    )),
+                    _ => invalid(),
+                },
+                Value::Float(l) => match r_value {
+                    Value::Int(r) => Value::Float(self.builder.build_float_rem(
+                        l,
+                        self.builder.build_signed_int_to_float(r, f64_type, "right"),
+                        "rem",
+                    )),
+                    Value::Float(r) => Value::Float(self.builder.build_float_rem(l, r, "rem")),
+                    _ => invalid(),
+                },
+                _ => invalid(),
+            },
+            Pow => match (l_value, r_value) {
+                (Value::Int(l), Value::Int(r)) => Value::Int(self.int_pow(l, r, span)),
+                (Value::Float(l), Value::Int(r)) => Value::Float(self.powi(l, r)),
+                (Value::Int(l), Value::Float(r)) => Value::Float(self.pow(
+                    self.builder.build_signed_int_to_float(l, f64_type, "left"),
+                    r,
+                )),
+                (Value::Float(l), Value::Float(r)) => Value::Float(self.pow(l, r)),
+                _ => invalid(),
+            },
+            BitAnd | BitOr | Shl | Shr => match (l_value, r_value) {
+                (Value::Int(l), Value::Int(r) | Value::Char(r)) => {
+                    Value::Int(self.bitwise(op, l, r, true, span))
+                }
+                (Value::Char(l), Value::Int(r) | Value::Char(r)) => {
+                    Value::Char(self.bitwise(op, l, r, false, span))
+                }
+                _ => invalid(),
+            },
+            Is | IsNot => {
+                let predicate = match op {
+                    Is => IntPredicate::EQ,
+                    _ => IntPredicate::NE,
+                };
+                match self.identity(&l_value, &r_value) {
+                    Some((l, r)) => {
+                        Value::Bool(self.builder.build_int_compare(predicate, l, r, "is"))
+                    }
+                    None => invalid(),
+                }
+            }
+            In => Value::Bool(self.contains(l_value, r_value)),
+            NotIn => {
+                let found = self.contains(l_value, r_value);
+                Value::Bool(self.builder.build_not(found, "not_in"))
+            }
+            And | Or => unreachable!("{} only evaluates its right side lazily", op),
+            EqEq => {
+                match l_value {
+                    Value::Int(l) | Value::Char(l) => match r_value {
+                        Value::Int(r) | Value::Char(r) => Value::Bool(
+                            self.builder
+                                .build_int_compare(IntPredicate::EQ, l, r, "eqeq"),
+                        ),
+                        Value::Float(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::OEQ,
+                            self.builder.build_signed_int_to_float(l, f64_type, "left"),
+                            r,
+                            "eqeq",
+                        )),
+                        _ => invalid(),
+                    },
+                    Value::Float(l) => match r_value {
+                        Value::Int(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::OEQ,
+                            l,
+                            self.builder.build_signed_int_to_float(r, f64_type, "right"),
+                            "eqeq",
+                        )),
+                        Value::Float(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::OEQ,
+                            l,
+                            r,
+                            "eqeq",
+                        )),
+                        _ => invalid(),
+                    },
+                    Value::Bool(l) => match r_value {
+                        Value::Bool(r) => Value::Bool(
+                            self.builder
+                                .build_not(self.builder.build_xor(l, r, "xor"), "not"),
+                        ),
+                        _ => invalid(),
+                    },
+                    _ => invalid(),
+                }
+            }
+            Neq => {
+                match l_value {
+                    Value::Int(l) | Value::Char(l) => match r_value {
+                        Value::Int(r) | Value::Char(r) => Value::Bool(
+                            self.builder
+                                .build_int_compare(IntPredicate::NE, l, r, "neq"),
+                        ),
+                        Value::Float(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::ONE,
+                            self.builder.build_signed_int_to_float(l, f64_type, "left"),
+                            r,
+                            "neq",
+                        )),
+                        _ => invalid(),
+                    },
+                    Value::Float(l) => match r_value {
+                        Value::Int(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::ONE,
+                            l,
+                            self.builder.build_signed_int_to_float(r, f64_type, "right"),
+                            "neq",
+                        )),
+                        Value::Float(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::ONE,
+                            l,
+                            r,
+                            "neq",
+                        )),
+                        _ => invalid(),
+                    },
+                    Value::Bool(l) => match r_value {
+                        Value::Bool(r) => Value::Bool(self.builder.build_xor(l, r, "xor")),
+                        _ => invalid(),
+                    },
+                    _ => invalid(),
+                }
+            }
+            Lt => {
+                match l_value {
+                    Value::Int(l) | Value::Char(l) => match r_value {
+                        Value::Int(r) | Value::Char(r) => Value::Bool(
+                            self.builder
+                                .build_int_compare(IntPredicate::SLT, l, r, "lt"),
+                        ),
+                        Value::Float(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::OLT,
+                            self.builder.build_signed_int_to_float(l, f64_type, "left"),
+                            r,
+                            "lt",
+                        )),
+                        _ => invalid(),
+                    },
+                    Value::Float(l) => match r_value {
+                        Value::Int(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::OLT,
+                            l,
+                            self.builder.build_signed_int_to_float(r, f64_type, "right"),
+                            "lt",
+                        )),
+                        Value::Float(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::OLT,
+                            l,
+                            r,
+                            "lt",
+                        )),
+                        _ => invalid(),
+                    },
+                    _ => invalid(),
+                }
+            }
+            Lte => {
+                match l_value {
+                    Value::Int(l) | Value::Char(l) => match r_value {
+                        Value::Int(r) | Value::Char(r) => Value::Bool(
+                            self.builder
+                                .build_int_compare(IntPredicate::SLE, l, r, "lte"),
+                        ),
+                        Value::Float(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::OLE,
+                            self.builder.build_signed_int_to_float(l, f64_type, "left"),
+                            r,
+                            "lte",
+                        )),
+                        _ => invalid(),
+                    },
+                    Value::Float(l) => match r_value {
+                        Value::Int(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::OLE,
+                            l,
+                            self.builder.build_signed_int_to_float(r, f64_type, "right"),
+                            "lte",
+                        )),
+                        Value::Float(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::OLE,
+                            l,
+                            r,
+                            "lte",
+                        )),
+                        _ => invalid(),
+                    },
+                    _ => invalid(),
+                }
+            }
+            Gt => {
+                match l_value {
+                    Value::Int(l) | Value::Char(l) => match r_value {
+                        Value::Int(r) | Value::Char(r) => Value::Bool(
+                            self.builder
+                                .build_int_compare(IntPredicate::SGT, l, r, "gt"),
+                        ),
+                        Value::Float(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::OGT,
+                            self.builder.build_signed_int_to_float(l, f64_type, "left"),
+                            r,
+                            "gt",
+                        )),
+                        _ => invalid(),
+                    },
+                    Value::Float(l) => match r_value {
+                        Value::Int(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::OGT,
+                            l,
+                            self.builder.build_signed_int_to_float(r, f64_type, "right"),
+                            "gt",
+                        )),
+                        Value::Float(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::OGT,
+                            l,
+                            r,
+                            "gt",
+                        )),
+                        _ => invalid(),
+                    },
+                    _ => invalid(),
+                }
+            }
+            Gte => {
+                match l_value {
+                    Value::Int(l) | Value::Char(l) => match r_value {
+                        Value::Int(r) | Value::Char(r) => Value::Bool(
+                            self.builder
+                                .build_int_compare(IntPredicate::SGE, l, r, "gte"),
+                        ),
+                        Value::Float(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::OGE,
+                            self.builder.build_signed_int_to_float(l, f64_type, "left"),
+                            r,
+                            "gte",
+                        )),
+                        _ => invalid(),
+                    },
+                    Value::Float(l) => match r_value {
+                        Value::Int(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::OGE,
+                            l,
+                            self.builder.build_signed_int_to_float(r, f64_type, "right"),
+                            "gte",
+                        )),
+                        Value::Float(r) => Value::Bool(self.builder.build_float_compare(
+                            FloatPredicate::OGE,
+                            l,
+                            r,
+                            "gte",
+                        )),
+                        _ => invalid(),
+                    },
+                    _ => invalid(),
+                }
+            }
+        })
+    }
+
+    // Generates a branch of an `if` in its own scope. With `keep_list`, a list
+    // the branch ends with outlives the scope, for the caller to take over
+    fn branch(&mut self, body: Node, keep_list: bool) -> Result<Value<'ctx>, Diagnostic> {
+        self.scope.push();
+        let mut result = self.visit(body);
+        if let (true, Ok(Value::List(header, item))) = (keep_list, &result) {
+            let (mut header, item) = (*header, item.clone());
+            // A list from an outer scope is copied, since it already has an owner
+            if !self.scope.lists.contains(&header) {
+                let copy = self.new_list(&item, self.list_data(header), self.list_len(header));
+                header = copy.get_value().into_pointer_value();
+                result = Ok(copy);
+            }
+            self.scope.lists.retain(|list| *list != header);
+        }
+        self.pop_scope();
+        result
+    }
+
+    fn visit(&mut self, node: Node) -> Result<Value<'ctx>, Diagnostic> {
+        let span = node.span;
+        let ty = node.ty.clone();
+        Ok(match node.kind {
+            NodeKind::Int(value) => Value::Int(self.int_type.const_int(value as u64, true)),
+            NodeKind::Float(value) => Value::Float(self.float_type.const_float(value)),
+            NodeKind::Bool(value) => {
                 Value::Bool(self.bool_type.const_int(if value { 1 } else { 0 }, false))
             }
-            Node::Str(value) => Value::Str({
+            NodeKind::Str(value) => Value::Str({
                 let string = self.context.const_string(value.as_bytes(), true);
-                let ptr = self.builder.build_alloca(string.get_type(), "str");
+                let ptr = self.literal_slot(string.get_type().into(), "str");
                 self.builder.build_store(ptr, string);
-                ptr
+                self.builder.build_pointer_cast(ptr, self.str_type, "str")
             }),
-            Node::Char(value) => Value::Char(self.char_type.const_int(value as u64, false)),
-            Node::Array(nodes) => {
+            NodeKind::Char(value) => Value::Char(self.char_type.const_int(value as u64, false)),
+            NodeKind::Array(nodes) => {
                 let size = nodes.len() as u32;
-                let mut ty = TypeLiteral::Int;
+                // Empty arrays get their item type from the type checker
+                let mut ty = match node.ty {
+                    Type::Array(ty, _) => *ty,
+                    _ => Type::Int,
+                };
 
                 let mut values: Vec<BasicValueEnum<'ctx>> = vec![];
                 for node in nodes {
-                    let value = self.visit(node);
-                    ty = match value {
-                        Value::Int(_) => TypeLiteral::Int,
-                        Value::Float(_) => TypeLiteral::Float,
-                        Value::Bool(_) => TypeLiteral::Bool,
-                        Value::Str(_) => TypeLiteral::Str,
-                        Value::Char(_) => TypeLiteral::Char,
-                        _ => panic!("invalid array type"),
-                    };
+                    let value = self.visit_value(node)?;
+                    ty = value.ty();
                     values.push(value.get_value());
                 }
 
-                let array = match ty {
-                    TypeLiteral::Int => self.int_type.const_array(
-                        values
-                            .iter()
-                            .map(|value| value.into_int_value())
-                            .collect::<Vec<IntValue<'ctx>>>()
-                            .as_slice(),
-                    ),
-                    TypeLiteral::Float => self.float_type.const_array(
-                        values
-                            .iter()
-                            .map(|value| value.into_float_value())
-                            .collect::<Vec<FloatValue<'ctx>>>()
-                            .as_slice(),
-                    ),
-                    TypeLiteral::Bool => self.bool_type.const_array(
-                        values
-                            .iter()
-                            .map(|value| value.into_int_value())
-                            .collect::<Vec<IntValue<'ctx>>>()
-                            .as_slice(),
-                    ),
-                    TypeLiteral::Str => self.str_type.const_array(
-                        values
-                            .iter()
-                            .map(|value| value.into_pointer_value())
-                            .collect::<Vec<PointerValue<'ctx>>>()
-                            .as_slice(),
-                    ),
-                    TypeLiteral::Char => self.char_type.const_array(
-                        values
-                            .iter()
-                            .map(|value| value.into_int_value())
-                            .collect::<Vec<IntValue<'ctx>>>()
-                            .as_slice(),
-                    ),
-                    TypeLiteral::Void => unreachable!(),
-                };
-
-                let size_value = self.int_type.const_int(size as u64, false);
-                let ptr = match ty {
-                    TypeLiteral::Int => {
-                        self.builder
-                            .build_array_alloca(self.int_type, size_value, "array")
-                    }
-                    TypeLiteral::Float => {
-                        self.builder
-                            .build_array_alloca(self.float_type, size_value, "array")
-                    }
-                    TypeLiteral::Bool => {
-                        self.builder
-                            .build_array_alloca(self.bool_type, size_value, "array")
-                    }
-                    TypeLiteral::Str => {
-                        self.builder
-                            .build_array_alloca(self.str_type, size_value, "array")
-                    }
-                    TypeLiteral::Char => {
-                        self.builder
-                            .build_array_alloca(self.char_type, size_value, "array")
-                    }
-                    TypeLiteral::Void => unreachable!(),
-                };
-                self.builder.build_store(ptr, array);
+                let item_type = ty.get_type(self.context);
+                let ptr = self.literal_slot(item_type.array_type(size).into(), "array");
+                let ptr = self.builder.build_pointer_cast(
+                    ptr,
+                    item_type.ptr_type(AddressSpace::Generic),
+                    "array",
+                );
+                for (i, value) in values.into_iter().enumerate() {
+                    let index = self.int_type.const_int(i as u64, false);
+                    let item_ptr = unsafe { self.builder.build_gep(ptr, &[index], "item") };
+                    self.builder.build_store(item_ptr, value);
+                }
 
                 Value::Array(ptr, ty, size)
             }
-            Node::Cast(ty, node) => {
-                let value = self.visit(*node);
+            NodeKind::Cast(ty, node) => {
+                let value = self.visit(*node)?;
+                let invalid = || -> ! {
+                    unreachable!("the type checker rejects casting {} to {}", value.ty(), ty)
+                };
 
-                match ty {
+                match &ty {
                     Type::Int => Value::Int(match value {
                         Value::Int(value) | Value::Bool(value) => value,
                         Value::Float(value) => {
                             self.builder
                                 .build_float_to_signed_int(value, self.int_type, "int")
                         }
-                        _ => unimplemented!(),
+                        _ => invalid(),
                     }),
                     Type::Float => Value::Float(match value {
                         Value::Int(value) => {
@@ -209,7 +1097,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                             self.float_type,
                             "float",
                         ),
-                        _ => unimplemented!(),
+                        _ => invalid(),
                     }),
                     Type::Bool => Value::Bool(match value {
                         Value::Int(value) | Value::Bool(value) => value,
@@ -217,31 +1105,50 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                             self.builder
                                 .build_float_to_unsigned_int(value, self.bool_type, "bool")
                         }
-                        _ => unimplemented!(),
+                        _ => invalid(),
                     }),
                     Type::Str => Value::Str(match value {
                         Value::Str(value) => value,
-                        _ => unimplemented!(),
+                        _ => invalid(),
                     }),
                     Type::Char => Value::Char(match value {
                         Value::Char(value) => value,
-                        _ => unimplemented!(),
+                        _ => invalid(),
                     }),
-                    Type::Array(_, _) => panic!("can't cast to an array"),
-                    Type::Void => panic!("can't cast to a void type"),
+                    Type::List(item) => match value {
+                        Value::Array(ptr, _, size) => {
+                            let len = self.int_type.const_int(size as u64, false);
+                            self.new_list(item, ptr, len)
+                        }
+                        Value::List(header, _) => {
+                            let len = self.list_len(header);
+                            let items = self.list_data(header);
+                            self.new_list(item, items, len)
+                        }
+                        _ => invalid(),
+                    },
+                    Type::Array(_, _) | Type::Struct(_) | Type::Void => invalid(),
                 }
             }
-            Node::Identifier(name) => self.scope.get(&name, &self.builder),
-            Node::Unary(op, node) => {
-                let value = self.visit(*node);
+            NodeKind::Identifier(name) => self
+                .scope
+                .get(&name, &self.builder)
+                .expect("variables are checked by the type checker"),
+            NodeKind::Unary(op, node) => {
+                let value = self.visit(*node)?;
+                let invalid = || -> ! {
+                    unreachable!("the type checker rejects unary {} on {}", op, value.ty())
+                };
 
                 use UnaryOp::*;
                 match op {
                     Pos => value,
                     Neg => match value {
-                        Value::Int(value) => Value::Int(value.const_neg()),
-                        Value::Float(value) => Value::Float(value.const_neg()),
-                        _ => unimplemented!(),
+                        Value::Int(value) => Value::Int(self.int_neg(value, span)),
+                        Value::Float(value) => {
+                            Value::Float(self.builder.build_float_neg(value, "neg"))
+                        }
+                        _ => invalid(),
                     },
                     Not => match value {
                         Value::Int(value) => Value::Bool(self.builder.build_int_compare(
@@ -262,475 +1169,159 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                             self.bool_type.const_zero(),
                             "not",
                         )),
-                        _ => unimplemented!(),
+                        _ => invalid(),
+                    },
+                    BitNot => match value {
+                        Value::Int(value) => Value::Int(self.builder.build_not(value, "bit_not")),
+                        Value::Char(value) => Value::Char(self.builder.build_not(value, "bit_not")),
+                        _ => invalid(),
                     },
                 }
             }
-            Node::Binary(left, op, right) => {
-                let l_value = self.visit(*left);
-                let r_value = self.visit(*right);
-
-                let f64_type = self.float_type;
-
-                use BinaryOp::*;
+            NodeKind::Binary(left, op @ (BinaryOp::And | BinaryOp::Or), right) => {
+                self.short_circuit(op, *left, *right)?
+            }
+            NodeKind::Binary(left, op @ (BinaryOp::In | BinaryOp::NotIn), right)
+                if matches!(right.kind, NodeKind::Range(..)) =>
+            {
+                let value = self.visit_value(*left)?;
+                let found = match right.kind {
+                    NodeKind::Range(start, end) => self.in_range(value, *start, *end)?,
+                    _ => unreachable!(),
+                };
                 match op {
-                    Add => match l_value {
-                        Value::Int(l) => match r_value {
-                            Value::Int(r) => Value::Int(self.builder.build_int_add(l, r, "add")),
-                            Value::Float(r) => Value::Float(self.builder.build_float_add(
-                                self.builder.build_signed_int_to_float(l, f64_type, "left"),
-                                r,
-                                "add",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        Value::Float(l) => match r_value {
-                            Value::Int(r) => Value::Float(self.builder.build_float_add(
-                                l,
-                                self.builder.build_signed_int_to_float(r, f64_type, "right"),
-                                "add",
-                            )),
-                            Value::Float(r) => {
-                                Value::Float(self.builder.build_float_add(l, r, "add"))
-                            }
-                            _ => unimplemented!(),
-                        },
-                        _ => unimplemented!(),
-                    },
-                    Sub => match l_value {
-                        Value::Int(l) => match r_value {
-                            Value::Int(r) => Value::Int(self.builder.build_int_sub(l, r, "sub")),
-                            Value::Float(r) => Value::Float(self.builder.build_float_sub(
-                                self.builder.build_signed_int_to_float(l, f64_type, "left"),
-                                r,
-                                "sub",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        Value::Float(l) => match r_value {
-                            Value::Int(r) => Value::Float(self.builder.build_float_sub(
-                                l,
-                                self.builder.build_signed_int_to_float(r, f64_type, "right"),
-                                "sub",
-                            )),
-                            Value::Float(r) => {
-                                Value::Float(self.builder.build_float_sub(l, r, "sub"))
-                            }
-                            _ => unimplemented!(),
-                        },
-                        _ => unimplemented!(),
-                    },
-                    Mul => match l_value {
-                        Value::Int(l) => match r_value {
-                            Value::Int(r) => Value::Int(self.builder.build_int_mul(l, r, "mul")),
-                            Value::Float(r) => Value::Float(self.builder.build_float_mul(
-                                self.builder.build_signed_int_to_float(l, f64_type, "left"),
-                                r,
-                                "mul",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        Value::Float(l) => match r_value {
-                            Value::Int(r) => Value::Float(self.builder.build_float_mul(
-                                l,
-                                self.builder.build_signed_int_to_float(r, f64_type, "right"),
-                                "mul",
-                            )),
-                            Value::Float(r) => {
-                                Value::Float(self.builder.build_float_mul(l, r, "mul"))
-                            }
-                            _ => unimplemented!(),
-                        },
-                        _ => unimplemented!(),
-                    },
-                    Div => match l_value {
-                        Value::Int(l) => match r_value {
-                            Value::Int(r) => {
-                                Value::Int(self.builder.build_int_unsigned_div(l, r, "div"))
-                            }
-                            Value::Float(r) => Value::Float(self.builder.build_float_div(
-                                self.builder.build_signed_int_to_float(l, f64_type, "left"),
-                                r,
-                                "div",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        Value::Float(l) => match r_value {
-                            Value::Int(r) => Value::Float(self.builder.build_float_div(
-                                l,
-                                self.builder.build_signed_int_to_float(r, f64_type, "right"),
-                                "div",
-                            )),
-                            Value::Float(r) => {
-                                Value::Float(self.builder.build_float_div(l, r, "div"))
-                            }
-                            _ => unimplemented!(),
-                        },
-                        _ => unimplemented!(),
-                    },
-                    Rem => match l_value {
-                        Value::Int(l) => match r_value {
-                            Value::Int(r) => {
-                                Value::Int(self.builder.build_int_unsigned_rem(l, r, "rem"))
-                            }
-                            Value::Float(r) => Value::Float(self.builder.build_float_rem(
-                                self.builder.build_signed_int_to_float(l, f64_type, "left"),
-                                r,
-                                "rem",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        Value::Float(l) => match r_value {
-                            Value::Int(r) => Value::Float(self.builder.build_float_rem(
-                                l,
-                                self.builder.build_signed_int_to_float(r, f64_type, "right"),
-                                "rem",
-                            )),
-                            Value::Float(r) => {
-                                Value::Float(self.builder.build_float_rem(l, r, "rem"))
-                            }
-                            _ => unimplemented!(),
-                        },
-                        _ => unimplemented!(),
-                    },
-                    And => match l_value {
-                        Value::Bool(l) => match r_value {
-                            Value::Bool(r) => Value::Bool(self.builder.build_and(l, r, "and")),
-                            _ => unimplemented!(),
-                        },
-                        _ => unimplemented!(),
-                    },
-                    Or => match l_value {
-                        Value::Bool(l) => match r_value {
-                            Value::Bool(r) => Value::Bool(self.builder.build_or(l, r, "or")),
-                            _ => unimplemented!(),
-                        },
-                        _ => unimplemented!(),
-                    },
-                    EqEq => match l_value {
-                        Value::Int(l) | Value::Char(l) => match r_value {
-                            Value::Int(r) | Value::Char(r) => Value::Bool(
-                                self.builder
-                                    .build_int_compare(IntPredicate::EQ, l, r, "eqeq"),
-                            ),
-                            Value::Float(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::OEQ,
-                                self.builder.build_signed_int_to_float(l, f64_type, "left"),
-                                r,
-                                "eqeq",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        Value::Float(l) => match r_value {
-                            Value::Int(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::OEQ,
-                                l,
-                                self.builder.build_signed_int_to_float(r, f64_type, "right"),
-                                "eqeq",
-                            )),
-                            Value::Float(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::OEQ,
-                                l,
-                                r,
-                                "eqeq",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        Value::Bool(l) => match r_value {
-                            Value::Bool(r) => Value::Bool(
-                                self.builder
-                                    .build_not(self.builder.build_xor(l, r, "xor"), "not"),
-                            ),
-                            _ => unimplemented!(),
-                        },
-                        _ => unimplemented!(),
-                    },
-                    Neq => match l_value {
-                        Value::Int(l) | Value::Char(l) => match r_value {
-                            Value::Int(r) | Value::Char(r) => Value::Bool(
-                                self.builder
-                                    .build_int_compare(IntPredicate::NE, l, r, "neq"),
-                            ),
-                            Value::Float(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::ONE,
-                                self.builder.build_signed_int_to_float(l, f64_type, "left"),
-                                r,
-                                "neq",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        Value::Float(l) => match r_value {
-                            Value::Int(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::ONE,
-                                l,
-                                self.builder.build_signed_int_to_float(r, f64_type, "right"),
-                                "neq",
-                            )),
-                            Value::Float(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::ONE,
-                                l,
-                                r,
-                                "neq",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        Value::Bool(l) => match r_value {
-                            Value::Bool(r) => Value::Bool(self.builder.build_xor(l, r, "xor")),
-                            _ => unimplemented!(),
-                        },
-                        _ => unimplemented!(),
-                    },
-                    Lt => match l_value {
-                        Value::Int(l) | Value::Char(l) => match r_value {
-                            Value::Int(r) | Value::Char(r) => Value::Bool(
-                                self.builder
-                                    .build_int_compare(IntPredicate::SLT, l, r, "lt"),
-                            ),
-                            Value::Float(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::OLT,
-                                self.builder.build_signed_int_to_float(l, f64_type, "left"),
-                                r,
-                                "lt",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        Value::Float(l) => match r_value {
-                            Value::Int(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::OLT,
-                                l,
-                                self.builder.build_signed_int_to_float(r, f64_type, "right"),
-                                "lt",
-                            )),
-                            Value::Float(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::OLT,
-                                l,
-                                r,
-                                "lt",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        _ => unimplemented!(),
-                    },
-                    Lte => match l_value {
-                        Value::Int(l) | Value::Char(l) => match r_value {
-                            Value::Int(r) | Value::Char(r) => Value::Bool(
-                                self.builder
-                                    .build_int_compare(IntPredicate::SLE, l, r, "lte"),
-                            ),
-                            Value::Float(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::OLE,
-                                self.builder.build_signed_int_to_float(l, f64_type, "left"),
-                                r,
-                                "lte",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        Value::Float(l) => match r_value {
-                            Value::Int(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::OLE,
-                                l,
-                                self.builder.build_signed_int_to_float(r, f64_type, "right"),
-                                "lte",
-                            )),
-                            Value::Float(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::OLE,
-                                l,
-                                r,
-                                "lte",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        _ => unimplemented!(),
-                    },
-                    Gt => match l_value {
-                        Value::Int(l) | Value::Char(l) => match r_value {
-                            Value::Int(r) | Value::Char(r) => Value::Bool(
-                                self.builder
-                                    .build_int_compare(IntPredicate::SGT, l, r, "gt"),
-                            ),
-                            Value::Float(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::OGT,
-                                self.builder.build_signed_int_to_float(l, f64_type, "left"),
-                                r,
-                                "gt",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        Value::Float(l) => match r_value {
-                            Value::Int(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::OGT,
-                                l,
-                                self.builder.build_signed_int_to_float(r, f64_type, "right"),
-                                "gt",
-                            )),
-                            Value::Float(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::OGT,
-                                l,
-                                r,
-                                "gt",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        _ => unimplemented!(),
-                    },
-                    Gte => match l_value {
-                        Value::Int(l) | Value::Char(l) => match r_value {
-                            Value::Int(r) | Value::Char(r) => Value::Bool(
-                                self.builder
-                                    .build_int_compare(IntPredicate::SGE, l, r, "gte"),
-                            ),
-                            Value::Float(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::OGE,
-                                self.builder.build_signed_int_to_float(l, f64_type, "left"),
-                                r,
-                                "gte",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        Value::Float(l) => match r_value {
-                            Value::Int(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::OGE,
-                                l,
-                                self.builder.build_signed_int_to_float(r, f64_type, "right"),
-                                "gte",
-                            )),
-                            Value::Float(r) => Value::Bool(self.builder.build_float_compare(
-                                FloatPredicate::OGE,
-                                l,
-                                r,
-                                "gte",
-                            )),
-                            _ => unimplemented!(),
-                        },
-                        _ => unimplemented!(),
-                    },
+                    BinaryOp::In => Value::Bool(found),
+                    _ => Value::Bool(self.builder.build_not(found, "not_in")),
                 }
             }
-            Node::Let(name, node) => {
-                let value = self.visit(*node);
-                self.scope.set(name, value, &self.context, &self.builder)
-            }
-            Node::IdentifierOp(name, op, node) => {
-                let ptr = match *name.clone() {
-                    Node::Identifier(name) => self.scope.get_ptr(&name, &self.builder),
-                    Node::Index(name, index) => match *name {
-                        Node::Identifier(name) => {
-                            let list_ptr = self.scope.get_ptr(&name, &self.builder);
-                            let index = self.visit(*index);
-                            unsafe {
-                                self.builder.build_gep(
-                                    list_ptr,
-                                    &[match index {
-                                        Value::Int(value) => value,
-                                        _ => unimplemented!(),
-                                    }],
-                                    "index",
-                                )
-                            }
-                        }
-                        _ => unimplemented!(),
-                    },
-                    _ => unimplemented!(),
-                };
-                let value = self.visit(*node.clone());
-
+            NodeKind::Binary(left, op, right) => {
+                let l_value = self.visit(*left)?;
+                let r_value = self.visit(*right)?;
+                self.binary(op, l_value, r_value, span)?
+            }
+            NodeKind::Compare(nodes, ops) => Value::Bool(self.compare(nodes, ops, span)?),
+            NodeKind::Let(name, _, node) => {
+                let value = self.visit_value(*node)?;
+                let entry_builder = self.entry_builder();
+                self.scope
+                    .set(name, value, self.context, &self.builder, &entry_builder)
+            }
+            NodeKind::IdentifierOp(name, op, node) => {
                 use IdentifierOp::*;
 
-                macro_rules! identifier_op {
+                macro_rules! binary_op {
                     ($($op:tt),*) => {
                         match op {
-                            Eq=>{
-                                self.builder.build_store(ptr,value.get_value());
-                                value
-                            },
-                            $(
-                                $op => self.visit(Node::IdentifierOp(
-                                    name.clone(),
-                                    $op,
-                                    Box::new(Node::Binary(
-                                        name,
-                                        BinaryOp::$op,
-                                        node,
-                                    )),
-                                )),
-                            )*
+                            Eq => None,
+                            $($op => Some(BinaryOp::$op),)*
                         }
                     };
                 }
 
-                identifier_op!(Add, Sub, Mul, Div, Rem)
-            }
-            Node::Index(node, index) => {
-                let value = self.visit(*node.clone());
-                let index = self.visit(*index);
-                match value {
-                    Value::Str(ptr) => {
-                        let index_ptr = unsafe {
-                            self.builder.build_gep(
-                                ptr,
-                                &[match index {
-                                    Value::Int(value) => value,
-                                    _ => unimplemented!(),
-                                }],
-                                "index",
-                            )
-                        };
-                        let item_value = self.builder.build_load(index_ptr, "index");
-                        Value::Char(item_value.into_int_value())
-                    }
-                    Value::Array(ptr, ty, _) => {
-                        let index_ptr = unsafe {
-                            self.builder.build_gep(
-                                ptr,
-                                &[match index {
-                                    Value::Int(value) => value,
-                                    _ => unimplemented!(),
-                                }],
-                                "index",
-                            )
-                        };
-                        let item_value = self.builder.build_load(index_ptr, "index");
+                match binary_op!(Add, Sub, Mul, Div, Rem, Pow, BitAnd, BitOr, Shl, Shr) {
+                    None => {
+                        let (ptr, ty) = self.assignment_ptr(*name)?;
+                        let value = self.visit_value(*node)?;
                         match ty {
-                            TypeLiteral::Int => Value::Int(item_value.into_int_value()),
-                            TypeLiteral::Float => Value::Float(item_value.into_float_value()),
-                            TypeLiteral::Bool => Value::Bool(item_value.into_int_value()),
-                            TypeLiteral::Str => Value::Str(item_value.into_pointer_value()),
-                            TypeLiteral::Char => Value::Char(item_value.into_int_value()),
-                            TypeLiteral::Void => panic!("can't have a void array"),
+                            // Lists own their items, so assigning copies them
+                            Type::List(item) => {
+                                if let Value::List(source, _) = value {
+                                    self.assign_list(ptr, source, &item);
+                                }
+                                value
+                            }
+                            _ => {
+                                self.builder.build_store(ptr, value.get_value());
+                                value
+                            }
                         }
                     }
-                    _ => panic!("cannot index {}", node),
+                    // The target is only evaluated once, so `xs[i()] += 1` calls `i` once
+                    Some(op) => {
+                        let (ptr, ty) = self.assignment_ptr(*name)?;
+                        let l_value = Value::new(self.builder.build_load(ptr, "value"), &ty);
+                        let r_value = self.visit_value(*node)?;
+                        let value = self.binary(op, l_value, r_value, span)?;
+                        self.builder.build_store(ptr, value.get_value());
+                        value
+                    }
                 }
             }
-            Node::While(condition, body) => {
-                let condition_block = self.context.append_basic_block(self.function, "while_cond");
-                self.builder.build_unconditional_branch(condition_block);
-                self.builder.position_at_end(condition_block);
-                let condition_value = match self.visit(*condition) {
-                    Value::Bool(value) => value,
-                    _ => panic!("while loops can only have a bool as their condition"),
+            NodeKind::Index(node, index) => {
+                let value = self.visit(*node)?;
+                if let NodeKind::Range(start, end) = index.kind {
+                    let start = self.int_value(*start)?;
+                    let end = self.int_value(*end)?;
+                    return Ok(match value {
+                        Value::Array(ptr, item, size) => {
+                            let len = self.int_type.const_int(size as u64, false);
+                            self.slice_list(ptr, &item, (start, end), len, span)
+                        }
+                        Value::List(header, item) => {
+                            let items = self.list_data(header);
+                            let len = self.list_len(header);
+                            self.slice_list(items, &item, (start, end), len, span)
+                        }
+                        _ => unreachable!("only arrays and lists can be sliced"),
+                    });
+                }
+                let index = self.int_value(*index)?;
+                let (ptr, item_ty) = match &value {
+                    Value::Str(ptr) => (*ptr, Type::Char),
+                    Value::Array(ptr, ty, _) | Value::List(ptr, ty) => (*ptr, ty.clone()),
+                    _ => unreachable!("only strings, arrays and lists can be indexed"),
                 };
-
-                let loop_block = self.context.append_basic_block(self.function, "while_loop");
-                self.builder.position_at_end(loop_block);
-                self.visit(*body);
-
+                let index_ptr = self.item_ptr(ptr, &value.ty(), index, span);
+                Value::new(self.builder.build_load(index_ptr, "index"), &item_ty)
+            }
+            NodeKind::While(label, condition, body, else_case) => {
+                let condition_block = self.context.append_basic_block(self.function, "while_cond");
+                let body_block = self.context.append_basic_block(self.function, "while_body");
+                let else_block = self.context.append_basic_block(self.function, "while_else");
                 let end_block = self.context.append_basic_block(self.function, "while_end");
                 self.builder.build_unconditional_branch(condition_block);
 
+                // The condition can span several blocks, e.g. with `and`, so the
+                // branch goes wherever it ends
                 self.builder.position_at_end(condition_block);
+                let condition_value = self.condition(*condition)?;
                 self.builder
-                    .build_conditional_branch(condition_value, loop_block, end_block);
+                    .build_conditional_branch(condition_value, body_block, else_block);
 
-                self.builder.position_at_end(end_block);
+                self.builder.position_at_end(body_block);
+                self.push_loop(label, condition_block, end_block);
+                self.scope.push();
+                let result = self.visit(*body);
+                self.pop_scope();
+                let mut l = self.loops.pop().unwrap();
+                result?;
+                self.builder.build_unconditional_branch(condition_block);
 
-                Value::Int(self.int_type.const_zero())
+                // The else leaves the loop the way a `break` with its value would
+                self.builder.position_at_end(else_block);
+                if let Some(else_case) = else_case {
+                    self.scope.push();
+                    let value = match self.visit(*else_case)? {
+                        _ if ty == Type::Void => None,
+                        // Copied, since the list's own scope is about to end
+                        Value::List(header, item) => Some(self.list_value(header, &item).into()),
+                        value => Some(value.get_value()),
+                    };
+                    self.pop_scope();
+                    if let Some(value) = value {
+                        l.ty = Some(ty.clone());
+                        let block = self.builder.get_insert_block().unwrap();
+                        l.breaks.push((value, block));
+                    }
+                }
+                self.builder.build_unconditional_branch(end_block);
+
+                self.builder.position_at_end(end_block);
+                self.loop_value(l)
             }
-            Node::If(condition, body, else_case) => {
-                let condition_value = match self.visit(*condition) {
-                    Value::Bool(value) => value,
-                    _ => panic!("if statements can only have a bool as their condition"),
-                };
+            NodeKind::If(condition, body, else_case) => {
+                let keep_list = matches!(ty, Type::List(_));
+                let condition_value = self.condition(*condition)?;
 
                 let then_block = self.context.append_basic_block(self.function, "then");
                 match else_case {
@@ -746,20 +1337,25 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
 
                         // Then
                         self.builder.position_at_end(then_block);
-                        let then_value = self.visit(*body);
+                        let then_value = self.branch(*body, keep_list)?;
                         self.builder.build_unconditional_branch(end_block);
 
                         let then_block = self.builder.get_insert_block().unwrap();
 
                         // Else
                         self.builder.position_at_end(else_block);
-                        let else_value = self.visit(*else_case);
+                        let else_value = self.branch(*else_case, keep_list)?;
                         self.builder.build_unconditional_branch(end_block);
 
                         let else_block = self.builder.get_insert_block().unwrap();
 
                         self.builder.position_at_end(end_block);
 
+                        // Branches of different types are only valid as a statement
+                        if matches!(then_value, Value::Void) || then_value.ty() != else_value.ty() {
+                            return Ok(Value::Int(self.int_type.const_zero()));
+                        }
+
                         let phi = self
                             .builder
                             .build_phi(then_value.get_type(self.context), "phi");
@@ -778,7 +1374,16 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                             Value::Array(_, ty, size) => {
                                 Value::Array(phi_value.into_pointer_value(), ty, size)
                             }
-                            Value::Void => panic!("void isn't a valid type"),
+                            Value::List(_, ty) => {
+                                // Owned by the scope around the if from here on
+                                let header = phi_value.into_pointer_value();
+                                self.scope.lists.push(header);
+                                Value::List(header, ty)
+                            }
+                            Value::Struct(_, name) => {
+                                Value::Struct(phi_value.into_struct_value(), name)
+                            }
+                            Value::Void => unreachable!(),
                         }
                     }
                     None => {
@@ -792,7 +1397,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
 
                         // Then
                         self.builder.position_at_end(then_block);
-                        self.visit(*body);
+                        self.branch(*body, false)?;
                         self.builder.build_unconditional_branch(end_block);
 
                         self.builder.position_at_end(end_block);
@@ -801,124 +1406,298 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                     }
                 }
             }
-            Node::Fn(name, args, return_type, body) => {
-                let arg_types = args.iter().map(|(_, ty)| ty.clone()).collect::<Vec<Type>>();
+            NodeKind::Fn(name, args, return_type, body) => {
+                let return_type = return_type.unwrap_or(Type::Void);
 
-                let function = Function::new_user(&name, arg_types, return_type.clone(), self);
+                // Registered before the body is generated so it can call itself
+                let function = match self.scope.get_local_function(&name) {
+                    Some(function) if function.value.count_basic_blocks() == 0 => function.clone(),
+                    _ => {
+                        self.declare_function(&name, &args, &return_type);
+                        self.scope.get_local_function(&name).unwrap().clone()
+                    }
+                };
                 let block = self.context.append_basic_block(function.value, "body");
 
-                let mut codegen = self.create_child(self.function);
-                codegen.builder.position_at_end(block);
-                args.iter().enumerate().for_each(|(i, (arg_name, ty))| {
-                    let arg_name = arg_name.clone();
-                    let value = function.value.get_nth_param(i as u32).unwrap();
-                    match ty {
-                        Type::Int => {
-                            let val_ptr = codegen.builder.build_alloca(self.int_type, &arg_name);
-                            codegen
-                                .scope
-                                .variables
-                                .insert(arg_name, (val_ptr, Type::Int));
-                            codegen.builder.build_store(val_ptr, value.into_int_value());
-                        }
-                        Type::Float => {
-                            let val_ptr = codegen.builder.build_alloca(self.float_type, &arg_name);
-                            codegen
-                                .scope
-                                .variables
-                                .insert(arg_name, (val_ptr, Type::Float));
-                            codegen
-                                .builder
-                                .build_store(val_ptr, value.into_float_value());
-                        }
-                        Type::Bool => {
-                            let val_ptr = codegen.builder.build_alloca(self.bool_type, &arg_name);
-                            codegen
-                                .scope
-                                .variables
-                                .insert(arg_name, (val_ptr, Type::Bool));
-                            codegen.builder.build_store(val_ptr, value.into_int_value());
-                        }
-                        Type::Str => {
-                            codegen
-                                .scope
-                                .variables
-                                .insert(arg_name, (value.into_pointer_value(), Type::Str));
-                        }
-                        Type::Char => {
-                            let val_ptr = codegen.builder.build_alloca(self.char_type, &arg_name);
-                            codegen
-                                .scope
-                                .variables
-                                .insert(arg_name, (val_ptr, Type::Char));
-                            codegen.builder.build_store(val_ptr, value.into_int_value());
+                let parent_function = mem::replace(&mut self.function, function.value);
+                let parent_return_type = mem::replace(&mut self.return_type, return_type.clone());
+                let parent_block = self.builder.get_insert_block();
+                self.builder.position_at_end(block);
+                self.scope.push();
+                self.scope.function = true;
+
+                // Skips the pointer an array or struct is returned through
+                let first_arg = return_type.sret_type(self.context).is_some() as u32;
+                for (i, (arg_name, ty)) in args.iter().enumerate() {
+                    let value = function.value.get_nth_param(first_arg + i as u32).unwrap();
+                    self.add_var(arg_name, Value::new(value, ty));
+                }
+
+                let result = self.visit(*body);
+                if result.is_ok() {
+                    match return_type {
+                        Type::Void => {
+                            self.free_lists(self.scope.function_lists());
+                            self.builder.build_return(None);
                         }
-                        Type::Array(arr_ty, size) => {
-                            codegen.scope.variables.insert(
-                                arg_name,
-                                (value.into_pointer_value(), Type::Array(*arr_ty, *size)),
-                            );
+                        // The type checker makes sure functions with a value
+                        // return before they get here
+                        _ => {
+                            self.builder.build_unreachable();
                         }
-                        Type::Void => panic!("void isn't a valid argument type"),
-                    };
-                });
+                    }
+                }
 
-                codegen.visit(*body);
-                if return_type == Type::Void {
-                    codegen.builder.build_return(None);
+                self.scope.pop();
+                self.function = parent_function;
+                self.return_type = parent_return_type;
+                if let Some(block) = parent_block {
+                    self.builder.position_at_end(block);
                 }
+                result?;
 
                 Value::Int(self.int_type.const_zero())
             }
-            Node::Return(node) => {
-                let value = self.visit(*node);
-                self.builder.build_return(Some(match &value {
-                    Value::Int(value) => value,
-                    Value::Float(value) => value,
-                    Value::Bool(value) => value,
-                    Value::Str(value) => value,
-                    Value::Char(value) => value,
-                    Value::Array(value, _, _) => value,
-                    Value::Void => panic!("void isn't a valid type"),
-                }));
+            NodeKind::Return(node) => {
+                let value = match self.visit_value(*node)? {
+                    // The caller gets its own copy, since this function's lists are freed
+                    Value::List(header, item) => self.list_value(header, &item).into(),
+                    value => value.get_value(),
+                };
+                self.free_lists(self.scope.function_lists());
+                self.build_return(value);
+                self.unreachable_block("after_return");
                 Value::Int(self.int_type.const_zero())
             }
-            Node::Call(name, args) => {
-                let mut arg_values = args
-                    .iter()
-                    .map(|arg| self.visit(arg.clone()))
-                    .collect::<Vec<Value<'ctx>>>();
+            NodeKind::Call(name, args) => {
+                let mut arg_values = vec![];
+                for arg in args {
+                    arg_values.push(self.visit_value(arg)?);
+                }
 
-                let function = self.scope.get_function(&name);
+                let function = match self.scope.get_function(&name) {
+                    Some(function) => function.clone(),
+                    // Builtins generated inline rather than called
+                    None if LIST_BUILTINS.contains(&name.as_str()) => {
+                        return Ok(self.list_builtin(&name, arg_values, span))
+                    }
+                    None if name == "wrapping_add" || name == "checked_add" => {
+                        return Ok(self.arithmetic_builtin(&name, arg_values, span))
+                    }
+                    None => unreachable!("functions are checked by the type checker"),
+                };
                 if name == "print" {
-                    arg_values.insert(
-                        0,
-                        Value::Str(
-                            self.generate_printf_format_string(&arg_values)
-                                .into_pointer_value(),
-                        ),
-                    );
+                    arg_values = self.print_args(arg_values);
                 }
 
-                let value = function.call(arg_values, &self.builder);
-                match function.return_type {
-                    Type::Int => Value::Int(value.into_int_value()),
-                    Type::Float => Value::Float(value.into_float_value()),
-                    Type::Bool => Value::Bool(value.into_int_value()),
-                    Type::Str => Value::Str(value.into_pointer_value()),
-                    Type::Char => Value::Char(value.into_int_value()),
-                    Type::Array(ty, size) => Value::Array(value.into_pointer_value(), ty, size),
-                    Type::Void => Value::Void,
+                let value = function.call(arg_values, self);
+                match (&function.return_type, value) {
+                    (Type::Void, _) | (_, None) => Value::Void,
+                    (Type::List(item), Some(value)) => {
+                        self.own_list(value.into_struct_value(), item)
+                    }
+                    (ty, Some(value)) => Value::new(value, ty),
                 }
             }
-            Node::Statements(nodes) => {
+            NodeKind::Statements(nodes) => {
+                self.declare_functions(&nodes);
                 let mut rtn_value = Value::Int(self.int_type.const_zero());
                 for node in nodes {
-                    rtn_value = self.visit(node);
+                    rtn_value = self.visit(node)?;
                 }
                 rtn_value
             }
-            _ => unimplemented!(),
-        }
+            NodeKind::Struct(..) => Value::Int(self.int_type.const_zero()),
+            NodeKind::Impl(..) | NodeKind::MethodCall(..) => {
+                unreachable!("the type checker turns methods into functions")
+            }
+            NodeKind::StructLiteral(name, fields) => {
+                let struct_type = self.context.get_struct_type(&name).unwrap();
+                let mut value = struct_type.get_undef();
+                for (field, node) in fields {
+                    let (index, _) = self.field_index(&Type::Struct(name.clone()), &field);
+                    let field_value = self.visit_value(node)?;
+                    value = self
+                        .builder
+                        .build_insert_value(value, field_value.get_value(), index, &field)
+                        .unwrap()
+                        .into_struct_value();
+                }
+                Value::Struct(value, name)
+            }
+            NodeKind::Field(node, field) => {
+                let value = self.visit_value(*node)?;
+                match value {
+                    Value::Struct(struct_value, ref name) => {
+                        let (index, ty) = self.field_index(&Type::Struct(name.clone()), &field);
+                        let field_value = self
+                            .builder
+                            .build_extract_value(struct_value, index, &field)
+                            .unwrap();
+                        Value::new(field_value, &ty)
+                    }
+                    _ => unreachable!("fields are only accessed on structs"),
+                }
+            }
+            NodeKind::Error => Value::Int(self.int_type.const_zero()),
+            NodeKind::Type(_) => unreachable!("the type checker rejects types used as values"),
+            NodeKind::Loop(label, body) => {
+                let body_block = self.context.append_basic_block(self.function, "loop");
+                let end_block = self.context.append_basic_block(self.function, "loop_end");
+                self.builder.build_unconditional_branch(body_block);
+
+                self.builder.position_at_end(body_block);
+                self.push_loop(label, body_block, end_block);
+                self.scope.push();
+                let result = self.visit(*body);
+                self.pop_scope();
+                let l = self.loops.pop().unwrap();
+                result?;
+                self.builder.build_unconditional_branch(body_block);
+
+                self.builder.position_at_end(end_block);
+                self.loop_value(l)
+            }
+            NodeKind::Break(label, value) => {
+                let index = self.find_loop(&label);
+                if let Some(value) = value {
+                    let value = self.visit_value(*value)?;
+                    self.loops[index].ty = Some(value.ty());
+                    let value = match value {
+                        // Copied, since the list's own scope is about to end
+                        Value::List(header, item) => self.list_value(header, &item).into(),
+                        value => value.get_value(),
+                    };
+                    let block = self.builder.get_insert_block().unwrap();
+                    self.loops[index].breaks.push((value, block));
+                }
+                let Loop {
+                    end_block, depth, ..
+                } = self.loops[index];
+                self.jump_out(end_block, depth);
+                Value::Int(self.int_type.const_zero())
+            }
+            NodeKind::Continue(label) => {
+                let index = self.find_loop(&label);
+                let Loop {
+                    continue_block,
+                    depth,
+                    ..
+                } = self.loops[index];
+                self.jump_out(continue_block, depth);
+                Value::Int(self.int_type.const_zero())
+            }
+            NodeKind::For(label, identifier, iterable, body) => {
+                let iterable_span = iterable.span;
+                let zero = self.int_type.const_zero();
+
+                // Ranges stop at their end, arrays at their size, and strings at the nul byte.
+                // Lists can grow in the loop, so their length is loaded every iteration
+                let (start, end, items, list) = match iterable.kind {
+                    NodeKind::Range(start, end) => {
+                        let start = self.int_value(*start)?;
+                        let end = self.int_value(*end)?;
+                        (start, Some(end), None, None)
+                    }
+                    kind => match self.visit(Node::new(kind, iterable_span))? {
+                        Value::Array(ptr, ty, size) => (
+                            zero,
+                            Some(self.int_type.const_int(size as u64, false)),
+                            Some((ptr, ty)),
+                            None,
+                        ),
+                        Value::List(header, ty) => (zero, None, None, Some((header, ty))),
+                        Value::Str(ptr) => (zero, None, Some((ptr, Type::Char)), None),
+                        _ => unreachable!(
+                            "only arrays, lists, strings and ranges can be iterated over"
+                        ),
+                    },
+                };
+
+                let index_ptr = self
+                    .entry_builder()
+                    .build_alloca(self.int_type, "for_index");
+                self.builder.build_store(index_ptr, start);
+
+                let condition_block = self.context.append_basic_block(self.function, "for_cond");
+                let body_block = self.context.append_basic_block(self.function, "for_body");
+                let step_block = self.context.append_basic_block(self.function, "for_step");
+                let end_block = self.context.append_basic_block(self.function, "for_end");
+                self.builder.build_unconditional_branch(condition_block);
+
+                // Condition
+                self.builder.position_at_end(condition_block);
+                let index = self.builder.build_load(index_ptr, "index").into_int_value();
+                let (end, items) = match list {
+                    Some((header, ty)) => (
+                        Some(self.list_len(header)),
+                        Some((self.list_data(header), ty)),
+                    ),
+                    None => (end, items),
+                };
+                let (condition_value, item) = match (end, &items) {
+                    (Some(end), _) => (
+                        self.builder
+                            .build_int_compare(IntPredicate::SLT, index, end, "for_cond"),
+                        None,
+                    ),
+                    (None, Some((ptr, _))) => {
+                        let char_ptr = unsafe { self.builder.build_gep(*ptr, &[index], "char") };
+                        let char_value = self.builder.build_load(char_ptr, "char").into_int_value();
+                        let condition_value = self.builder.build_int_compare(
+                            IntPredicate::NE,
+                            char_value,
+                            self.char_type.const_zero(),
+                            "for_cond",
+                        );
+                        (condition_value, Some(Value::Char(char_value)))
+                    }
+                    (None, None) => unreachable!(),
+                };
+                self.builder
+                    .build_conditional_branch(condition_value, body_block, end_block);
+
+                // Body
+                self.builder.position_at_end(body_block);
+                let item = match (item, items) {
+                    (Some(item), _) => item,
+                    (None, Some((ptr, ty))) => {
+                        let item_ptr = unsafe { self.builder.build_gep(ptr, &[index], "item") };
+                        Value::new(self.builder.build_load(item_ptr, "item"), &ty)
+                    }
+                    (None, None) => Value::Int(index),
+                };
+                self.push_loop(label, step_block, end_block);
+                self.scope.push();
+                self.add_var(&identifier, item);
+                let result = self.visit(*body);
+                self.pop_scope();
+                self.loops.pop();
+                result?;
+                self.builder.build_unconditional_branch(step_block);
+
+                // Step
+                self.builder.position_at_end(step_block);
+                let next =
+                    self.builder
+                        .build_int_add(index, self.int_type.const_int(1, false), "next");
+                self.builder.build_store(index_ptr, next);
+                self.builder.build_unconditional_branch(condition_block);
+
+                self.builder.position_at_end(end_block);
+
+                Value::Int(zero)
+            }
+            NodeKind::Range(..) => {
+                unreachable!("ranges are only used to slice and in for loops and `in`")
+            }
+        })
+    }
+}
+
+fn op_name(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::And => "and",
+        _ => "or",
     }
 }
@@ -1,7 +1,7 @@
 use inkwell::{
-    builder::Builder,
+    attributes::{Attribute, AttributeLoc},
     module::Linkage,
-    types::BasicMetadataTypeEnum,
+    types::{AnyType, BasicType, FunctionType},
     values::{BasicMetadataValueEnum, BasicValueEnum, FunctionValue},
     AddressSpace,
 };
@@ -11,6 +11,7 @@ use crate::{
     Type,
 };
 
+#[derive(Clone)]
 pub struct Function<'ctx> {
     pub value: FunctionValue<'ctx>,
     pub return_type: Type,
@@ -52,43 +53,66 @@ impl<'ctx> Function<'ctx> {
         return_type: Type,
         linkage: Option<Linkage>,
         codegen: &mut Codegen<'a, 'ctx>,
+    ) -> Self {
+        let fn_type = return_type.fn_type(arg_types, var_args, codegen.context);
+        Function::declare(name, fn_type, return_type, linkage, codegen)
+    }
+
+    // Adds a function of a type made by `Type::fn_type` to the module
+    pub fn declare<'a>(
+        name: &str,
+        fn_type: FunctionType<'ctx>,
+        return_type: Type,
+        linkage: Option<Linkage>,
+        codegen: &mut Codegen<'a, 'ctx>,
     ) -> Self {
         let context = codegen.context;
-        let arg_types = arg_types
-            .iter()
-            .map(|ty| ty.get_type(context).into())
-            .collect::<Vec<BasicMetadataTypeEnum<'ctx>>>();
-        let fn_type = match return_type {
-            Type::Int => context.i32_type().fn_type(&arg_types, var_args),
-            Type::Float => context.f32_type().fn_type(&arg_types, var_args),
-            Type::Char => context.i8_type().fn_type(&arg_types, var_args),
-            Type::Str => context
-                .i8_type()
-                .ptr_type(AddressSpace::Generic)
-                .fn_type(&arg_types, var_args),
-            Type::Void => context.void_type().fn_type(&arg_types, var_args),
-            _ => unimplemented!(),
-        };
         let fn_value = codegen.module.add_function(name, fn_type, linkage);
+        if let Some(sret_type) = return_type.sret_type(context) {
+            let kind = Attribute::get_named_enum_kind_id("sret");
+            let sret = context.create_type_attribute(kind, sret_type.as_any_type_enum());
+            fn_value.add_attribute(AttributeLoc::Param(0), sret);
+        }
         Self {
             value: fn_value,
             return_type,
         }
     }
 
-    pub fn call(&self, values: Vec<Value<'ctx>>, builder: &Builder<'ctx>) -> BasicValueEnum<'ctx> {
-        builder
-            .build_call(
-                self.value,
-                values
-                    .iter()
-                    .map(|value| value.get_value().into())
-                    .collect::<Vec<BasicMetadataValueEnum>>()
-                    .as_slice(),
-                "call",
-            )
+    pub fn call<'a>(
+        &self,
+        values: Vec<Value<'ctx>>,
+        codegen: &Codegen<'a, 'ctx>,
+    ) -> Option<BasicValueEnum<'ctx>> {
+        // Arrays and structs are written to a slot in the caller's frame
+        let result = self.return_type.sret_type(codegen.context).map(|ty| {
+            let slot_type = match self.return_type {
+                Type::Array(_, size) => ty.array_type(size).into(),
+                _ => ty,
+            };
+            let slot = codegen.literal_slot(slot_type, "result");
+            codegen
+                .builder
+                .build_pointer_cast(slot, ty.ptr_type(AddressSpace::Generic), "result")
+        });
+
+        let mut args: Vec<BasicMetadataValueEnum<'ctx>> =
+            result.iter().map(|&ptr| ptr.into()).collect();
+        args.extend(
+            values
+                .iter()
+                .map(|value| BasicMetadataValueEnum::from(value.get_value())),
+        );
+        let value = codegen
+            .builder
+            .build_call(self.value, &args, "call")
             .try_as_basic_value()
-            .left()
-            .unwrap()
+            .left();
+
+        match (&self.return_type, result) {
+            (Type::Array(..), Some(ptr)) => Some(ptr.into()),
+            (_, Some(ptr)) => Some(codegen.builder.build_load(ptr, "result")),
+            (_, None) => value,
+        }
     }
 }
@@ -0,0 +1,111 @@
+use std::os::raw::{c_char, c_int, c_void};
+
+use inkwell::{
+    context::Context,
+    module::{Linkage, Module},
+    values::FunctionValue,
+    OptimizationLevel,
+};
+
+extern "C" {
+    fn printf(format: *const c_char, ...) -> c_int;
+    fn dprintf(fd: c_int, format: *const c_char, ...) -> c_int;
+    fn snprintf(buffer: *mut c_char, size: usize, format: *const c_char, ...) -> c_int;
+    fn exit(code: c_int) -> !;
+    fn setjmp(env: *mut c_void) -> c_int;
+    fn longjmp(env: *mut c_void, value: c_int) -> !;
+    fn malloc(size: usize) -> *mut c_void;
+    fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void;
+    fn free(ptr: *mut c_void);
+    fn strlen(string: *const c_char) -> usize;
+    fn fflush(stream: *mut c_void) -> c_int;
+
+    fn pow(x: f64, y: f64) -> f64;
+    fn sin(x: f64) -> f64;
+    fn cos(x: f64) -> f64;
+    fn tan(x: f64) -> f64;
+    fn round(x: f64) -> f64;
+    fn floor(x: f64) -> f64;
+    fn ceil(x: f64) -> f64;
+}
+
+// `llvm.powi` lowers to this compiler runtime call, which the host binary doesn't export
+extern "C" fn powi(x: f64, n: c_int) -> f64 {
+    x.powi(n)
+}
+
+// Compiles the module in memory and calls its `main`, returning the exit code
+pub fn run_jit<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    opt_level: OptimizationLevel,
+    args: &[String],
+) -> Result<i32, String> {
+    let symbols = host_symbols(context, module);
+    let engine = module
+        .create_jit_execution_engine(opt_level)
+        .map_err(|err| format!("couldn't create JIT: {}", err))?;
+    for (function, address) in symbols {
+        engine.add_global_mapping(&function, address);
+    }
+
+    let main = engine
+        .get_function_value("main")
+        .map_err(|err| format!("couldn't find main: {}", err))?;
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    Ok(unsafe { engine.run_function_as_main(main, &args) })
+}
+
+// Writes out what the program printed, which C buffers separately from Rust
+pub fn flush_output() {
+    unsafe {
+        fflush(std::ptr::null_mut());
+    }
+}
+
+// Points the module's external functions at the ones linked into this process
+pub fn host_symbols<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+) -> Vec<(FunctionValue<'ctx>, usize)> {
+    let libc = [
+        ("printf", printf as *const () as usize),
+        ("dprintf", dprintf as *const () as usize),
+        ("snprintf", snprintf as *const () as usize),
+        ("exit", exit as *const () as usize),
+        ("setjmp", setjmp as *const () as usize),
+        ("longjmp", longjmp as *const () as usize),
+        ("malloc", malloc as *const () as usize),
+        ("realloc", realloc as *const () as usize),
+        ("free", free as *const () as usize),
+        ("strlen", strlen as *const () as usize),
+    ];
+    let mut symbols: Vec<_> = libc
+        .into_iter()
+        .filter_map(|(name, address)| Some((module.get_function(name)?, address)))
+        .collect();
+
+    // Float intrinsics only become these calls during instruction selection, so they're
+    // declared here to have something to map
+    let f64_type = context.f64_type();
+    let unary = f64_type.fn_type(&[f64_type.into()], false);
+    let binary = f64_type.fn_type(&[f64_type.into(), f64_type.into()], false);
+    let int_power = f64_type.fn_type(&[f64_type.into(), context.i32_type().into()], false);
+    let libm = [
+        ("pow", binary, pow as *const () as usize),
+        ("__powidf2", int_power, powi as *const () as usize),
+        ("sin", unary, sin as *const () as usize),
+        ("cos", unary, cos as *const () as usize),
+        ("tan", unary, tan as *const () as usize),
+        ("round", unary, round as *const () as usize),
+        ("floor", unary, floor as *const () as usize),
+        ("ceil", unary, ceil as *const () as usize),
+    ];
+    for (name, fn_type, address) in libm {
+        let function = module
+            .get_function(name)
+            .unwrap_or_else(|| module.add_function(name, fn_type, Some(Linkage::External)));
+        symbols.push((function, address));
+    }
+    symbols
+}
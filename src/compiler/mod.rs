@@ -1,11 +1,16 @@
 mod builtins;
 mod codegen;
 mod function;
+mod jit;
 mod scope;
+mod session;
+#[cfg(test)]
+mod tests;
 mod value;
 
-pub use builtins::*;
 pub use codegen::*;
 pub use function::Function;
+pub use jit::run_jit;
 pub use scope::Scope;
+pub use session::Session;
 pub use value::Value;
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, mem};
 
 use inkwell::{builder::Builder, context::Context, values::PointerValue};
 
@@ -7,114 +7,192 @@ use crate::{
     Type,
 };
 
-pub struct Scope<'a, 'ctx> {
+#[derive(Default)]
+pub struct Scope<'ctx> {
     pub variables: HashMap<String, (PointerValue<'ctx>, Type)>,
-    functions: HashMap<String, Function<'ctx>>,
-    parent: Option<&'a Scope<'a, 'ctx>>,
+    pub functions: HashMap<String, Function<'ctx>>,
+    // Headers of the lists created in this scope, freed when it ends
+    pub lists: Vec<PointerValue<'ctx>>,
+    // Returning ends every scope up to the function's
+    pub function: bool,
+    parent: Option<Box<Scope<'ctx>>>,
 }
 
-impl<'a, 'ctx> Scope<'a, 'ctx> {
-    pub fn new(parent: Option<&'a Scope<'a, 'ctx>>) -> Self {
+impl<'ctx> Scope<'ctx> {
+    pub fn new() -> Self {
         Self {
             variables: HashMap::new(),
             functions: HashMap::new(),
-            parent,
+            lists: vec![],
+            function: false,
+            parent: None,
         }
     }
 
-    pub fn get_ptr(&self, name: &str, builder: &Builder<'ctx>) -> PointerValue<'ctx> {
-        match self.variables.get(name) {
-            Some((ptr, _)) => *ptr,
-            None => match self.parent {
-                Some(parent) => parent.get_ptr(name, builder),
-                None => panic!("{} is not defined", name),
-            },
+    pub fn push(&mut self) {
+        let parent = mem::replace(self, Scope::new());
+        self.parent = Some(Box::new(parent));
+    }
+
+    pub fn pop(&mut self) {
+        if let Some(parent) = self.parent.take() {
+            *self = *parent;
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.parent.as_ref().map_or(0, |parent| parent.depth() + 1)
+    }
+
+    // The lists owned by the scopes nested deeper than `depth`, which a jump
+    // out to that depth skips the end of
+    pub fn lists_above(&self, depth: usize) -> Vec<PointerValue<'ctx>> {
+        let mut lists = vec![];
+        let mut scope = self;
+        for _ in depth..self.depth() {
+            lists.extend(&scope.lists);
+            match &scope.parent {
+                Some(parent) => scope = parent,
+                None => break,
+            }
         }
+        lists
     }
 
-    pub fn get(&self, name: &str, builder: &Builder<'ctx>) -> Value<'ctx> {
+    // The lists owned by every scope in the current function
+    pub fn function_lists(&self) -> Vec<PointerValue<'ctx>> {
+        let mut lists = self.lists.clone();
+        if !self.function {
+            if let Some(parent) = &self.parent {
+                lists.extend(parent.function_lists());
+            }
+        }
+        lists
+    }
+
+    pub fn get_variable(&self, name: &str) -> Option<(PointerValue<'ctx>, Type)> {
         match self.variables.get(name) {
-            Some((ptr, ty)) => match ty {
-                Type::Int => {
-                    let value = builder.build_load(*ptr, &name);
-                    Value::Int(value.into_int_value())
-                }
-                Type::Float => {
-                    let value = builder.build_load(*ptr, &name);
-                    Value::Float(value.into_float_value())
-                }
-                Type::Bool => {
-                    let value = builder.build_load(*ptr, &name);
-                    Value::Bool(value.into_int_value())
-                }
-                Type::Str => Value::Str(*ptr),
-                Type::Char => {
-                    let value = builder.build_load(*ptr, &name);
-                    Value::Char(value.into_int_value())
-                }
-                Type::Array(ty, size) => Value::Array(*ptr, *ty, *size),
-                Type::Void => panic!("void isn't a valid variable type"),
-            },
-            None => match self.parent {
-                Some(parent) => parent.get(name, builder),
-                None => panic!("{} is not defined", name),
-            },
+            Some((ptr, ty)) => Some((*ptr, ty.clone())),
+            None => self.parent.as_ref()?.get_variable(name),
         }
     }
 
+    pub fn get(&self, name: &str, builder: &Builder<'ctx>) -> Option<Value<'ctx>> {
+        let (ptr, ty) = self.get_variable(name)?;
+        Some(match ty {
+            Type::Int => {
+                let value = builder.build_load(ptr, name);
+                Value::Int(value.into_int_value())
+            }
+            Type::Float => {
+                let value = builder.build_load(ptr, name);
+                Value::Float(value.into_float_value())
+            }
+            Type::Bool => {
+                let value = builder.build_load(ptr, name);
+                Value::Bool(value.into_int_value())
+            }
+            Type::Str => {
+                let value = builder.build_load(ptr, name);
+                Value::Str(value.into_pointer_value())
+            }
+            Type::Char => {
+                let value = builder.build_load(ptr, name);
+                Value::Char(value.into_int_value())
+            }
+            Type::Array(ty, size) => {
+                let value = builder.build_load(ptr, name);
+                Value::Array(value.into_pointer_value(), *ty, size)
+            }
+            Type::List(ty) => Value::List(ptr, *ty),
+            Type::Struct(struct_name) => {
+                let value = builder.build_load(ptr, name);
+                Value::Struct(value.into_struct_value(), struct_name)
+            }
+            Type::Void => unreachable!("void isn't a valid variable type"),
+        })
+    }
+
     pub fn set(
         &mut self,
         name: String,
         value: Value<'ctx>,
         context: &'ctx Context,
         builder: &Builder<'ctx>,
+        entry_builder: &Builder<'ctx>,
     ) -> Value<'ctx> {
         let int_type = context.i32_type();
         let float_type = context.f64_type();
         let bool_type = context.bool_type();
         let char_type = context.i8_type();
 
+        // A let in the same scope reuses the variable's slot if the type is
+        // unchanged. Otherwise it gets a new one, leaving any outer variable alone
         let val_ptr_result = self.variables.get(&name);
         match value {
             Value::Int(value) => {
                 let val_ptr = match val_ptr_result {
-                    None => builder.build_alloca(int_type, &name),
-                    Some((ptr, _)) => *ptr,
+                    Some((ptr, Type::Int)) => *ptr,
+                    _ => entry_builder.build_alloca(int_type, &name),
                 };
                 self.variables.insert(name, (val_ptr, Type::Int));
                 builder.build_store(val_ptr, value);
             }
             Value::Float(value) => {
                 let val_ptr = match val_ptr_result {
-                    None => builder.build_alloca(float_type, &name),
-                    Some((ptr, _)) => *ptr,
+                    Some((ptr, Type::Float)) => *ptr,
+                    _ => entry_builder.build_alloca(float_type, &name),
                 };
                 self.variables.insert(name, (val_ptr, Type::Float));
                 builder.build_store(val_ptr, value);
             }
             Value::Bool(value) => {
                 let val_ptr = match val_ptr_result {
-                    None => builder.build_alloca(bool_type, &name),
-                    Some((ptr, _)) => *ptr,
+                    Some((ptr, Type::Bool)) => *ptr,
+                    _ => entry_builder.build_alloca(bool_type, &name),
                 };
                 self.variables.insert(name, (val_ptr, Type::Bool));
                 builder.build_store(val_ptr, value);
             }
             Value::Str(value) => {
-                self.variables.insert(name, (value, Type::Str));
+                let val_ptr = match val_ptr_result {
+                    Some((ptr, Type::Str)) => *ptr,
+                    _ => entry_builder.build_alloca(value.get_type(), &name),
+                };
+                self.variables.insert(name, (val_ptr, Type::Str));
+                builder.build_store(val_ptr, value);
             }
             Value::Char(value) => {
                 let val_ptr = match val_ptr_result {
-                    None => builder.build_alloca(char_type, &name),
-                    Some((ptr, _)) => *ptr,
+                    Some((ptr, Type::Char)) => *ptr,
+                    _ => entry_builder.build_alloca(char_type, &name),
                 };
                 self.variables.insert(name, (val_ptr, Type::Char));
                 builder.build_store(val_ptr, value);
             }
-            Value::Array(value, ty, size) => {
-                self.variables.insert(name, (value, Type::Array(ty, size)));
+            Value::Array(value, ref ty, size) => {
+                let array_type = Type::Array(Box::new(ty.clone()), size);
+                let val_ptr = match val_ptr_result {
+                    Some((ptr, old_type)) if *old_type == array_type => *ptr,
+                    _ => entry_builder.build_alloca(value.get_type(), &name),
+                };
+                self.variables.insert(name, (val_ptr, array_type));
+                builder.build_store(val_ptr, value);
+            }
+            Value::List(value, ref ty) => {
+                self.variables
+                    .insert(name, (value, Type::List(Box::new(ty.clone()))));
+            }
+            Value::Struct(value, ref struct_name) => {
+                let val_ptr = match val_ptr_result {
+                    Some((ptr, Type::Struct(old_name))) if old_name == struct_name => *ptr,
+                    _ => entry_builder.build_alloca(value.get_type(), &name),
+                };
+                self.variables
+                    .insert(name, (val_ptr, Type::Struct(struct_name.clone())));
+                builder.build_store(val_ptr, value);
             }
-            Value::Void => panic!("void isn't a valid type"),
+            Value::Void => unreachable!("void isn't a valid variable type"),
         };
 
         value
@@ -124,13 +202,14 @@ impl<'a, 'ctx> Scope<'a, 'ctx> {
         self.functions.insert(name, function);
     }
 
-    pub fn get_function(&self, name: &str) -> &Function<'ctx> {
+    pub fn get_local_function(&self, name: &str) -> Option<&Function<'ctx>> {
+        self.functions.get(name)
+    }
+
+    pub fn get_function(&self, name: &str) -> Option<&Function<'ctx>> {
         match self.functions.get(name) {
-            Some(value) => value,
-            None => match self.parent {
-                Some(parent) => parent.get_function(name),
-                None => panic!("function {} is not defined", name),
-            },
+            Some(value) => Some(value),
+            None => self.parent.as_ref()?.get_function(name),
         }
     }
 }
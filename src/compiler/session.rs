@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use inkwell::{
+    context::Context,
+    execution_engine::ExecutionEngine,
+    module::{Linkage, Module},
+    targets::{InitializationConfig, Target},
+    types::FunctionType,
+    OptimizationLevel,
+};
+
+use crate::{
+    compiler::{
+        builtins::{jump_buffer_type, PANIC_JUMP},
+        jit::{flush_output, host_symbols},
+        Codegen, Function, Value,
+    },
+    Diagnostic, Node, Type,
+};
+
+// The REPL's compiler. Each entry becomes its own module, which links against
+// the variables and functions of the entries before it by name
+pub struct Session<'ctx> {
+    context: &'ctx Context,
+    engine: ExecutionEngine<'ctx>,
+    // The engine runs code from these, so they live as long as it does
+    modules: Vec<Module<'ctx>>,
+    // Each variable's global and type
+    variables: HashMap<String, (String, Type)>,
+    // Each function's symbol, type and return type
+    functions: HashMap<String, (String, FunctionType<'ctx>, Type)>,
+    structs: HashMap<String, Vec<(String, Type)>>,
+    pub bounds_checks: bool,
+    pub overflow_checks: bool,
+}
+
+// A compiled entry, which only affects the session once it's run
+pub struct Entry<'ctx> {
+    pub module: Module<'ctx>,
+    name: String,
+    variables: HashMap<String, (String, Type)>,
+    functions: HashMap<String, (String, FunctionType<'ctx>, Type)>,
+    structs: HashMap<String, Vec<(String, Type)>>,
+}
+
+impl<'ctx> Session<'ctx> {
+    pub fn new(context: &'ctx Context) -> Result<Self, String> {
+        Target::initialize_native(&InitializationConfig::default())?;
+        let module = context.create_module("repl");
+        let jump_buffer = jump_buffer_type(context);
+        let global = module.add_global(jump_buffer, None, PANIC_JUMP);
+        global.set_initializer(&jump_buffer.const_zero());
+        let engine = module
+            .create_jit_execution_engine(OptimizationLevel::None)
+            .map_err(|err| format!("couldn't create JIT: {}", err))?;
+        Ok(Self {
+            context,
+            engine,
+            modules: vec![module],
+            variables: HashMap::new(),
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            bounds_checks: true,
+            overflow_checks: false,
+        })
+    }
+
+    pub fn compile(&self, ast: Node) -> Result<Entry<'ctx>, Diagnostic> {
+        let index = self.modules.len();
+        let name = format!("repl.{}", index);
+        let module = self.context.create_module(&name);
+        let mut codegen = Codegen::new(
+            "<repl>",
+            self.context,
+            &module,
+            self.context.create_builder(),
+        );
+        codegen.repl = true;
+        codegen.bounds_checks = self.bounds_checks;
+        codegen.overflow_checks = self.overflow_checks;
+        codegen.structs = self.structs.clone();
+
+        // Earlier variables are read once at the start, so the entry works on its own copy
+        for (name, (symbol, ty)) in &self.variables {
+            let global = module.add_global(ty.get_type(self.context), None, symbol);
+            let value = codegen.builder.build_load(global.as_pointer_value(), name);
+            codegen.add_var(name, Value::new(value, ty));
+        }
+        for (name, (symbol, fn_type, return_type)) in &self.functions {
+            let function = Function::declare(
+                symbol,
+                *fn_type,
+                return_type.clone(),
+                Some(Linkage::External),
+                &mut codegen,
+            );
+            codegen.scope.add_function(name.clone(), function);
+        }
+
+        codegen.generate_llvm_ir(ast)?;
+
+        // Every top-level variable is copied into a global of its own before
+        // returning, for the next entry to start from
+        let block = codegen.builder.get_insert_block().unwrap();
+        codegen
+            .builder
+            .position_before(&block.get_terminator().unwrap());
+        let mut variables = HashMap::new();
+        for (variable, (_, ty)) in codegen.scope.variables.clone() {
+            let value = codegen.scope.get(&variable, &codegen.builder).unwrap();
+            let symbol = format!("{}.{}", variable, index);
+            let global_type = ty.get_type(self.context);
+            let global = module.add_global(global_type, None, &symbol);
+            global.set_initializer(&global_type.const_zero());
+            codegen
+                .builder
+                .build_store(global.as_pointer_value(), value.get_value());
+            variables.insert(variable, (symbol, ty));
+        }
+
+        // Functions defined here get names no other entry uses. Nested functions
+        // aren't visible to later entries, so they're kept private
+        let mut functions = HashMap::new();
+        for (function_name, function) in codegen.scope.functions.clone() {
+            let value = function.value;
+            if value.count_basic_blocks() > 0 {
+                let symbol = format!("{}.{}", function_name, index);
+                value.as_global_value().as_pointer_value().set_name(&symbol);
+            }
+            let symbol = value.get_name().to_string_lossy().into_owned();
+            functions.insert(
+                function_name,
+                (symbol, value.get_type(), function.return_type),
+            );
+        }
+        for function in module.get_functions() {
+            let symbol = function.get_name().to_string_lossy();
+            let exported = functions.values().any(|(name, ..)| *name == symbol);
+            if function.count_basic_blocks() > 0 && function != codegen.function && !exported {
+                function.set_linkage(Linkage::Private);
+            }
+        }
+        codegen
+            .function
+            .as_global_value()
+            .as_pointer_value()
+            .set_name(&name);
+
+        let structs = codegen.structs.clone();
+        drop(codegen);
+        Ok(Entry {
+            module,
+            name,
+            variables,
+            functions,
+            structs,
+        })
+    }
+
+    // Runs an entry, after which later entries can use what it defined, unless
+    // it panicked
+    pub fn run(&mut self, entry: Entry<'ctx>) -> Result<(), String> {
+        if let Err(err) = entry.module.verify() {
+            return Err(format!("generated invalid LLVM IR: {}", err));
+        }
+
+        let symbols = host_symbols(self.context, &entry.module);
+        self.engine
+            .add_module(&entry.module)
+            .map_err(|_| String::from("couldn't add the entry to the JIT"))?;
+        for (function, address) in symbols {
+            self.engine.add_global_mapping(&function, address);
+        }
+        let function = self
+            .engine
+            .get_function_value(&entry.name)
+            .map_err(|err| format!("couldn't find {}: {}", entry.name, err))?;
+        let code = unsafe { self.engine.run_function_as_main(function, &[]) };
+        flush_output();
+
+        // Functions from earlier entries may have run, so the code stays either way
+        self.modules.push(entry.module);
+        if code != 0 {
+            return Err(String::from(
+                "the entry panicked, so nothing it defined was kept",
+            ));
+        }
+        self.variables = entry.variables;
+        self.functions = entry.functions;
+        self.structs = entry.structs;
+        Ok(())
+    }
+}
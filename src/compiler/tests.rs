@@ -0,0 +1,337 @@
+use inkwell::{
+    context::Context,
+    module::Module,
+    values::{AnyValue, InstructionOpcode},
+};
+
+use crate::{compiler::Session, Codegen, Lexer, Parser, TypeChecker};
+
+// Generates IR for `source` and hands the module to `check`
+fn generate(source: &str, check: impl FnOnce(&Module)) {
+    let tokens = Lexer::new(source.to_string()).lex().0;
+    let (ast, errors) = Parser::new(tokens).parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    let (ast, errors) = TypeChecker::new().check(ast);
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let context = Context::create();
+    let module = context.create_module("test");
+    let mut codegen = Codegen::new("test.thor", &context, &module, context.create_builder());
+    codegen.generate_llvm_ir(ast).unwrap();
+    check(&module);
+}
+
+// Checks that LLVM accepts the IR generated for `source`
+fn verify(source: &str) {
+    generate(source, |module| {
+        if let Err(err) = module.verify() {
+            panic!(
+                "{}\n{}",
+                err.to_string(),
+                module.print_to_string().to_string()
+            );
+        }
+    });
+}
+
+#[test]
+fn while_loop() {
+    verify(
+        "let i = 0
+while i < 10 {
+  i += 1
+}
+print(i)",
+    );
+}
+
+#[test]
+fn while_condition_with_control_flow() {
+    verify(
+        "let xs = [1, 2, 3]
+let i = 0
+while i < 3 and xs[i] > 0 {
+  i += 1
+}
+while 0 <= i < 10: i += 1",
+    );
+}
+
+#[test]
+fn while_with_if() {
+    verify(
+        "let i = 0
+let evens = 0
+while i < 10 {
+  if i % 2 == 0 {
+    evens += 1
+  } else {
+    evens -= 0
+  }
+  i += 1
+}",
+    );
+}
+
+#[test]
+fn nested_while() {
+    verify(
+        "let i = 0
+let total = 0
+while i < 10 {
+  let j = 0
+  while j < i {
+    total += j
+    j += 1
+  }
+  i += 1
+}",
+    );
+}
+
+#[test]
+fn return_from_while() {
+    verify(
+        "fn first_square_above(n: int): int {
+  let i = 0
+  while true {
+    if i * i > n: return i
+    i += 1
+  }
+  return 0
+}
+fn count(n: int) {
+  let i = 0
+  while i < n {
+    if i == 5: break
+    i += 1
+  }
+  print(i)
+}
+print(first_square_above(50))
+count(10)",
+    );
+}
+
+#[test]
+fn break_and_continue_in_while() {
+    verify(
+        "let i = 0
+'outer: while i < 10 {
+  i += 1
+  let j = 0
+  while j < 10 {
+    j += 1
+    if j == i: continue 'outer
+    if i == 7: break 'outer
+  }
+}",
+    );
+}
+
+#[test]
+fn shadowing_in_blocks() {
+    verify(
+        "let x = 1
+if x > 0 {
+  let x = 2.5
+  print(x * 2)
+}
+let x = \"now a string\"
+print(x)",
+    );
+}
+
+#[test]
+fn builtin_constant_in_function() {
+    verify(
+        "fn area(r: float) {
+  return PI * r * r
+}
+print(area(2.0))",
+    );
+}
+
+#[test]
+fn int_pow_in_a_loop() {
+    verify(
+        "let total = 0
+for i in 0..5 {
+  total += i ^ (i + 1)
+}
+print(total ^ 2)",
+    );
+}
+
+#[test]
+fn allocas_in_entry_block() {
+    let source = "let total = 0
+for i in 0..10 {
+  let square = i * i
+  let name = \"square\"
+  let xs = [square, i]
+  total += xs[0]
+}
+print(total)";
+    generate(source, |module| {
+        let main = module.get_function("main").unwrap();
+        for block in main.get_basic_blocks().into_iter().skip(1) {
+            let mut instruction = block.get_first_instruction();
+            while let Some(current) = instruction {
+                assert_ne!(current.get_opcode(), InstructionOpcode::Alloca);
+                instruction = current.get_next_instruction();
+            }
+        }
+    });
+}
+
+// The name of the block of each call to `free` in `main`
+fn frees(module: &Module) -> Vec<String> {
+    let main = module.get_function("main").unwrap();
+    let mut blocks = vec![];
+    for block in main.get_basic_blocks() {
+        let mut instruction = block.get_first_instruction();
+        while let Some(current) = instruction {
+            if current.print_to_string().to_string().contains("@free(") {
+                blocks.push(block.get_name().to_string_lossy().into_owned());
+            }
+            instruction = current.get_next_instruction();
+        }
+    }
+    blocks
+}
+
+#[test]
+fn short_circuit_frees_lists_only_if_the_right_side_ran() {
+    let source = "let xs = [1, 2, 3]
+print(false and len(xs[0..2]) == 2)";
+    generate(source, |module| {
+        module.verify().unwrap();
+        let frees = frees(module);
+        assert_eq!(frees.len(), 1);
+        assert_ne!(frees[0], "and_end");
+    });
+}
+
+#[test]
+fn chained_comparison_frees_lists_only_if_their_operand_ran() {
+    let source = "let xs = [1, 2, 3]
+print(1 < len(xs[0..2]) < 2)";
+    generate(source, |module| {
+        module.verify().unwrap();
+        assert_eq!(frees(module), ["compare_fail", "compare_next"]);
+    });
+}
+
+#[test]
+fn repl_entries_link_to_earlier_ones() {
+    let context = Context::create();
+    let mut session = Session::new(&context).unwrap();
+    let mut checker = TypeChecker::new();
+    let entries = [
+        "let xs = [1, 2]
+fn double(n: int): int {
+  return n * 2
+}",
+        "let total = double(xs[1])",
+        "print(total, xs[0])",
+    ];
+    for source in entries {
+        let tokens = Lexer::new(source.to_string()).lex().0;
+        let (ast, errors) = Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let (ast, errors) = checker.check(ast);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let entry = session.compile(ast).unwrap();
+        session.run(entry).unwrap();
+    }
+}
+
+#[test]
+fn repl_panics_keep_earlier_entries() {
+    let context = Context::create();
+    let mut session = Session::new(&context).unwrap();
+    let mut checker = TypeChecker::new();
+    let entries = [
+        ("let xs = [1, 2]", true),
+        ("let y = xs[5]", false),
+        ("let z = 1\nprint(xs[1] / (z - 1))", false),
+        ("print(xs[1])", true),
+    ];
+    for (source, runs) in entries {
+        let tokens = Lexer::new(source.to_string()).lex().0;
+        let (ast, errors) = Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let (ast, errors) = checker.check(ast);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let entry = session.compile(ast).unwrap();
+        assert_eq!(session.run(entry).is_ok(), runs, "{}", source);
+    }
+}
+
+#[test]
+fn every_type_as_argument_and_return() {
+    verify(
+        "struct Point { x: int, y: float }
+fn int_id(n: int): int {
+  return n
+}
+fn float_id(n: float): float {
+  return n
+}
+fn bool_id(b: bool): bool {
+  return b
+}
+fn str_id(s: str): str {
+  return s
+}
+fn char_id(c: char): char {
+  return c
+}
+fn array_id(xs: int[3]): int[3] {
+  return xs
+}
+fn list_id(xs: int[]): int[] {
+  return xs
+}
+fn point_id(p: Point): Point {
+  return p
+}
+let xs = array_id([1, 2, 3])
+let p = point_id(Point { x: 1, y: 2.5 })
+print(int_id(1), float_id(2.5), bool_id(true), str_id(\"s\"), char_id('c'))
+print(xs[2], len(list_id([4, 5])), p.y)",
+    );
+}
+
+#[test]
+fn arrays_and_structs_return_through_a_pointer() {
+    let source = "struct Point { x: int, y: float }
+fn origin(): Point {
+  if false: return Point { x: 1, y: 1.0 }
+  return Point { x: 0, y: 0.0 }
+}
+fn zeros(): float[4] {
+  return [0.0, 0.0, 0.0, 0.0]
+}
+fn half(n: int): float {
+  return n / 2.0
+}
+let p = origin()
+let zs = zeros()
+print(p.x, zs[3], half(3))";
+    generate(source, |module| {
+        let context = module.get_context();
+        for name in ["origin", "zeros"] {
+            let function = module.get_function(name).unwrap();
+            assert_eq!(function.get_type().get_return_type(), None);
+            assert_eq!(function.count_params(), 1);
+        }
+        let half = module.get_function("half").unwrap();
+        assert_eq!(
+            half.get_type().get_return_type(),
+            Some(context.f64_type().into())
+        );
+        module.verify().unwrap();
+    });
+}
@@ -1,49 +1,56 @@
 use inkwell::{
     context::Context,
     types::BasicTypeEnum,
-    values::{BasicValueEnum, FloatValue, IntValue, PointerValue},
-    AddressSpace,
+    values::{BasicValueEnum, FloatValue, IntValue, PointerValue, StructValue},
 };
 
-use crate::TypeLiteral;
+use crate::Type;
 
+#[derive(Clone)]
 pub enum Value<'ctx> {
     Int(IntValue<'ctx>),
     Float(FloatValue<'ctx>),
     Bool(IntValue<'ctx>),
     Str(PointerValue<'ctx>),
     Char(IntValue<'ctx>),
-    Array(PointerValue<'ctx>, TypeLiteral, u32),
+    Array(PointerValue<'ctx>, Type, u32),
+    // A pointer to the list's header
+    List(PointerValue<'ctx>, Type),
+    Struct(StructValue<'ctx>, String),
     Void,
 }
 
 impl<'ctx> Value<'ctx> {
+    pub fn new(value: BasicValueEnum<'ctx>, ty: &Type) -> Self {
+        match ty {
+            Type::Int => Value::Int(value.into_int_value()),
+            Type::Float => Value::Float(value.into_float_value()),
+            Type::Bool => Value::Bool(value.into_int_value()),
+            Type::Str => Value::Str(value.into_pointer_value()),
+            Type::Char => Value::Char(value.into_int_value()),
+            Type::Array(ty, size) => Value::Array(value.into_pointer_value(), *ty.clone(), *size),
+            Type::List(ty) => Value::List(value.into_pointer_value(), *ty.clone()),
+            Type::Struct(name) => Value::Struct(value.into_struct_value(), name.clone()),
+            Type::Void => panic!("void isn't a valid value type"),
+        }
+    }
+
     pub fn get_type(&self, context: &'ctx Context) -> BasicTypeEnum<'ctx> {
-        let int_type = context.i32_type();
-        let float_type = context.f64_type();
-        let bool_type = context.bool_type();
-        let str_type = context.i8_type().ptr_type(AddressSpace::Generic);
-        let char_type = context.i8_type();
+        self.ty().get_type(context)
+    }
 
+    pub fn ty(&self) -> Type {
         use Value::*;
         match self {
-            Int(_) => BasicTypeEnum::IntType(int_type),
-            Float(_) => BasicTypeEnum::FloatType(float_type),
-            Bool(_) => BasicTypeEnum::IntType(bool_type),
-            Str(_) => BasicTypeEnum::PointerType(str_type),
-            Char(_) => BasicTypeEnum::IntType(char_type),
-            Array(_, ty, size) => BasicTypeEnum::PointerType(
-                match ty {
-                    TypeLiteral::Int => int_type.array_type(*size),
-                    TypeLiteral::Float => float_type.array_type(*size),
-                    TypeLiteral::Bool => bool_type.array_type(*size),
-                    TypeLiteral::Str => str_type.array_type(*size),
-                    TypeLiteral::Char => char_type.array_type(*size),
-                    TypeLiteral::Void => panic!("can't have a void array"),
-                }
-                .ptr_type(AddressSpace::Generic),
-            ),
-            Void => panic!("void isn't a valid type"),
+            Int(_) => Type::Int,
+            Float(_) => Type::Float,
+            Bool(_) => Type::Bool,
+            Str(_) => Type::Str,
+            Char(_) => Type::Char,
+            Array(_, ty, size) => Type::Array(Box::new(ty.clone()), *size),
+            List(_, ty) => Type::List(Box::new(ty.clone())),
+            Struct(_, name) => Type::Struct(name.clone()),
+            Void => Type::Void,
         }
     }
 
@@ -55,7 +62,8 @@ impl<'ctx> Value<'ctx> {
             Bool(value) => BasicValueEnum::IntValue(*value),
             Str(ptr) => BasicValueEnum::PointerValue(*ptr),
             Char(value) => BasicValueEnum::IntValue(*value),
-            Array(ptr, _, _) => BasicValueEnum::PointerValue(*ptr),
+            Array(ptr, _, _) | List(ptr, _) => BasicValueEnum::PointerValue(*ptr),
+            Struct(value, _) => BasicValueEnum::StructValue(*value),
             Void => panic!("void isn't a valid value type"),
         }
     }
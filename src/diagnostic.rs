@@ -0,0 +1,122 @@
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Self {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end.max(self.end),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Level {
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    pub span: Span,
+    pub notes: Vec<String>,
+    // The file the span is in, filled in by whoever read the source
+    pub file: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            level: Level::Error,
+            message: message.into(),
+            span,
+            notes: vec![],
+            file: None,
+        }
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    // Sets the file the span is in, unless it's already set
+    pub fn in_file(mut self, file: impl Into<String>) -> Self {
+        self.file.get_or_insert_with(|| file.into());
+        self
+    }
+
+    pub fn render(&self, source: &str) -> String {
+        let line_text = source
+            .lines()
+            .nth(self.span.line.saturating_sub(1))
+            .unwrap_or("");
+        let gutter = self.span.line.to_string().len();
+        let pad = " ".repeat(gutter);
+
+        // Keep tabs in the prefix so the carets line up with the source line
+        let prefix: String = line_text
+            .chars()
+            .take(self.span.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let remaining = line_text
+            .chars()
+            .count()
+            .saturating_sub(self.span.column.saturating_sub(1));
+        let width = (self.span.end - self.span.start).clamp(1, remaining.max(1));
+
+        let mut output = format!(
+            "{}: {}\n{}--> {}:{}\n{} |\n{} | {}\n{} | {}{}",
+            self.level,
+            self.message,
+            pad,
+            self.file.as_deref().unwrap_or("<unknown>"),
+            self.span,
+            pad,
+            self.span.line,
+            line_text,
+            pad,
+            prefix,
+            "^".repeat(width),
+        );
+        if !self.notes.is_empty() {
+            output.push_str(&format!("\n{} |", pad));
+            for note in &self.notes {
+                output.push_str(&format!("\n{} = note: {}", pad, note));
+            }
+        }
+        output
+    }
+}
@@ -0,0 +1,338 @@
+use std::{cmp::Ordering, rc::Rc};
+
+use crate::{
+    interp::{
+        interpreter::Flow,
+        value::{format_float, str_len, Value},
+        Interpreter,
+    },
+    BinaryOp, Diagnostic, Span,
+};
+
+impl<'a> Interpreter<'a> {
+    pub fn binary(
+        &mut self,
+        op: BinaryOp,
+        l_value: Value,
+        r_value: Value,
+        span: Span,
+    ) -> Flow<Value> {
+        let invalid = || {
+            Diagnostic::error(
+                format!(
+                    "unsupported operand types for {}: {} and {}",
+                    op,
+                    l_value.ty(),
+                    r_value.ty()
+                ),
+                span,
+            )
+        };
+
+        use BinaryOp::*;
+        Ok(match op {
+            Add | Sub | Mul => match (&l_value, &r_value) {
+                (Value::Int(l), Value::Int(r)) => {
+                    Value::Int(self.int_arithmetic(op, *l, *r, span)?)
+                }
+                _ => match (l_value.float(), r_value.float()) {
+                    (Some(l), Some(r)) => Value::Float(match op {
+                        Add => l + r,
+                        Sub => l - r,
+                        _ => l * r,
+                    }),
+                    _ => return Err(invalid().into()),
+                },
+            },
+            Div | Rem => match (&l_value, &r_value) {
+                (Value::Int(l), Value::Int(r)) => Value::Int(self.int_division(op, *l, *r, span)?),
+                _ => match (l_value.float(), r_value.float()) {
+                    (Some(l), Some(r)) => Value::Float(if op == Div { l / r } else { l % r }),
+                    _ => return Err(invalid().into()),
+                },
+            },
+            Pow => match (&l_value, &r_value) {
+                (Value::Int(l), Value::Int(r)) => Value::Int(self.int_pow(*l, *r, span)?),
+                (Value::Float(l), Value::Int(r)) => Value::Float(l.powi(*r)),
+                _ => match (l_value.float(), r_value.float()) {
+                    (Some(l), Some(r)) => Value::Float(l.powf(r)),
+                    _ => return Err(invalid().into()),
+                },
+            },
+            BitAnd | BitOr | Shl | Shr => match (&l_value, r_value.integer()) {
+                (Value::Int(l), Some(r)) => Value::Int(match op {
+                    BitAnd => l & r,
+                    BitOr => l | r,
+                    Shl => l.wrapping_shl(self.shift_amount(op, r as u32, i32::BITS, span)?),
+                    _ => l.wrapping_shr(self.shift_amount(op, r as u32, i32::BITS, span)?),
+                }),
+                // The right side is truncated to a char, and chars shift right logically
+                (Value::Char(l), Some(r)) => Value::Char(match op {
+                    BitAnd => l & r as u8,
+                    BitOr => l | r as u8,
+                    Shl => l.wrapping_shl(self.shift_amount(op, r as u8 as u32, u8::BITS, span)?),
+                    _ => l.wrapping_shr(self.shift_amount(op, r as u8 as u32, u8::BITS, span)?),
+                }),
+                _ => return Err(invalid().into()),
+            },
+            Is | IsNot => match identity(&l_value, &r_value) {
+                Some(same) => Value::Bool(same == (op == Is)),
+                None => return Err(invalid().into()),
+            },
+            In | NotIn => Value::Bool(self.contains(&l_value, &r_value, span)? == (op == In)),
+            And | Or => unreachable!("{} only evaluates its right side lazily", op),
+            EqEq | Neq | Lt | Lte | Gt | Gte => match (&l_value, &r_value) {
+                (Value::Bool(l), Value::Bool(r)) if matches!(op, EqEq | Neq) => {
+                    Value::Bool((l == r) == (op == EqEq))
+                }
+                _ => match (l_value.integer(), r_value.integer()) {
+                    (Some(l), Some(r)) => Value::Bool(compare(op, l, r)),
+                    _ => match (l_value.float(), r_value.float()) {
+                        (Some(l), Some(r)) => Value::Bool(compare(op, l, r)),
+                        _ => return Err(invalid().into()),
+                    },
+                },
+            },
+        })
+    }
+
+    // Integer +, - and *, which panic on overflow when overflow checks are on
+    fn int_arithmetic(&self, op: BinaryOp, l: i32, r: i32, span: Span) -> Flow<i32> {
+        if self.overflow_checks {
+            self.checked_int_arithmetic(op, l, r, span)
+        } else {
+            Ok(match op {
+                BinaryOp::Add => l.wrapping_add(r),
+                BinaryOp::Sub => l.wrapping_sub(r),
+                _ => l.wrapping_mul(r),
+            })
+        }
+    }
+
+    fn checked_int_arithmetic(&self, op: BinaryOp, l: i32, r: i32, span: Span) -> Flow<i32> {
+        let (result, message) = match op {
+            BinaryOp::Add => (l.checked_add(r), "attempt to add with overflow"),
+            BinaryOp::Sub => (l.checked_sub(r), "attempt to subtract with overflow"),
+            BinaryOp::Mul => (l.checked_mul(r), "attempt to multiply with overflow"),
+            _ => unreachable!("{} can't overflow", op),
+        };
+        result.ok_or_else(|| self.panic(span, message))
+    }
+
+    pub fn int_neg(&self, value: i32, span: Span) -> Flow<i32> {
+        if self.overflow_checks {
+            value
+                .checked_neg()
+                .ok_or_else(|| self.panic(span, "attempt to negate with overflow"))
+        } else {
+            Ok(value.wrapping_neg())
+        }
+    }
+
+    // Ints are raised by squaring, multiplying like `*` does so overflow
+    // wraps or panics the same way. Negative exponents always panic
+    fn int_pow(&self, mut base: i32, exponent: i32, span: Span) -> Flow<i32> {
+        if exponent < 0 {
+            return Err(self.panic(span, "attempt to raise an int to a negative power"));
+        }
+        let mut exponent = exponent as u32;
+        let mut result = 1;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = self.int_arithmetic(BinaryOp::Mul, result, base, span)?;
+            }
+            exponent >>= 1;
+            // The last square would go unused, and could overflow when the result doesn't
+            if exponent > 0 {
+                base = self.int_arithmetic(BinaryOp::Mul, base, base, span)?;
+            }
+        }
+        Ok(result)
+    }
+
+    // An amount outside the bit width panics when overflow checks are on and
+    // otherwise wraps around it, like the compiled code does
+    fn shift_amount(&self, op: BinaryOp, amount: u32, bits: u32, span: Span) -> Flow<u32> {
+        if self.overflow_checks && amount >= bits {
+            let message = match op {
+                BinaryOp::Shl => "attempt to shift left with overflow",
+                _ => "attempt to shift right with overflow",
+            };
+            return Err(self.panic(span, message));
+        }
+        Ok(amount % bits)
+    }
+
+    // Signed / and %, which always panic on a zero divisor and on the minimum
+    // int divided by -1
+    fn int_division(&self, op: BinaryOp, l: i32, r: i32, span: Span) -> Flow<i32> {
+        let (zero_message, overflow_message) = match op {
+            BinaryOp::Div => (
+                "attempt to divide by zero",
+                "attempt to divide with overflow",
+            ),
+            _ => (
+                "attempt to calculate the remainder with a divisor of zero",
+                "attempt to calculate the remainder with overflow",
+            ),
+        };
+        if r == 0 {
+            return Err(self.panic(span, zero_message));
+        }
+        if l == i32::MIN && r == -1 {
+            return Err(self.panic(span, overflow_message));
+        }
+        Ok(if op == BinaryOp::Div { l / r } else { l % r })
+    }
+
+    // Whether `value` is in the string, array or list `items`
+    fn contains(&self, value: &Value, items: &Value, span: Span) -> Flow<bool> {
+        Ok(match (value, items) {
+            (Value::Char(value), Value::Str(bytes)) => {
+                let bytes = bytes.borrow();
+                bytes[..str_len(&bytes)].contains(value)
+            }
+            (value, Value::Array(items, _, _) | Value::List(items, _)) => {
+                items.borrow().iter().any(|item| same(value, item))
+            }
+            _ => {
+                return Err(Diagnostic::error(
+                    format!("can't search a value of type {}", items.ty()),
+                    span,
+                )
+                .into())
+            }
+        })
+    }
+
+    // Functions the compiler declares itself or generates inline
+    pub fn builtin(&mut self, name: &str, mut args: Vec<Value>, span: Span) -> Flow<Value> {
+        let invalid = |args: &[Value]| {
+            let types: Vec<String> = args.iter().map(|arg| arg.ty().to_string()).collect();
+            Diagnostic::error(
+                format!("`{}` can't take ({})", name, types.join(", ")),
+                span,
+            )
+        };
+
+        Ok(match (name, &args[..]) {
+            ("print", _) => Value::Int(self.print(args)),
+            ("abs", [Value::Int(value)]) => Value::Int(value.wrapping_abs()),
+            ("round", [Value::Float(value)]) => Value::Float(value.round()),
+            ("floor", [Value::Float(value)]) => Value::Float(value.floor()),
+            ("ceil", [Value::Float(value)]) => Value::Float(value.ceil()),
+            ("sqrt", [Value::Float(value)]) => Value::Float(value.sqrt()),
+            ("sin", [Value::Float(value)]) => Value::Float(value.sin()),
+            ("cos", [Value::Float(value)]) => Value::Float(value.cos()),
+            ("tan", [Value::Float(value)]) => Value::Float(value.tan()),
+            // wrapping_add and checked_add ignore whether overflow checks are on
+            ("wrapping_add", [Value::Int(l), Value::Int(r)]) => Value::Int(l.wrapping_add(*r)),
+            ("checked_add", [Value::Int(l), Value::Int(r)]) => {
+                Value::Int(self.checked_int_arithmetic(BinaryOp::Add, *l, *r, span)?)
+            }
+            ("len", [Value::List(items, _)]) => Value::Int(items.borrow().len() as i32),
+            ("len", [Value::Array(_, _, size)]) => Value::Int(*size as i32),
+            ("len", [Value::Str(bytes)]) => Value::Int(str_len(&bytes.borrow()) as i32),
+            ("push", [Value::List(items, _), _]) => {
+                let items = Rc::clone(items);
+                items.borrow_mut().push(args.remove(1));
+                Value::Void
+            }
+            ("pop", [Value::List(items, _)]) => match items.borrow_mut().pop() {
+                Some(item) => item,
+                None => return Err(self.panic(span, "pop from an empty list")),
+            },
+            ("abs" | "round" | "floor" | "ceil" | "sqrt" | "sin" | "cos" | "tan", _)
+            | ("wrapping_add" | "checked_add" | "len" | "push" | "pop", _) => {
+                return Err(invalid(&args).into())
+            }
+            _ => {
+                return Err(Diagnostic::error(
+                    format!("cannot find function `{}` in this scope", name),
+                    span,
+                )
+                .into())
+            }
+        })
+    }
+
+    // Writes the values the way printf does with the format string `print`
+    // compiles to, returning how many bytes that was
+    fn print(&mut self, args: Vec<Value>) -> i32 {
+        let mut output = vec![];
+        for arg in &args {
+            self.format_value(arg, &mut output);
+            output.push(b' ');
+        }
+        output.push(b'\n');
+        // printf doesn't report failed writes either
+        let _ = self.out.write_all(&output);
+        output.len() as i32
+    }
+
+    fn format_value(&self, value: &Value, output: &mut Vec<u8>) {
+        match value {
+            Value::Int(value) => output.extend(value.to_string().bytes()),
+            Value::Float(value) => output.extend(format_float(*value).bytes()),
+            Value::Bool(value) => output.push(if *value { b'1' } else { b'0' }),
+            Value::Str(bytes) => {
+                let bytes = bytes.borrow();
+                output.extend(&bytes[..str_len(&bytes)]);
+            }
+            Value::Char(value) => output.push(*value),
+            // Compiled code prints where the items are, which differs between runs anyway
+            Value::Array(items, _, _) | Value::List(items, _) => {
+                output.extend(format!("{:p}", Rc::as_ptr(items)).bytes())
+            }
+            Value::Struct(values, name) => {
+                output.extend(format!("{} {{ ", name).bytes());
+                for (i, ((field, _), value)) in self.structs[name].iter().zip(values).enumerate() {
+                    if i > 0 {
+                        output.extend(b", ");
+                    }
+                    output.extend(format!("{}: ", field).bytes());
+                    self.format_value(value, output);
+                }
+                output.extend(b" }");
+            }
+            Value::Void => output.extend(b"(nil)"),
+        }
+    }
+}
+
+// What `is` compares: values directly, floats by their bits, and strings,
+// arrays and lists by address
+fn identity(l_value: &Value, r_value: &Value) -> Option<bool> {
+    Some(match (l_value, r_value) {
+        (Value::Int(l), Value::Int(r)) => l == r,
+        (Value::Bool(l), Value::Bool(r)) => l == r,
+        (Value::Char(l), Value::Char(r)) => l == r,
+        (Value::Float(l), Value::Float(r)) => l.to_bits() == r.to_bits(),
+        (Value::Str(l), Value::Str(r)) => Rc::ptr_eq(l, r),
+        (Value::Array(l, _, _), Value::Array(r, _, _)) | (Value::List(l, _), Value::List(r, _)) => {
+            Rc::ptr_eq(l, r)
+        }
+        _ => return None,
+    })
+}
+
+// Whether an array or list item equals `value`, the way `in` compares them
+fn same(value: &Value, item: &Value) -> bool {
+    match (value, item) {
+        (Value::Float(value), Value::Float(item)) => value == item,
+        (Value::Bool(value), Value::Bool(item)) => value == item,
+        _ => value.integer().is_some() && value.integer() == item.integer(),
+    }
+}
+
+// Float comparisons are ordered, so every one of them is false for a NaN
+fn compare<T: PartialOrd>(op: BinaryOp, l: T, r: T) -> bool {
+    match op {
+        BinaryOp::EqEq => l == r,
+        BinaryOp::Neq => matches!(l.partial_cmp(&r), Some(Ordering::Less | Ordering::Greater)),
+        BinaryOp::Lt => l < r,
+        BinaryOp::Lte => l <= r,
+        BinaryOp::Gt => l > r,
+        _ => l >= r,
+    }
+}
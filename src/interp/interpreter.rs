@@ -0,0 +1,848 @@
+use std::{collections::HashMap, io::Write, rc::Rc};
+
+use crate::{
+    interp::{
+        value::{str_len, Value},
+        Error,
+    },
+    BinaryOp, Diagnostic, IdentifierOp, Node, NodeKind, Span, Type, UnaryOp,
+};
+
+// Walks a checked syntax tree, doing what the code `Codegen` generates for it
+// would do. Indexing is always bounds-checked, since there's no memory to read
+// past the end of
+pub struct Interpreter<'a> {
+    pub filename: String,
+    pub out: &'a mut dyn Write,
+    pub structs: HashMap<String, Vec<(String, Type)>>,
+    pub overflow_checks: bool,
+    frames: Vec<Frame>,
+}
+
+// The variables and functions of one scope
+#[derive(Default)]
+struct Frame {
+    variables: HashMap<String, Value>,
+    functions: HashMap<String, Rc<Definition>>,
+    // The scope names are looked up in next: the enclosing block, or for a
+    // function body the block the function was defined in
+    parent: Option<usize>,
+    // Function bodies can't see the variables around them
+    function: bool,
+}
+
+struct Definition {
+    args: Vec<(String, Type)>,
+    return_type: Type,
+    body: Node,
+    frame: usize,
+}
+
+// Why a node stopped before reaching its end
+pub enum Unwind {
+    Break(Option<String>, Option<Value>),
+    Continue(Option<String>),
+    Return(Value),
+    Error(Error),
+}
+
+impl From<Diagnostic> for Unwind {
+    fn from(diagnostic: Diagnostic) -> Self {
+        Unwind::Error(Error::Diagnostic(Box::new(diagnostic)))
+    }
+}
+
+pub type Flow<T> = Result<T, Unwind>;
+
+// What an iteration of a loop body leads to
+enum Step {
+    Next,
+    Exit(Option<Value>),
+}
+
+// Where an assignment stores its value
+enum Place {
+    // A variable in a frame, or a field nested in it
+    Variable(usize, String, Vec<usize>),
+    // An item of a string, array or list, or a field nested in it
+    Item(Value, usize, Vec<usize>),
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(filename: &str, out: &'a mut dyn Write) -> Self {
+        let mut builtins = Frame::default();
+        builtins
+            .variables
+            .insert("PI".to_string(), Value::Float(std::f64::consts::PI));
+        // User code gets its own scope so it can shadow builtins
+        let user = Frame {
+            parent: Some(0),
+            function: true,
+            ..Frame::default()
+        };
+
+        Self {
+            filename: filename.to_string(),
+            out,
+            structs: HashMap::new(),
+            overflow_checks: false,
+            frames: vec![builtins, user],
+        }
+    }
+
+    pub fn run(&mut self, mut ast: Node) -> Result<(), Error> {
+        self.declare_structs(&mut ast);
+        let result = self.visit(&ast);
+        let _ = self.out.flush();
+        match result {
+            Ok(_) => Ok(()),
+            Err(Unwind::Error(error)) => Err(error),
+            Err(_) => {
+                unreachable!("jumps out of loops and functions are checked by the type checker")
+            }
+        }
+    }
+
+    // Panics at `span`, with the message thor_panic prints
+    pub fn panic(&self, span: Span, message: impl AsRef<str>) -> Unwind {
+        Unwind::Error(Error::Panic(format!(
+            "{} at {}:{}:{}",
+            message.as_ref(),
+            self.filename,
+            span.line,
+            span.column
+        )))
+    }
+
+    fn declare_structs(&mut self, node: &mut Node) {
+        if let NodeKind::Struct(name, fields) = &node.kind {
+            self.structs.insert(name.clone(), fields.clone());
+        }
+        for child in node.children_mut() {
+            self.declare_structs(child);
+        }
+    }
+
+    fn field_index(&self, ty: &Type, field: &str) -> usize {
+        let name = match ty {
+            Type::Struct(name) => name,
+            _ => unreachable!("fields are only accessed on structs"),
+        };
+        self.structs[name]
+            .iter()
+            .position(|(other, _)| other == field)
+            .expect("fields are checked by the type checker")
+    }
+
+    fn push_scope(&mut self, parent: usize, function: bool) {
+        self.frames.push(Frame {
+            parent: Some(parent),
+            function,
+            ..Frame::default()
+        });
+    }
+
+    fn block_scope(&mut self) {
+        self.push_scope(self.frames.len() - 1, false);
+    }
+
+    fn pop_scope(&mut self) {
+        self.frames.pop();
+    }
+
+    // The frame holding the variable, falling back to the builtins once a
+    // function's own frames run out
+    fn find_variable(&self, name: &str) -> Option<usize> {
+        let mut index = self.frames.len() - 1;
+        loop {
+            let frame = &self.frames[index];
+            if frame.variables.contains_key(name) {
+                return Some(index);
+            }
+            match frame.parent {
+                Some(parent) if !frame.function => index = parent,
+                _ => break,
+            }
+        }
+        self.frames[0].variables.contains_key(name).then_some(0)
+    }
+
+    fn find_function(&self, name: &str) -> Option<Rc<Definition>> {
+        let mut index = Some(self.frames.len() - 1);
+        while let Some(current) = index {
+            let frame = &self.frames[current];
+            if let Some(definition) = frame.functions.get(name) {
+                return Some(Rc::clone(definition));
+            }
+            index = frame.parent;
+        }
+        None
+    }
+
+    fn declare_var(&mut self, name: &str, value: Value) {
+        let frame = self.frames.last_mut().unwrap();
+        frame.variables.insert(name.to_string(), value);
+    }
+
+    fn declare_function(
+        &mut self,
+        name: &str,
+        args: &[(String, Type)],
+        return_type: &Option<Type>,
+        body: &Node,
+    ) {
+        let frame = self.frames.len() - 1;
+        let definition = Definition {
+            args: args.to_vec(),
+            return_type: return_type.clone().unwrap_or(Type::Void),
+            body: body.clone(),
+            frame,
+        };
+        self.frames[frame]
+            .functions
+            .insert(name.to_string(), Rc::new(definition));
+    }
+
+    // Declares every function in a block before running it, so functions can
+    // call each other regardless of the order they're defined in
+    fn declare_functions(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            if let NodeKind::Fn(name, args, return_type, body) = &node.kind {
+                self.declare_function(name, args, return_type, body);
+            }
+        }
+    }
+
+    fn call(&mut self, definition: &Definition, args: Vec<Value>) -> Flow<Value> {
+        let depth = self.frames.len();
+        self.push_scope(definition.frame, true);
+        for ((name, _), value) in definition.args.iter().zip(args) {
+            self.declare_var(name, value);
+        }
+        let result = self.visit(&definition.body);
+        self.frames.truncate(depth);
+
+        match result {
+            Ok(_) => match definition.return_type {
+                Type::Void => Ok(Value::Void),
+                _ => unreachable!("functions with a value always return"),
+            },
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(unwind) => Err(unwind),
+        }
+    }
+
+    fn visit_value(&mut self, node: &Node) -> Flow<Value> {
+        match self.visit(node)? {
+            Value::Void => Err(Diagnostic::error("expected a value, found void", node.span).into()),
+            value => Ok(value),
+        }
+    }
+
+    fn condition(&mut self, node: &Node) -> Flow<bool> {
+        match self.visit(node)? {
+            Value::Bool(value) => Ok(value),
+            value => Err(Diagnostic::error(
+                format!("expected bool, found {}", value.ty()),
+                node.span,
+            )
+            .into()),
+        }
+    }
+
+    fn int(&mut self, node: &Node, what: &str) -> Flow<i32> {
+        match self.visit(node)? {
+            Value::Int(value) => Ok(value),
+            value => Err(Diagnostic::error(
+                format!("expected an int {}, found {}", what, value.ty()),
+                node.span,
+            )
+            .into()),
+        }
+    }
+
+    // Checks an index against a length, the way `check_bounds` does
+    fn check_bounds(&self, index: i32, len: usize, span: Span) -> Flow<usize> {
+        // Negative indices wrap around to huge unsigned ones
+        if (index as u32 as usize) < len {
+            Ok(index as usize)
+        } else {
+            Err(self.panic(
+                span,
+                format!("index out of bounds: index {}, len {}", index, len),
+            ))
+        }
+    }
+
+    fn check_slice(&self, start: i32, end: i32, len: usize, span: Span) -> Flow<(usize, usize)> {
+        let (unsigned_start, unsigned_end) = (start as u32, end as u32);
+        if unsigned_start <= unsigned_end && unsigned_end as usize <= len {
+            Ok((start as usize, end as usize))
+        } else {
+            Err(self.panic(
+                span,
+                format!("slice index out of bounds: {}..{}, len {}", start, end, len),
+            ))
+        }
+    }
+
+    // The item at `index` of a string, array or list
+    fn item(&self, value: &Value, index: i32, span: Span) -> Flow<Value> {
+        Ok(match value {
+            Value::Str(bytes) => {
+                let bytes = bytes.borrow();
+                let index = self.check_bounds(index, str_len(&bytes), span)?;
+                Value::Char(bytes[index])
+            }
+            Value::Array(items, _, _) | Value::List(items, _) => {
+                let items = items.borrow();
+                let index = self.check_bounds(index, items.len(), span)?;
+                items[index].clone()
+            }
+            _ => {
+                return Err(Diagnostic::error(
+                    format!("can't index into a value of type {}", value.ty()),
+                    span,
+                )
+                .into())
+            }
+        })
+    }
+
+    fn place(&mut self, node: &Node) -> Flow<Place> {
+        let span = node.span;
+        match &node.kind {
+            NodeKind::Identifier(name) => match self.find_variable(name) {
+                Some(frame) => Ok(Place::Variable(frame, name.clone(), vec![])),
+                None => Err(Diagnostic::error(
+                    format!("use of undeclared variable `{}`", name),
+                    span,
+                )
+                .into()),
+            },
+            NodeKind::Field(inner, field) => {
+                let index = self.field_index(&inner.ty, field);
+                Ok(match self.place(inner)? {
+                    Place::Variable(frame, name, mut path) => {
+                        path.push(index);
+                        Place::Variable(frame, name, path)
+                    }
+                    Place::Item(container, item, mut path) => {
+                        path.push(index);
+                        Place::Item(container, item, path)
+                    }
+                })
+            }
+            NodeKind::Index(inner, index) => {
+                let place = self.place(inner)?;
+                let container = self.load(&place);
+                let index = self.int(index, "index")?;
+                let len = match &container {
+                    Value::Str(bytes) => str_len(&bytes.borrow()),
+                    Value::Array(items, _, _) | Value::List(items, _) => items.borrow().len(),
+                    value => {
+                        return Err(Diagnostic::error(
+                            format!("can't index into a value of type {}", value.ty()),
+                            span,
+                        )
+                        .into())
+                    }
+                };
+                let index = self.check_bounds(index, len, span)?;
+                Ok(Place::Item(container, index, vec![]))
+            }
+            _ => Err(Diagnostic::error("invalid left-hand side of assignment", span).into()),
+        }
+    }
+
+    fn load(&self, place: &Place) -> Value {
+        match place {
+            Place::Variable(frame, name, path) => {
+                field(&self.frames[*frame].variables[name], path).clone()
+            }
+            Place::Item(Value::Str(bytes), index, _) => Value::Char(bytes.borrow()[*index]),
+            Place::Item(Value::Array(items, _, _) | Value::List(items, _), index, path) => {
+                field(&items.borrow()[*index], path).clone()
+            }
+            Place::Item(..) => unreachable!("only strings, arrays and lists have items"),
+        }
+    }
+
+    fn store(&mut self, place: Place, value: Value) {
+        match place {
+            Place::Variable(frame, name, path) => {
+                let target = self.frames[frame].variables.get_mut(&name).unwrap();
+                match (field_mut(target, &path), value) {
+                    // Lists own their items, so assigning copies them
+                    (Value::List(items, _), Value::List(source, _)) => {
+                        let copy = source.borrow().clone();
+                        *items.borrow_mut() = copy;
+                    }
+                    // Everything else, strings and arrays included, is rebound
+                    (target, value) => *target = value,
+                }
+            }
+            Place::Item(Value::Str(bytes), index, _) => {
+                if let Value::Char(value) = value {
+                    bytes.borrow_mut()[index] = value;
+                }
+            }
+            Place::Item(Value::Array(items, _, _) | Value::List(items, _), index, path) => {
+                *field_mut(&mut items.borrow_mut()[index], &path) = value;
+            }
+            Place::Item(..) => unreachable!("only strings, arrays and lists have items"),
+        }
+    }
+
+    // Runs one iteration of a loop body in a scope of its own
+    fn iterate(
+        &mut self,
+        label: &Option<String>,
+        body: &Node,
+        item: Option<(&str, Value)>,
+    ) -> Flow<Step> {
+        self.block_scope();
+        if let Some((name, value)) = item {
+            self.declare_var(name, value);
+        }
+        let result = self.visit(body);
+        self.pop_scope();
+
+        // Jumps without a label are caught by the innermost loop
+        let targets = |target: &Option<String>| target.is_none() || target == label;
+        match result {
+            Ok(_) => Ok(Step::Next),
+            Err(Unwind::Continue(target)) if targets(&target) => Ok(Step::Next),
+            Err(Unwind::Break(target, value)) if targets(&target) => Ok(Step::Exit(value)),
+            Err(unwind) => Err(unwind),
+        }
+    }
+
+    // Runs a branch of an `if` in its own scope
+    fn branch(&mut self, body: &Node) -> Flow<Value> {
+        self.block_scope();
+        let result = self.visit(body);
+        self.pop_scope();
+        result
+    }
+
+    // Whether `value` is in a range, which is never built
+    fn in_range(&mut self, value: &Value, start: &Node, end: &Node, span: Span) -> Flow<bool> {
+        let value = match value {
+            Value::Int(value) => *value,
+            _ => {
+                return Err(Diagnostic::error(
+                    format!(
+                        "unsupported operand types for in: {} and a range",
+                        value.ty()
+                    ),
+                    span,
+                )
+                .into())
+            }
+        };
+        let start = self.int(start, "range bound")?;
+        let end = self.int(end, "range bound")?;
+        Ok(start <= value && value < end)
+    }
+
+    fn compare(&mut self, nodes: &[Node], ops: &[BinaryOp], span: Span) -> Flow<bool> {
+        let mut l_value = self.visit_value(&nodes[0])?;
+        for (op, right) in ops.iter().zip(&nodes[1..]) {
+            let result = match &right.kind {
+                NodeKind::Range(start, end) => {
+                    let found = self.in_range(&l_value, start, end, span)?;
+                    found != (*op == BinaryOp::NotIn)
+                }
+                _ => {
+                    let r_value = self.visit_value(right)?;
+                    let result = self.binary(*op, l_value, r_value.clone(), span)?;
+                    l_value = r_value;
+                    matches!(result, Value::Bool(true))
+                }
+            };
+            // Later operands aren't evaluated once a comparison fails
+            if !result {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn visit(&mut self, node: &Node) -> Flow<Value> {
+        let span = node.span;
+        Ok(match &node.kind {
+            NodeKind::Int(value) => Value::Int(*value as i32),
+            NodeKind::Float(value) => Value::Float(*value),
+            NodeKind::Bool(value) => Value::Bool(*value),
+            NodeKind::Str(value) => Value::str(value.as_bytes()),
+            NodeKind::Char(value) => Value::Char(*value as u8),
+            NodeKind::Array(nodes) => {
+                // Empty arrays get their item type from the type checker
+                let mut ty = match &node.ty {
+                    Type::Array(ty, _) => *ty.clone(),
+                    _ => Type::Int,
+                };
+                let mut values = vec![];
+                for node in nodes {
+                    let value = self.visit_value(node)?;
+                    ty = match value {
+                        Value::Array(..) | Value::List(..) => {
+                            return Err(Diagnostic::error(
+                                format!("arrays can't contain values of type {}", value.ty()),
+                                node.span,
+                            )
+                            .into())
+                        }
+                        _ => value.ty(),
+                    };
+                    values.push(value);
+                }
+                let size = values.len() as u32;
+                Value::Array(Rc::new(values.into()), ty, size)
+            }
+            NodeKind::Cast(ty, node) => {
+                let value = self.visit(node)?;
+                match (ty, &value) {
+                    (Type::Int, Value::Int(value)) => Value::Int(*value),
+                    (Type::Int, Value::Bool(value)) => Value::Int(*value as i32),
+                    (Type::Int, Value::Float(value)) => Value::Int(*value as i32),
+                    (Type::Float, Value::Int(value)) => Value::Float(*value as f64),
+                    (Type::Float, Value::Float(value)) => Value::Float(*value),
+                    (Type::Float, Value::Bool(value)) => Value::Float(*value as u8 as f64),
+                    (Type::Bool, Value::Int(value)) => Value::Bool(*value != 0),
+                    (Type::Bool, Value::Bool(value)) => Value::Bool(*value),
+                    (Type::Bool, Value::Float(value)) => Value::Bool(*value != 0.0),
+                    (Type::Str, Value::Str(_)) | (Type::Char, Value::Char(_)) => value,
+                    (Type::List(item), Value::Array(items, _, _) | Value::List(items, _)) => {
+                        Value::list(items.borrow().clone(), *item.clone())
+                    }
+                    _ => {
+                        return Err(Diagnostic::error(
+                            format!("can't cast {} to {}", value.ty(), ty),
+                            span,
+                        )
+                        .into())
+                    }
+                }
+            }
+            NodeKind::Identifier(name) => match self.find_variable(name) {
+                Some(frame) => self.frames[frame].variables[name].clone(),
+                None => {
+                    return Err(Diagnostic::error(
+                        format!("use of undeclared variable `{}`", name),
+                        span,
+                    )
+                    .into())
+                }
+            },
+            NodeKind::Unary(op, node) => {
+                let value = self.visit(node)?;
+                match (op, value) {
+                    (UnaryOp::Pos, value) => value,
+                    (UnaryOp::Neg, Value::Int(value)) => Value::Int(self.int_neg(value, span)?),
+                    (UnaryOp::Neg, Value::Float(value)) => Value::Float(-value),
+                    (UnaryOp::Not, Value::Int(value)) => Value::Bool(value == 0),
+                    (UnaryOp::Not, Value::Float(value)) => Value::Bool(value == 0.0),
+                    (UnaryOp::Not, Value::Bool(value)) => Value::Bool(!value),
+                    (UnaryOp::BitNot, Value::Int(value)) => Value::Int(!value),
+                    (UnaryOp::BitNot, Value::Char(value)) => Value::Char(!value),
+                    (op, value) => {
+                        return Err(Diagnostic::error(
+                            format!("can't apply unary {} to {}", op, value.ty()),
+                            span,
+                        )
+                        .into())
+                    }
+                }
+            }
+            // The right side only runs when the left doesn't decide the result
+            NodeKind::Binary(left, op @ (BinaryOp::And | BinaryOp::Or), right) => {
+                let l_value = self.condition(left)?;
+                if l_value == (*op == BinaryOp::Or) {
+                    Value::Bool(l_value)
+                } else {
+                    Value::Bool(self.condition(right)?)
+                }
+            }
+            NodeKind::Binary(left, op @ (BinaryOp::In | BinaryOp::NotIn), right)
+                if matches!(right.kind, NodeKind::Range(..)) =>
+            {
+                let value = self.visit_value(left)?;
+                let found = match &right.kind {
+                    NodeKind::Range(start, end) => self.in_range(&value, start, end, span)?,
+                    _ => unreachable!(),
+                };
+                Value::Bool(found != (*op == BinaryOp::NotIn))
+            }
+            NodeKind::Binary(left, op, right) => {
+                let l_value = self.visit(left)?;
+                let r_value = self.visit(right)?;
+                self.binary(*op, l_value, r_value, span)?
+            }
+            NodeKind::Compare(nodes, ops) => Value::Bool(self.compare(nodes, ops, span)?),
+            NodeKind::Let(name, _, node) => {
+                let value = self.visit_value(node)?;
+                self.declare_var(name, value.clone());
+                value
+            }
+            NodeKind::IdentifierOp(target, op, node) => {
+                let place = self.place(target)?;
+                let value = match op {
+                    IdentifierOp::Eq => self.visit_value(node)?,
+                    // The target is only evaluated once, so `xs[i()] += 1` calls `i` once
+                    op => {
+                        let l_value = self.load(&place);
+                        let r_value = self.visit_value(node)?;
+                        self.binary(binary_op(*op), l_value, r_value, span)?
+                    }
+                };
+                self.store(place, value.clone());
+                value
+            }
+            NodeKind::Index(node, index) => {
+                let value = self.visit(node)?;
+                if let NodeKind::Range(start, end) = &index.kind {
+                    let start = self.int(start, "range bound")?;
+                    let end = self.int(end, "range bound")?;
+                    return match value {
+                        Value::Array(items, item, _) | Value::List(items, item) => {
+                            let items = items.borrow();
+                            let (start, end) = self.check_slice(start, end, items.len(), span)?;
+                            Ok(Value::list(items[start..end].to_vec(), item))
+                        }
+                        _ => Err(Diagnostic::error(
+                            format!("can't slice a value of type {}", value.ty()),
+                            node.span,
+                        )
+                        .into()),
+                    };
+                }
+                let index = self.int(index, "index")?;
+                self.item(&value, index, span)?
+            }
+            NodeKind::While(label, condition, body, else_case) => {
+                let value = loop {
+                    if !self.condition(condition)? {
+                        // The else is the loop's value when its condition fails
+                        break match else_case {
+                            Some(else_case) => self.branch(else_case)?,
+                            None => Value::Int(0),
+                        };
+                    }
+                    if let Step::Exit(value) = self.iterate(label, body, None)? {
+                        break value.unwrap_or(Value::Int(0));
+                    }
+                };
+                match (&node.ty, value) {
+                    (_, Value::Void) | (Type::Void, _) => Value::Int(0),
+                    (_, value) => value.copy_list(),
+                }
+            }
+            NodeKind::If(condition, body, else_case) => {
+                let condition = self.condition(condition)?;
+                let value = match (condition, else_case) {
+                    (true, _) => self.branch(body)?,
+                    (false, Some(else_case)) => self.branch(else_case)?,
+                    (false, None) => return Ok(Value::Int(0)),
+                };
+                match (&node.ty, value) {
+                    // Only an if with an else and branches of one type has a value
+                    (_, Value::Void) | (Type::Void, _) => Value::Int(0),
+                    // A list from an outer scope is copied, since it already has an owner
+                    (_, value @ Value::List(..)) => value.copy_list(),
+                    (_, value) => value,
+                }
+            }
+            NodeKind::Fn(name, args, return_type, body) => {
+                let frame = self.frames.last().unwrap();
+                if !frame.functions.contains_key(name) {
+                    self.declare_function(name, args, return_type, body);
+                }
+                Value::Int(0)
+            }
+            NodeKind::Return(node) => {
+                // The caller gets its own copy of a list or array, like compiled code
+                let value = match self.visit_value(node)? {
+                    Value::Array(items, item, size) => {
+                        Value::Array(Rc::new(items.borrow().clone().into()), item, size)
+                    }
+                    value => value.copy_list(),
+                };
+                return Err(Unwind::Return(value));
+            }
+            NodeKind::Call(name, args) => {
+                let mut values = vec![];
+                for arg in args {
+                    values.push(self.visit_value(arg)?);
+                }
+                match self.find_function(name) {
+                    Some(definition) => self.call(&definition, values)?,
+                    None => self.builtin(name, values, span)?,
+                }
+            }
+            NodeKind::Statements(nodes) => {
+                self.declare_functions(nodes);
+                let mut rtn_value = Value::Int(0);
+                for node in nodes {
+                    rtn_value = self.visit(node)?;
+                }
+                rtn_value
+            }
+            NodeKind::Struct(..) => Value::Int(0),
+            NodeKind::Impl(..) | NodeKind::MethodCall(..) => {
+                unreachable!("the type checker turns methods into functions")
+            }
+            NodeKind::StructLiteral(name, fields) => {
+                let ty = Type::Struct(name.clone());
+                let mut values = vec![Value::Void; fields.len()];
+                for (field, node) in fields {
+                    let index = self.field_index(&ty, field);
+                    values[index] = self.visit_value(node)?;
+                }
+                Value::Struct(values, name.clone())
+            }
+            NodeKind::Field(node, field) => match self.visit_value(node)? {
+                Value::Struct(mut values, name) => {
+                    let index = self.field_index(&Type::Struct(name), field);
+                    values.swap_remove(index)
+                }
+                value => {
+                    return Err(Diagnostic::error(
+                        format!(
+                            "can't access field `{}` on a value of type {}",
+                            field,
+                            value.ty()
+                        ),
+                        node.span,
+                    )
+                    .into())
+                }
+            },
+            NodeKind::Error => Value::Int(0),
+            NodeKind::Type(ty) => {
+                return Err(
+                    Diagnostic::error(format!("expected a value, found type {}", ty), span).into(),
+                )
+            }
+            NodeKind::Loop(label, body) => loop {
+                if let Step::Exit(value) = self.iterate(label, body, None)? {
+                    // A `loop`'s value is whichever `break` left it
+                    break match value {
+                        Some(value) => value.copy_list(),
+                        None => Value::Int(0),
+                    };
+                }
+            },
+            NodeKind::Break(label, value) => {
+                let value = match value {
+                    Some(value) => Some(self.visit_value(value)?),
+                    None => None,
+                };
+                return Err(Unwind::Break(label.clone(), value));
+            }
+            NodeKind::Continue(label) => return Err(Unwind::Continue(label.clone())),
+            NodeKind::For(label, identifier, iterable, body) => {
+                self.for_loop(label, identifier, iterable, body)?;
+                Value::Int(0)
+            }
+            NodeKind::Range(..) => {
+                return Err(Diagnostic::error(
+                    "ranges can only be used to slice or as the iterable of a for loop",
+                    span,
+                )
+                .into())
+            }
+        })
+    }
+
+    // Ranges stop at their end, arrays at their size, and strings at the nul byte.
+    // Lists can grow in the loop, so their length is checked every iteration
+    fn for_loop(
+        &mut self,
+        label: &Option<String>,
+        identifier: &str,
+        iterable: &Node,
+        body: &Node,
+    ) -> Flow<()> {
+        let (mut index, end, items) = match &iterable.kind {
+            NodeKind::Range(start, end) => {
+                let start = self.int(start, "range bound")?;
+                (start, Some(self.int(end, "range bound")?), None)
+            }
+            _ => match self.visit(iterable)? {
+                Value::Array(items, item, size) => {
+                    (0, Some(size as i32), Some(Value::Array(items, item, size)))
+                }
+                value @ (Value::List(..) | Value::Str(_)) => (0, None, Some(value)),
+                value => {
+                    return Err(Diagnostic::error(
+                        format!("can't iterate over a value of type {}", value.ty()),
+                        iterable.span,
+                    )
+                    .with_note("only arrays, lists, strings and ranges can be iterated over")
+                    .into())
+                }
+            },
+        };
+
+        loop {
+            let item = match (&items, end) {
+                (None, Some(end)) if index < end => Value::Int(index),
+                (Some(items), Some(end)) if index < end => {
+                    self.item(items, index, iterable.span)?
+                }
+                (Some(Value::List(list, _)), None) if (index as usize) < list.borrow().len() => {
+                    list.borrow()[index as usize].clone()
+                }
+                (Some(Value::Str(bytes)), None) => match bytes.borrow().get(index as usize) {
+                    Some(byte) if *byte != 0 => Value::Char(*byte),
+                    _ => break,
+                },
+                _ => break,
+            };
+            if let Step::Exit(_) = self.iterate(label, body, Some((identifier, item)))? {
+                break;
+            }
+            index += 1;
+        }
+        Ok(())
+    }
+}
+
+// The field of a struct that `path` leads to, or the value itself for an empty path
+fn field<'v>(mut value: &'v Value, path: &[usize]) -> &'v Value {
+    for index in path {
+        value = match value {
+            Value::Struct(fields, _) => &fields[*index],
+            _ => unreachable!("fields are only accessed on structs"),
+        };
+    }
+    value
+}
+
+fn field_mut<'v>(mut value: &'v mut Value, path: &[usize]) -> &'v mut Value {
+    for index in path {
+        value = match value {
+            Value::Struct(fields, _) => &mut fields[*index],
+            _ => unreachable!("fields are only accessed on structs"),
+        };
+    }
+    value
+}
+
+fn binary_op(op: IdentifierOp) -> BinaryOp {
+    match op {
+        IdentifierOp::Add => BinaryOp::Add,
+        IdentifierOp::Sub => BinaryOp::Sub,
+        IdentifierOp::Mul => BinaryOp::Mul,
+        IdentifierOp::Div => BinaryOp::Div,
+        IdentifierOp::Rem => BinaryOp::Rem,
+        IdentifierOp::Pow => BinaryOp::Pow,
+        IdentifierOp::BitAnd => BinaryOp::BitAnd,
+        IdentifierOp::BitOr => BinaryOp::BitOr,
+        IdentifierOp::Shl => BinaryOp::Shl,
+        IdentifierOp::Shr => BinaryOp::Shr,
+        IdentifierOp::Eq => unreachable!("= isn't a binary operator"),
+    }
+}
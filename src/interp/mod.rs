@@ -0,0 +1,18 @@
+mod builtins;
+mod interpreter;
+#[cfg(test)]
+mod tests;
+mod value;
+
+pub use interpreter::Interpreter;
+
+use crate::Diagnostic;
+
+// Why a program stopped early
+pub enum Error {
+    // A runtime panic's message, which compiled code prints before exiting with code 101
+    Panic(String),
+    // Code the compiler would reject too. Boxed, since every result the
+    // interpreter passes up could hold one
+    Diagnostic(Box<Diagnostic>),
+}
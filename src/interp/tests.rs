@@ -0,0 +1,167 @@
+use crate::{
+    interp::{Error, Interpreter},
+    Lexer, Parser, TypeChecker,
+};
+
+// Runs `source`, returning what it printed and the panic message it stopped
+// with, if any
+fn run(source: &str, overflow_checks: bool) -> (String, Option<String>) {
+    let tokens = Lexer::new(source.to_string()).lex().0;
+    let (ast, errors) = Parser::new(tokens).parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    let (ast, errors) = TypeChecker::new().check(ast);
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let mut out = vec![];
+    let mut interpreter = Interpreter::new("test.thor", &mut out);
+    interpreter.overflow_checks = overflow_checks;
+    let panic = match interpreter.run(ast) {
+        Ok(()) => None,
+        Err(Error::Panic(message)) => Some(message),
+        Err(Error::Diagnostic(diagnostic)) => panic!("{:?}", diagnostic),
+    };
+    (String::from_utf8(out).unwrap(), panic)
+}
+
+fn output(source: &str) -> String {
+    let (output, panic) = run(source, false);
+    assert_eq!(panic, None);
+    output
+}
+
+#[test]
+fn print_formats() {
+    assert_eq!(
+        output(
+            "struct Point { x: int, y: float }
+print(1, 2.5, true, \"hi\", 'c')
+print(Point { y: 1, x: -3 })
+print(sqrt(16.0), abs(-4), floor(PI))"
+        ),
+        "1 2.500000 1 hi c \nPoint { x: -3, y: 1.000000 } \n4.000000 4 3.000000 \n"
+    );
+}
+
+#[test]
+fn arithmetic() {
+    assert_eq!(
+        output(
+            "print(7 / 2, -7 % 3, 2 ^ 10, 1 + 0.5, 2.0 ^ 0.5 > 1.41)
+print(2147483647 + 1, wrapping_add(2147483647, 2))
+print(-8 >> 1, 1 << 4, 6 & 3, 6 | 3, ~0)
+print(1 < 2 <= 2, 3 in 0..3, 2 not in [1, 2])"
+        ),
+        "3 -1 1024 1.500000 1 \n-2147483648 -2147483647 \n-4 16 2 7 -1 \n1 0 0 \n"
+    );
+}
+
+#[test]
+fn control_flow() {
+    assert_eq!(
+        output(
+            "let total = 0
+'outer: for i in 0..10 {
+  for j in 0..10 {
+    if j == i: continue 'outer
+    if i == 6: break 'outer
+    total += j
+  }
+}
+let n = 0
+let found = loop {
+  n += 1
+  if n * n > 50: break n
+}
+while n > 0: n -= 3
+for c in \"ab\": print(c)
+print(total, found, n)"
+        ),
+        "a \nb \n20 8 -1 \n"
+    );
+}
+
+#[test]
+fn functions() {
+    assert_eq!(
+        output(
+            "print(fib(20), half(5))
+fn fib(n: int): int {
+  if n < 2: return n
+  return fib(n - 1) + fib(n - 2)
+}
+fn half(n: int): float {
+  return n / 2.0
+}"
+        ),
+        "6765 2.500000 \n"
+    );
+}
+
+#[test]
+fn lists_are_shared_but_copied_on_assignment() {
+    assert_eq!(
+        output(
+            "fn fill(xs: int[], n: int) {
+  for i in 0..n: push(xs, i)
+}
+let xs: int[] = []
+fill(xs, 4)
+let alias = xs
+let copy: int[] = [0]
+copy = xs
+push(alias, pop(copy))
+let array = [1, 2, 3]
+array[0] = 9
+print(len(xs), xs[4], len(copy), array[0], len(xs[1..3]))"
+        ),
+        "5 3 3 9 2 \n"
+    );
+}
+
+#[test]
+fn panics() {
+    let cases = [
+        (
+            "let xs = [1, 2]\nprint(xs[2])",
+            "index out of bounds: index 2, len 2 at test.thor:2:7",
+        ),
+        (
+            "let zero = 0\nprint(1 / zero)",
+            "attempt to divide by zero at test.thor:2:7",
+        ),
+        (
+            "let xs: int[] = []\npop(xs)",
+            "pop from an empty list at test.thor:2:1",
+        ),
+        (
+            "print(2 ^ -1)",
+            "attempt to raise an int to a negative power at test.thor:1:7",
+        ),
+        (
+            "print(checked_add(2147483647, 1))",
+            "attempt to add with overflow at test.thor:1:7",
+        ),
+    ];
+    for (source, message) in cases {
+        let (_, panic) = run(source, false);
+        assert_eq!(panic.as_deref(), Some(message), "{}", source);
+    }
+
+    let (output, panic) = run("print(1)\nlet x = 2147483647\nprint(x * 2)", true);
+    assert_eq!(output, "1 \n");
+    assert_eq!(
+        panic.as_deref(),
+        Some("attempt to multiply with overflow at test.thor:3:7")
+    );
+    let (output, panic) = run("print((-2) ^ 31)\nprint(2 ^ 31)", true);
+    assert_eq!(output, "-2147483648 \n");
+    assert_eq!(
+        panic.as_deref(),
+        Some("attempt to multiply with overflow at test.thor:2:7")
+    );
+    let (_, panic) = run("let n = -1\nprint(8 >> n)", true);
+    assert_eq!(
+        panic.as_deref(),
+        Some("attempt to shift right with overflow at test.thor:2:7")
+    );
+}
@@ -0,0 +1,97 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::Type;
+
+// Strings, arrays and lists are shared like the pointers the compiler passes
+// around, so a change through one name shows through every other
+pub type Items = Rc<RefCell<Vec<Value>>>;
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Int(i32),
+    Float(f64),
+    Bool(bool),
+    // The bytes of the string, without the nul byte a compiled one ends with
+    Str(Rc<RefCell<Vec<u8>>>),
+    Char(u8),
+    Array(Items, Type, u32),
+    List(Items, Type),
+    // The fields in the order the struct declares them
+    Struct(Vec<Value>, String),
+    Void,
+}
+
+impl Value {
+    pub fn str(bytes: &[u8]) -> Self {
+        Value::Str(Rc::new(RefCell::new(bytes.to_vec())))
+    }
+
+    pub fn list(items: Vec<Value>, item: Type) -> Self {
+        Value::List(Rc::new(RefCell::new(items)), item)
+    }
+
+    pub fn ty(&self) -> Type {
+        use Value::*;
+        match self {
+            Int(_) => Type::Int,
+            Float(_) => Type::Float,
+            Bool(_) => Type::Bool,
+            Str(_) => Type::Str,
+            Char(_) => Type::Char,
+            Array(_, ty, size) => Type::Array(Box::new(ty.clone()), *size),
+            List(_, ty) => Type::List(Box::new(ty.clone())),
+            Struct(_, name) => Type::Struct(name.clone()),
+            Void => Type::Void,
+        }
+    }
+
+    // A copy of a list with items of its own, like the compiler makes when a
+    // list is returned, assigned or cast
+    pub fn copy_list(&self) -> Self {
+        match self {
+            Value::List(items, item) => Value::list(items.borrow().clone(), item.clone()),
+            value => value.clone(),
+        }
+    }
+
+    // Ints and chars as the signed integers the compiler compares them as
+    pub fn integer(&self) -> Option<i32> {
+        match self {
+            Value::Int(value) => Some(*value),
+            Value::Char(value) => Some(*value as i8 as i32),
+            _ => None,
+        }
+    }
+
+    // Ints widened to floats, for arithmetic mixing the two
+    pub fn float(&self) -> Option<f64> {
+        match self {
+            Value::Int(value) => Some(*value as f64),
+            Value::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+// A string's length is wherever its first nul byte is, like `strlen`
+pub fn str_len(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(bytes.len())
+}
+
+// printf's `%f`, which spells out infinities and NaNs in lowercase
+pub fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        String::from(if value.is_sign_negative() {
+            "-nan"
+        } else {
+            "nan"
+        })
+    } else if value.is_infinite() {
+        String::from(if value < 0.0 { "-inf" } else { "inf" })
+    } else {
+        format!("{:.6}", value)
+    }
+}
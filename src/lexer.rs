@@ -1,49 +1,85 @@
-use crate::{Token, TypeLiteral};
+use crate::{Diagnostic, Span, Token, TypeLiteral};
 use Token::*;
 
+#[derive(Debug, Copy, Clone)]
+struct Position {
+    index: usize,
+    line: usize,
+    column: usize,
+}
+
 pub struct Lexer {
     text: String,
     index: usize,
+    line: usize,
+    column: usize,
     current_char: char,
+    errors: Vec<Diagnostic>,
 }
 
 impl Lexer {
     pub fn new(text: String) -> Self {
         Self {
             index: 0,
-            current_char: text.chars().nth(0).unwrap(),
+            line: 1,
+            column: 1,
+            current_char: text.chars().next().unwrap_or('\0'),
             text,
+            errors: vec![],
         }
     }
 
     fn advance(&mut self) -> Token {
+        if self.current_char == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
         self.index += 1;
         let next = self.text.chars().nth(self.index);
-        self.current_char = match next {
-            Some(c) => c,
-            _ => '\0',
-        };
+        self.current_char = next.unwrap_or('\0');
         EOF
     }
 
-    pub fn lex(&mut self) -> Vec<Token> {
-        let mut tokens: Vec<Token> = vec![];
+    fn peek(&self) -> char {
+        self.text.chars().nth(self.index + 1).unwrap_or('\0')
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            index: self.index,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn span_from(&self, start: Position) -> Span {
+        Span::new(start.index, self.index, start.line, start.column)
+    }
+
+    // Lexes the whole text, carrying on past errors so the parser can report
+    // the rest of the file's too
+    pub fn lex(&mut self) -> (Vec<(Token, Span)>, Vec<Diagnostic>) {
+        let mut tokens: Vec<(Token, Span)> = vec![];
         let mut token = self.next_token();
-        while token != EOF {
+        while token.0 != EOF {
             tokens.push(token);
             token = self.next_token();
         }
         tokens.push(token);
-        tokens
+        (tokens, std::mem::take(&mut self.errors))
     }
 
-    pub fn next_token(&mut self) -> Token {
+    pub fn next_token(&mut self) -> (Token, Span) {
         while self.current_char != '\0' {
+            let start = self.position();
             let token = match self.current_char {
                 ' ' | '\t' | '\r' => self.advance(),
-                '0'..='9' => self.number(),
-                '"' => self.string(),
-                '\'' => self.char(),
+                '0'..='9' => self.literal(start, Self::number),
+                '"' => self.literal(start, Self::string),
+                '\'' if self.is_label() => self.label(),
+                '\'' => self.literal(start, Self::char),
                 'a'..='z' | 'A'..='Z' | '_' | 'Α'..='ω' | '∞' => self.word(),
                 '=' => {
                     self.advance();
@@ -105,6 +141,40 @@ impl Lexer {
                         _ => Rem,
                     }
                 }
+                '^' => {
+                    self.advance();
+                    match self.current_char {
+                        '=' => {
+                            self.advance();
+                            CaretEq
+                        }
+                        _ => Caret,
+                    }
+                }
+                '&' => {
+                    self.advance();
+                    match self.current_char {
+                        '=' => {
+                            self.advance();
+                            AmpEq
+                        }
+                        _ => Amp,
+                    }
+                }
+                '|' => {
+                    self.advance();
+                    match self.current_char {
+                        '=' => {
+                            self.advance();
+                            PipeEq
+                        }
+                        _ => Pipe,
+                    }
+                }
+                '~' => {
+                    self.advance();
+                    Tilde
+                }
                 '<' => {
                     self.advance();
                     match self.current_char {
@@ -112,6 +182,16 @@ impl Lexer {
                             self.advance();
                             Lte
                         }
+                        '<' => {
+                            self.advance();
+                            match self.current_char {
+                                '=' => {
+                                    self.advance();
+                                    ShlEq
+                                }
+                                _ => Shl,
+                            }
+                        }
                         _ => Lt,
                     }
                 }
@@ -122,6 +202,16 @@ impl Lexer {
                             self.advance();
                             Gte
                         }
+                        '>' => {
+                            self.advance();
+                            match self.current_char {
+                                '=' => {
+                                    self.advance();
+                                    ShrEq
+                                }
+                                _ => Shr,
+                            }
+                        }
                         _ => Gt,
                     }
                 }
@@ -157,26 +247,62 @@ impl Lexer {
                     self.advance();
                     Colon
                 }
+                '.' if self.peek() == '.' => {
+                    self.advance();
+                    self.advance();
+                    DotDot
+                }
+                '.' => {
+                    self.advance();
+                    Dot
+                }
                 '\n' | ';' => {
                     self.advance();
                     Newline
                 }
                 '\0' => EOF,
-                _ => panic!("Illegal character: '{}'", self.current_char),
+                // Skipped, since the code around it may still make sense
+                c => {
+                    self.advance();
+                    self.errors.push(Diagnostic::error(
+                        format!("illegal character '{}'", c),
+                        self.span_from(start),
+                    ));
+                    continue;
+                }
             };
             if token != EOF {
-                return token;
+                return (token, self.span_from(start));
+            }
+        }
+        (EOF, self.span_from(self.position()))
+    }
+
+    // A malformed literal is reported and left in place as an error token
+    fn literal(
+        &mut self,
+        start: Position,
+        lex: fn(&mut Self, Position) -> Result<Token, Diagnostic>,
+    ) -> Token {
+        match lex(self, start) {
+            Ok(token) => token,
+            Err(error) => {
+                self.errors.push(error);
+                Error
             }
         }
-        EOF
     }
 
-    fn number(&mut self) -> Token {
+    fn number(&mut self, start: Position) -> Result<Token, Diagnostic> {
         let mut num_str: String = self.current_char.to_string();
         let mut decimals = 0;
         self.advance();
 
         while "0123456789.".contains(self.current_char) {
+            // `1..5` is a range, not a malformed float
+            if self.current_char == '.' && self.peek() == '.' {
+                break;
+            }
             if self.current_char == '.' {
                 decimals += 1;
             }
@@ -184,20 +310,40 @@ impl Lexer {
             self.advance();
         }
 
+        if decimals > 1 {
+            return Err(Diagnostic::error(
+                format!("invalid float literal '{}'", num_str),
+                self.span_from(start),
+            )
+            .with_note("a float literal can only contain one '.'"));
+        }
+
         if decimals > 0 {
-            Float(num_str.parse::<f64>().unwrap())
+            Ok(Float(num_str.parse::<f64>().unwrap()))
         } else {
-            Int(num_str.parse::<u32>().unwrap())
+            match num_str.parse::<u32>() {
+                Ok(value) => Ok(Int(value)),
+                Err(_) => Err(Diagnostic::error(
+                    format!("integer literal '{}' is too large", num_str),
+                    self.span_from(start),
+                )),
+            }
         }
     }
 
-    fn string(&mut self) -> Token {
+    fn string(&mut self, start: Position) -> Result<Token, Diagnostic> {
         self.advance();
         let mut string = String::new();
         let mut escape = false;
 
-        while self.current_char != '"' {
-            if self.current_char == '\\' {
+        while escape || self.current_char != '"' {
+            if self.current_char == '\0' {
+                return Err(Diagnostic::error(
+                    "unterminated string literal",
+                    self.span_from(start),
+                ));
+            }
+            if !escape && self.current_char == '\\' {
                 escape = true;
                 self.advance();
                 continue;
@@ -218,10 +364,10 @@ impl Lexer {
         }
         self.advance();
 
-        Str(string)
+        Ok(Str(string))
     }
 
-    fn char(&mut self) -> Token {
+    fn char(&mut self, start: Position) -> Result<Token, Diagnostic> {
         self.advance();
 
         let ch = match self.current_char {
@@ -240,11 +386,42 @@ impl Lexer {
         self.advance();
 
         if self.current_char != '\'' {
-            panic!("char is too long");
+            while !matches!(self.current_char, '\'' | '\n' | '\0') {
+                self.advance();
+            }
+            if self.current_char == '\'' {
+                self.advance();
+            }
+            return Err(Diagnostic::error(
+                "character literal may only contain one character",
+                self.span_from(start),
+            )
+            .with_note("use double quotes for a string literal"));
         }
         self.advance();
 
-        Char(ch)
+        Ok(Char(ch))
+    }
+
+    // `'outer` is a label and `'c'` is a character, told apart by the closing quote
+    fn is_label(&self) -> bool {
+        let mut chars = self.text.chars().skip(self.index + 1);
+        match chars.next() {
+            Some('a'..='z' | 'A'..='Z' | '_') => {}
+            _ => return false,
+        }
+        let end = chars.find(|c| !matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '_'));
+        end != Some('\'')
+    }
+
+    fn label(&mut self) -> Token {
+        self.advance();
+        let mut name = String::new();
+        while matches!(self.current_char, 'a'..='z' | 'A'..='Z' | '0'..='9' | '_') {
+            name.push(self.current_char);
+            self.advance();
+        }
+        Label(name)
     }
 
     fn word(&mut self) -> Token {
@@ -277,8 +454,14 @@ impl Lexer {
             "else" => Else,
             "while" => While,
             "for" => For,
+            "loop" => Loop,
+            "break" => Break,
+            "continue" => Continue,
             "in" => In,
+            "is" => Is,
             "fn" => Fn,
+            "struct" => Struct,
+            "impl" => Impl,
             "return" => Return,
             _ => Identifier(word),
         }
@@ -1,115 +1,366 @@
 use std::fs;
+use std::io::{self, Write};
+#[cfg(feature = "llvm")]
 use std::path::Path;
-use std::process::Command;
+use std::process;
+#[cfg(feature = "llvm")]
+use std::process::Command as Process;
 use std::time::Instant;
 
+#[cfg(feature = "llvm")]
 use inkwell::{
     context::Context,
-    targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine},
-    OptimizationLevel,
+    module::Module,
+    passes::{PassManager, PassManagerBuilder},
+    targets::{
+        CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple,
+    },
 };
 
+mod cli;
+#[cfg(feature = "llvm")]
 mod compiler;
+mod diagnostic;
+mod interp;
 mod lexer;
 mod node;
 mod parser;
+#[cfg(feature = "llvm")]
+mod repl;
 mod token;
+mod typeck;
 
-pub use compiler::{Codegen, Function};
+#[cfg(feature = "llvm")]
+use cli::OptLevel;
+use cli::{Cli, Command, Emit, Invocation, Options};
+#[cfg(feature = "llvm")]
+pub use compiler::{run_jit, Codegen, Function};
+pub use diagnostic::{Diagnostic, Span};
+use interp::Interpreter;
 pub use lexer::Lexer;
-pub use node::{BinaryOp, IdentifierOp, Node, Type, UnaryOp};
+pub use node::{BinaryOp, IdentifierOp, Node, NodeKind, Type, UnaryOp};
 pub use parser::Parser;
 pub use token::{Token, TypeLiteral};
+pub use typeck::TypeChecker;
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-
-    match args.len() {
-        1 => panic!("Too few many arguments passed"),
-        2 => compile_file(&args[1], "output", false),
-        3 => match args[2].as_str() {
-            "--log" => compile_file(&args[1], "output", true),
-            out_filename => compile_file(&args[1], out_filename, false),
-        },
-        4 => match args[3].as_str() {
-            "--log" => compile_file(&args[1], &args[2], true),
-            _ => panic!("Too many arguments passed"),
-        },
-        _ => panic!("Too many arguments passed"),
+    let cli = match cli::parse(std::env::args().skip(1)) {
+        Ok(Invocation::Compile(cli)) => cli,
+        Ok(Invocation::Repl(options)) => {
+            if let Err(message) = start_repl(&options) {
+                eprintln!("error: {}", message);
+                process::exit(1);
+            }
+            return;
+        }
+        Ok(Invocation::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Ok(Invocation::Version) => {
+            println!("thor {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(message) => {
+            eprintln!("error: {}", message);
+            eprintln!("note: run `thor --help` for usage");
+            process::exit(2);
+        }
     };
+
+    let text = match fs::read_to_string(&cli.path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("error: couldn't read {}: {}", cli.path, err);
+            process::exit(1);
+        }
+    };
+    match execute(&cli, &text) {
+        Ok(code) => process::exit(code),
+        Err(Failure::Diagnostics(diagnostics)) => {
+            for diagnostic in &diagnostics {
+                eprintln!("{}\n", diagnostic.render(&text));
+            }
+            eprintln!(
+                "error: could not compile {} due to {} previous error{}",
+                cli.path,
+                diagnostics.len(),
+                if diagnostics.len() == 1 { "" } else { "s" }
+            );
+            process::exit(1);
+        }
+        Err(Failure::Other(message)) => {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        }
+    }
+}
+
+// Why a command couldn't finish
+enum Failure {
+    Diagnostics(Vec<Diagnostic>),
+    Other(String),
 }
 
-fn compile_file(path: &str, out_filename: &str, log: bool) {
-    let text = fs::read_to_string(path).unwrap();
-    compile(text, path, out_filename, log);
+impl Failure {
+    // Every diagnostic of a run comes from the one file being run
+    fn diagnostics(cli: &Cli, diagnostics: Vec<Diagnostic>) -> Self {
+        Self::Diagnostics(
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| diagnostic.in_file(&cli.path))
+                .collect(),
+        )
+    }
 }
 
-fn compile(text: String, filename: &str, out_filename: &str, log: bool) {
+// Runs the command, returning the exit code
+fn execute(cli: &Cli, text: &str) -> Result<i32, Failure> {
     let begin = Instant::now();
 
-    let mut lexer = Lexer::new(text);
-    let tokens = lexer.lex();
-    if log {
-        println!("tokens:");
-        for token in &tokens {
-            print!(" {}", token);
+    let mut lexer = Lexer::new(text.to_string());
+    let (tokens, mut errors) = lexer.lex();
+    if cli.command == Command::Emit(Emit::Tokens) {
+        if !errors.is_empty() {
+            return Err(Failure::diagnostics(cli, errors));
         }
-        print!("\n");
+        let tokens: Vec<String> = tokens.iter().map(|(token, _)| token.to_string()).collect();
+        write_output(cli, format!("{}\n", tokens.join(" ")).as_bytes())?;
+        return Ok(0);
     }
 
     let mut parser = Parser::new(tokens);
-    let ast = parser.parse();
-    if log {
-        println!("ast: {}", ast);
+    let (ast, parse_errors) = parser.parse();
+    errors.extend(parse_errors);
+    if !errors.is_empty() {
+        errors.sort_by_key(|error| error.span.start);
+        return Err(Failure::diagnostics(cli, errors));
+    }
+    if cli.command == Command::Emit(Emit::Ast) {
+        write_output(cli, format!("{}\n", ast).as_bytes())?;
+        return Ok(0);
+    }
+
+    let mut checker = TypeChecker::new();
+    let (ast, errors) = checker.check(ast);
+    if !errors.is_empty() {
+        return Err(Failure::diagnostics(cli, errors));
+    }
+    if cli.command == Command::Check {
+        return Ok(0);
+    }
+    if cli.command == Command::Run && (cli.interpret || cfg!(not(feature = "llvm"))) {
+        return interpret(cli, ast);
+    }
+    compile(cli, ast, begin)
+}
+
+// Runs the program by walking its syntax tree, which needs neither LLVM nor a linker
+fn interpret(cli: &Cli, ast: Node) -> Result<i32, Failure> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut interpreter = Interpreter::new(&cli.path, &mut out);
+    interpreter.overflow_checks = cli.options.overflow_checks;
+    match interpreter.run(ast) {
+        Ok(()) => Ok(0),
+        // Compiled programs exit with the same code when they panic
+        Err(interp::Error::Panic(message)) => {
+            eprintln!("{}", message);
+            Ok(101)
+        }
+        Err(interp::Error::Diagnostic(diagnostic)) => {
+            Err(Failure::diagnostics(cli, vec![*diagnostic]))
+        }
     }
+}
+
+#[cfg(not(feature = "llvm"))]
+fn compile(_cli: &Cli, _ast: Node, _begin: Instant) -> Result<i32, Failure> {
+    Err(Failure::Other(String::from(
+        "thor was built without LLVM, so it can only check, run and emit --tokens or --ast",
+    )))
+}
 
+#[cfg(feature = "llvm")]
+fn compile(cli: &Cli, ast: Node, begin: Instant) -> Result<i32, Failure> {
     let context = Context::create();
     let module = context.create_module("main");
     let builder = context.create_builder();
-    let mut codegen = Codegen::new(filename, &context, &module, builder);
-    codegen.generate_llvm_ir(ast);
-    match codegen.module.verify() {
-        Ok(_) => {}
-        Err(err) => eprintln!("{}", err.to_string()),
-    };
-    if log {
-        codegen
-            .module
-            .print_to_file(format!("{}.ll", out_filename))
-            .unwrap();
+    let mut codegen = Codegen::new(&cli.path, &context, &module, builder);
+    codegen.bounds_checks = cli.options.bounds_checks;
+    codegen.overflow_checks = cli.options.overflow_checks;
+    codegen
+        .generate_llvm_ir(ast)
+        .map_err(|err| Failure::diagnostics(cli, vec![err]))?;
+    if let Err(err) = module.verify() {
+        return Err(Failure::Other(format!(
+            "generated invalid LLVM IR: {}",
+            err
+        )));
     }
 
+    let target_machine = target_machine(cli)?;
+    module.set_triple(&target_machine.get_triple());
+    module.set_data_layout(&target_machine.get_target_data().get_data_layout());
+    optimize(&module, &target_machine, cli.options.opt_level);
+
+    match cli.command {
+        Command::Emit(Emit::Ir) => {
+            write_output(cli, module.print_to_string().to_bytes())?;
+        }
+        Command::Emit(Emit::Asm) => {
+            let buffer = target_machine
+                .write_to_memory_buffer(&module, FileType::Assembly)
+                .map_err(|err| Failure::Other(format!("couldn't emit assembly: {}", err)))?;
+            write_output(cli, buffer.as_slice())?;
+        }
+        Command::Emit(Emit::Obj) => {
+            let path = match &cli.output {
+                Some(path) => path.clone(),
+                None => format!("{}.o", stem(&cli.path)),
+            };
+            write_object(&target_machine, &module, Path::new(&path))?;
+        }
+        Command::Build => {
+            let path = match &cli.output {
+                Some(path) => path.clone(),
+                None => stem(&cli.path),
+            };
+            link(cli, &target_machine, &module, Path::new(&path))?;
+            println!("compiled in {:.3}s", begin.elapsed().as_secs_f64());
+        }
+        Command::Run => {
+            let mut args = vec![cli.path.clone()];
+            args.extend(cli.program_args.iter().cloned());
+            return run_jit(&context, &module, cli.options.opt_level.llvm(), &args)
+                .map_err(Failure::Other);
+        }
+        Command::Check | Command::Repl | Command::Emit(Emit::Tokens | Emit::Ast) => {
+            unreachable!()
+        }
+    }
+    Ok(0)
+}
+
+#[cfg(feature = "llvm")]
+fn start_repl(options: &Options) -> Result<(), String> {
+    repl::run(options)
+}
+
+#[cfg(not(feature = "llvm"))]
+fn start_repl(_options: &Options) -> Result<(), String> {
+    Err(String::from(
+        "thor was built without LLVM, which the REPL needs to run each entry",
+    ))
+}
+
+// The input's file name without its extension, used to name outputs
+#[cfg(feature = "llvm")]
+fn stem(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("output")
+        .to_string()
+}
+
+// Writes to the `-o` path, or to stdout without one
+fn write_output(cli: &Cli, bytes: &[u8]) -> Result<(), Failure> {
+    let result = match &cli.output {
+        Some(path) => fs::write(path, bytes),
+        None => io::stdout().write_all(bytes),
+    };
+    result.map_err(|err| Failure::Other(format!("couldn't write output: {}", err)))
+}
+
+#[cfg(feature = "llvm")]
+fn target_machine(cli: &Cli) -> Result<TargetMachine, Failure> {
     Target::initialize_all(&InitializationConfig::default());
-    let triple = TargetMachine::get_default_triple();
-    let target = Target::from_triple(&triple).expect("couldn't create target from target triple");
-    let target_machine = target
+    let triple = match &cli.target {
+        Some(target) => TargetTriple::create(target),
+        None => TargetMachine::get_default_triple(),
+    };
+    let target = Target::from_triple(&triple)
+        .map_err(|err| Failure::Other(format!("unknown target `{}`: {}", triple, err)))?;
+    target
         .create_target_machine(
             &triple,
             "generic",
             "",
-            OptimizationLevel::Aggressive,
+            cli.options.opt_level.llvm(),
             RelocMode::Default,
             CodeModel::Default,
         )
-        .expect("couldn't create target machine");
-    let object_filename = &format!("{}.o", out_filename);
+        .ok_or_else(|| Failure::Other(format!("couldn't create a target machine for `{}`", triple)))
+}
+
+#[cfg(feature = "llvm")]
+fn write_object(
+    target_machine: &TargetMachine,
+    module: &Module,
+    path: &Path,
+) -> Result<(), Failure> {
     target_machine
-        .write_to_file(
-            &codegen.module,
-            FileType::Object,
-            Path::new(object_filename),
-        )
-        .expect("couldn't write module to file");
-
-    let args = [object_filename, "-o", out_filename];
-    Command::new("clang")
-        .args(args)
-        .output()
-        .expect("failed to execute linker");
-
-    let end = Instant::now();
-    println!(
-        "compiled in {:.3}s",
-        end.duration_since(begin).as_secs_f64()
-    );
+        .write_to_file(module, FileType::Object, path)
+        .map_err(|err| Failure::Other(format!("couldn't write {}: {}", path.display(), err)))
+}
+
+// Links through a temporary object file next to the executable
+#[cfg(feature = "llvm")]
+fn link(
+    cli: &Cli,
+    target_machine: &TargetMachine,
+    module: &Module,
+    executable: &Path,
+) -> Result<(), Failure> {
+    let object = executable.with_extension("o");
+    write_object(target_machine, module, &object)?;
+    let result = Process::new(&cli.linker)
+        .arg(&object)
+        .arg("-o")
+        .arg(executable)
+        // Float `^` lowers to a call to `pow`
+        .arg("-lm")
+        .output();
+    let _ = fs::remove_file(&object);
+
+    let output = result
+        .map_err(|err| Failure::Other(format!("couldn't run linker `{}`: {}", cli.linker, err)))?;
+    if !output.status.success() {
+        return Err(Failure::Other(format!(
+            "linking with `{}` failed: {}\n{}",
+            cli.linker,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "llvm")]
+fn optimize(module: &Module, target_machine: &TargetMachine, level: OptLevel) {
+    if level == OptLevel::O0 {
+        return;
+    }
+
+    let builder = PassManagerBuilder::create();
+    builder.set_optimization_level(level.llvm());
+    builder.set_size_level(if level == OptLevel::Os { 1 } else { 0 });
+    if let Some(threshold) = level.inline_threshold() {
+        builder.set_inliner_with_threshold(threshold);
+    }
+
+    let function_passes = PassManager::create(module);
+    target_machine.add_analysis_passes(&function_passes);
+    builder.populate_function_pass_manager(&function_passes);
+    function_passes.initialize();
+    for function in module.get_functions() {
+        function_passes.run_on(&function);
+    }
+    function_passes.finalize();
+
+    let module_passes = PassManager::create(());
+    target_machine.add_analysis_passes(&module_passes);
+    builder.populate_module_pass_manager(&module_passes);
+    module_passes.run_on(module);
 }
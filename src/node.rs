@@ -1,8 +1,13 @@
 use std::fmt;
 
-use inkwell::{context::Context, types::BasicTypeEnum, AddressSpace};
+#[cfg(feature = "llvm")]
+use inkwell::{
+    context::Context,
+    types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType, StructType},
+    AddressSpace,
+};
 
-use crate::TypeLiteral;
+use crate::{Span, TypeLiteral};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Type {
@@ -11,10 +16,13 @@ pub enum Type {
     Bool,
     Str,
     Char,
-    Array(TypeLiteral, u32),
+    Array(Box<Type>, u32),
+    List(Box<Type>),
+    Struct(String),
     Void,
 }
 
+#[cfg(feature = "llvm")]
 impl Type {
     pub fn get_type<'ctx>(&self, context: &'ctx Context) -> BasicTypeEnum<'ctx> {
         let int_type = context.i32_type();
@@ -29,20 +37,78 @@ impl Type {
             Type::Bool => BasicTypeEnum::IntType(bool_type),
             Type::Str => BasicTypeEnum::PointerType(str_type),
             Type::Char => BasicTypeEnum::IntType(char_type),
-            Type::Array(ty, size) => BasicTypeEnum::PointerType(
-                match ty {
-                    TypeLiteral::Int => int_type.array_type(*size),
-                    TypeLiteral::Float => float_type.array_type(*size),
-                    TypeLiteral::Bool => bool_type.array_type(*size),
-                    TypeLiteral::Str => str_type.array_type(*size),
-                    TypeLiteral::Char => char_type.array_type(*size),
-                    TypeLiteral::Void => panic!("can't have a void array"),
-                }
-                .ptr_type(AddressSpace::Generic),
+            Type::Array(ty, _) => {
+                BasicTypeEnum::PointerType(ty.get_type(context).ptr_type(AddressSpace::Generic))
+            }
+            // Lists are passed around as pointers to their header
+            Type::List(ty) => BasicTypeEnum::PointerType(
+                Type::list_type(ty, context).ptr_type(AddressSpace::Generic),
+            ),
+            Type::Struct(name) => BasicTypeEnum::StructType(
+                context
+                    .get_struct_type(name)
+                    .expect("struct types are declared before they're used"),
             ),
             Type::Void => panic!("void isn't a valid argument type"),
         }
     }
+
+    // The type of a function returning this type. Arrays and structs are written
+    // through a pointer the caller passes before the other arguments
+    pub fn fn_type<'ctx>(
+        &self,
+        arg_types: &[Type],
+        var_args: bool,
+        context: &'ctx Context,
+    ) -> FunctionType<'ctx> {
+        let mut param_types: Vec<BasicMetadataTypeEnum<'ctx>> = vec![];
+        if let Some(sret_type) = self.sret_type(context) {
+            param_types.push(sret_type.ptr_type(AddressSpace::Generic).into());
+        }
+        param_types.extend(
+            arg_types
+                .iter()
+                .map(|ty| BasicMetadataTypeEnum::from(ty.get_type(context))),
+        );
+
+        match self {
+            Type::Void | Type::Array(..) | Type::Struct(_) => {
+                context.void_type().fn_type(&param_types, var_args)
+            }
+            // Lists are returned by value, for the caller to own
+            Type::List(item) => Type::list_type(item, context).fn_type(&param_types, var_args),
+            _ => self.get_type(context).fn_type(&param_types, var_args),
+        }
+    }
+
+    // What the pointer a function returns this type through points to
+    pub fn sret_type<'ctx>(&self, context: &'ctx Context) -> Option<BasicTypeEnum<'ctx>> {
+        match self {
+            Type::Array(item, _) => Some(item.get_type(context)),
+            Type::Struct(_) => Some(self.get_type(context)),
+            _ => None,
+        }
+    }
+
+    // A list's header: a pointer to its items, its length and its capacity
+    pub fn list_type<'ctx>(item: &Type, context: &'ctx Context) -> StructType<'ctx> {
+        let int_type = context.i32_type();
+        let item_type = item.get_type(context).ptr_type(AddressSpace::Generic);
+        context.struct_type(&[item_type.into(), int_type.into(), int_type.into()], false)
+    }
+}
+
+impl From<TypeLiteral> for Type {
+    fn from(literal: TypeLiteral) -> Self {
+        match literal {
+            TypeLiteral::Int => Type::Int,
+            TypeLiteral::Float => Type::Float,
+            TypeLiteral::Bool => Type::Bool,
+            TypeLiteral::Str => Type::Str,
+            TypeLiteral::Char => Type::Char,
+            TypeLiteral::Void => Type::Void,
+        }
+    }
 }
 
 impl fmt::Display for Type {
@@ -54,7 +120,11 @@ impl fmt::Display for Type {
             Bool => write!(f, "bool"),
             Str => write!(f, "str"),
             Char => write!(f, "char"),
+            // An empty array whose item type isn't known yet
+            Array(ty, _) if **ty == Void => write!(f, "[]"),
             Array(ty, size) => write!(f, "{}[{}]", ty, size),
+            List(ty) => write!(f, "{}[]", ty),
+            Struct(name) => write!(f, "{}", name),
             Void => write!(f, "void"),
         }
     }
@@ -65,7 +135,21 @@ pub enum UnaryOp {
     Pos,
     Neg,
     Not,
+    BitNot,
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use UnaryOp::*;
+        match self {
+            Pos => write!(f, "+"),
+            Neg => write!(f, "-"),
+            Not => write!(f, "not"),
+            BitNot => write!(f, "~"),
+        }
+    }
 }
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum BinaryOp {
     Add,
@@ -73,6 +157,11 @@ pub enum BinaryOp {
     Mul,
     Div,
     Rem,
+    Pow,
+    BitAnd,
+    BitOr,
+    Shl,
+    Shr,
     And,
     Or,
     EqEq,
@@ -81,7 +170,42 @@ pub enum BinaryOp {
     Lte,
     Gt,
     Gte,
+    Is,
+    IsNot,
+    In,
+    NotIn,
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use BinaryOp::*;
+        match self {
+            Add => write!(f, "+"),
+            Sub => write!(f, "-"),
+            Mul => write!(f, "*"),
+            Div => write!(f, "/"),
+            Rem => write!(f, "%"),
+            Pow => write!(f, "^"),
+            BitAnd => write!(f, "&"),
+            BitOr => write!(f, "|"),
+            Shl => write!(f, "<<"),
+            Shr => write!(f, ">>"),
+            And => write!(f, "and"),
+            Or => write!(f, "or"),
+            EqEq => write!(f, "=="),
+            Neq => write!(f, "!="),
+            Lt => write!(f, "<"),
+            Lte => write!(f, "<="),
+            Gt => write!(f, ">"),
+            Gte => write!(f, ">="),
+            Is => write!(f, "is"),
+            IsNot => write!(f, "is not"),
+            In => write!(f, "in"),
+            NotIn => write!(f, "not in"),
+        }
+    }
 }
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum IdentifierOp {
     Eq,
@@ -90,10 +214,112 @@ pub enum IdentifierOp {
     Mul,
     Div,
     Rem,
+    Pow,
+    BitAnd,
+    BitOr,
+    Shl,
+    Shr,
+}
+
+impl fmt::Display for IdentifierOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use IdentifierOp::*;
+        match self {
+            Eq => write!(f, "="),
+            Add => write!(f, "+="),
+            Sub => write!(f, "-="),
+            Mul => write!(f, "*="),
+            Div => write!(f, "/="),
+            Rem => write!(f, "%="),
+            Pow => write!(f, "^="),
+            BitAnd => write!(f, "&="),
+            BitOr => write!(f, "|="),
+            Shl => write!(f, "<<="),
+            Shr => write!(f, ">>="),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub span: Span,
+    // Filled in by the type checker
+    pub ty: Type,
+    // The type checker's slot for the item type of an empty array this node's
+    // type still waits on
+    pub slot: Option<usize>,
+}
+
+impl Node {
+    pub fn new(kind: NodeKind, span: Span) -> Self {
+        Self {
+            kind,
+            span,
+            ty: Type::Void,
+            slot: None,
+        }
+    }
+}
+
+impl Node {
+    pub fn children_mut(&mut self) -> Vec<&mut Node> {
+        match &mut self.kind {
+            NodeKind::Array(nodes)
+            | NodeKind::Call(_, nodes)
+            | NodeKind::Compare(nodes, _)
+            | NodeKind::Impl(_, nodes)
+            | NodeKind::Statements(nodes) => nodes.iter_mut().collect(),
+            NodeKind::MethodCall(receiver, _, args) => {
+                let mut nodes = vec![receiver.as_mut()];
+                nodes.extend(args.iter_mut());
+                nodes
+            }
+            NodeKind::Cast(_, node)
+            | NodeKind::Unary(_, node)
+            | NodeKind::Let(_, _, node)
+            | NodeKind::Return(node)
+            | NodeKind::Loop(_, node)
+            | NodeKind::Break(_, Some(node))
+            | NodeKind::Fn(_, _, _, node)
+            | NodeKind::Field(node, _) => vec![node],
+            NodeKind::StructLiteral(_, fields) => fields.iter_mut().map(|(_, node)| node).collect(),
+            NodeKind::Binary(left, _, right)
+            | NodeKind::Range(left, right)
+            | NodeKind::IdentifierOp(left, _, right)
+            | NodeKind::Index(left, right)
+            | NodeKind::For(_, _, left, right) => vec![left, right],
+            NodeKind::If(condition, body, else_case)
+            | NodeKind::While(_, condition, body, else_case) => {
+                let mut nodes = vec![condition.as_mut(), body.as_mut()];
+                if let Some(else_case) = else_case {
+                    nodes.push(else_case);
+                }
+                nodes
+            }
+            NodeKind::Int(_)
+            | NodeKind::Float(_)
+            | NodeKind::Bool(_)
+            | NodeKind::Str(_)
+            | NodeKind::Char(_)
+            | NodeKind::Type(_)
+            | NodeKind::Identifier(_)
+            | NodeKind::Struct(..)
+            | NodeKind::Break(_, None)
+            | NodeKind::Continue(_)
+            | NodeKind::Error => vec![],
+        }
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Node {
+pub enum NodeKind {
     Int(u32),
     Float(f64),
     Bool(bool),
@@ -105,28 +331,43 @@ pub enum Node {
     Identifier(String),
     Unary(UnaryOp, Box<Node>),
     Binary(Box<Node>, BinaryOp, Box<Node>),
-    Let(String, Box<Node>),
+    // Two or more comparisons in a row, e.g. `a < b <= c`
+    Compare(Vec<Node>, Vec<BinaryOp>),
+    Range(Box<Node>, Box<Node>),
+    Let(String, Option<Type>, Box<Node>),
     IdentifierOp(Box<Node>, IdentifierOp, Box<Node>),
     Index(Box<Node>, Box<Node>),
     If(Box<Node>, Box<Node>, Option<Box<Node>>),
-    While(Box<Node>, Box<Node>),
-    For(String, Box<Node>, Box<Node>),
-    Fn(String, Vec<(String, Type)>, Type, Box<Node>),
+    // Loops start with an optional label for `break` and `continue`. A while
+    // loop's else is its value when the condition fails, letting `break` leave with one
+    While(Option<String>, Box<Node>, Box<Node>, Option<Box<Node>>),
+    For(Option<String>, String, Box<Node>, Box<Node>),
+    Loop(Option<String>, Box<Node>),
+    Break(Option<String>, Option<Box<Node>>),
+    Continue(Option<String>),
+    Fn(String, Vec<(String, Type)>, Option<Type>, Box<Node>),
+    Struct(String, Vec<(String, Type)>),
+    // Methods on a struct, which take the struct as their first argument
+    Impl(String, Vec<Node>),
+    StructLiteral(String, Vec<(String, Node)>),
+    Field(Box<Node>, String),
     Return(Box<Node>),
     Call(String, Vec<Node>),
+    // Becomes a call to the method's function once the receiver's type is known
+    MethodCall(Box<Node>, String, Vec<Node>),
     Statements(Vec<Node>),
-    EOF,
+    Error,
 }
 
-impl fmt::Display for Node {
+impl fmt::Display for NodeKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Node::Int(value) => write!(f, "{}", value),
-            Node::Float(value) => write!(f, "{}f", value),
-            Node::Bool(value) => write!(f, "{}", value),
-            Node::Str(value) => write!(f, "\"{}\"", value),
-            Node::Char(value) => write!(f, "'{}'", value),
-            Node::Array(nodes) => write!(
+            NodeKind::Int(value) => write!(f, "{}", value),
+            NodeKind::Float(value) => write!(f, "{}f", value),
+            NodeKind::Bool(value) => write!(f, "{}", value),
+            NodeKind::Str(value) => write!(f, "\"{}\"", value),
+            NodeKind::Char(value) => write!(f, "'{}'", value),
+            NodeKind::Array(nodes) => write!(
                 f,
                 "[{}]",
                 nodes
@@ -135,71 +376,111 @@ impl fmt::Display for Node {
                     .collect::<Vec<String>>()
                     .join(", "),
             ),
-            Node::Type(ty) => write!(f, "{}", ty),
-            Node::Cast(literal, node) => write!(f, "{}({})", literal, node),
-            Node::Identifier(name) => write!(f, "{}", name),
-            Node::Unary(op, node) => {
-                use UnaryOp::*;
-                match op {
-                    Pos => write!(f, "(+{})", node),
-                    Neg => write!(f, "(-{})", node),
-                    Not => write!(f, "(not {})", node),
-                }
-            }
-            Node::Binary(left, op, right) => {
-                use BinaryOp::*;
-                match op {
-                    Add => write!(f, "({} + {})", left, right),
-                    Sub => write!(f, "({} - {})", left, right),
-                    Mul => write!(f, "({} * {})", left, right),
-                    Div => write!(f, "({} / {})", left, right),
-                    Rem => write!(f, "({} % {})", left, right),
-                    And => write!(f, "({} and {})", left, right),
-                    Or => write!(f, "({} or {})", left, right),
-                    EqEq => write!(f, "({} == {})", left, right),
-                    Neq => write!(f, "({} != {})", left, right),
-                    Lt => write!(f, "({} < {})", left, right),
-                    Lte => write!(f, "({} <= {})", left, right),
-                    Gt => write!(f, "({} > {})", left, right),
-                    Gte => write!(f, "({} >= {})", left, right),
-                }
-            }
-            Node::Let(name, node) => write!(f, "let {} = {}", name, node),
-            Node::IdentifierOp(name, op, node) => {
-                use IdentifierOp::*;
-                match op {
-                    Eq => write!(f, "({} = {})", name, node),
-                    Add => write!(f, "({} += {})", name, node),
-                    Sub => write!(f, "({} -= {})", name, node),
-                    Mul => write!(f, "({} *= {})", name, node),
-                    Div => write!(f, "({} /= {})", name, node),
-                    Rem => write!(f, "({} %= {})", name, node),
+            NodeKind::Type(ty) => write!(f, "{}", ty),
+            NodeKind::Cast(literal, node) => write!(f, "{}({})", literal, node),
+            NodeKind::Identifier(name) => write!(f, "{}", name),
+            NodeKind::Unary(op, node) => match op {
+                UnaryOp::Not => write!(f, "(not {})", node),
+                _ => write!(f, "({}{})", op, node),
+            },
+            NodeKind::Binary(left, op, right) => write!(f, "({} {} {})", left, op, right),
+            NodeKind::Compare(nodes, ops) => {
+                write!(f, "({}", nodes[0])?;
+                for (op, node) in ops.iter().zip(&nodes[1..]) {
+                    write!(f, " {} {}", op, node)?;
                 }
+                write!(f, ")")
             }
-            Node::Index(node, index) => write!(f, "{}[{}]", node, index),
-            Node::If(condition, body, else_case) => match else_case {
+            NodeKind::Range(start, end) => write!(f, "({}..{})", start, end),
+            NodeKind::Let(name, ty, node) => match ty {
+                Some(ty) => write!(f, "let {}: {} = {}", name, ty, node),
+                None => write!(f, "let {} = {}", name, node),
+            },
+            NodeKind::IdentifierOp(name, op, node) => write!(f, "({} {} {})", name, op, node),
+            NodeKind::Index(node, index) => write!(f, "{}[{}]", node, index),
+            NodeKind::If(condition, body, else_case) => match else_case {
                 Some(case) => write!(f, "if {}: {} else: {}", condition, body, case),
                 _ => write!(f, "if {}: {}", condition, body),
             },
-            Node::While(condition, body) => write!(f, "while {}: {}", condition, body),
-            Node::For(identifier, iterable, body) => {
-                write!(f, "for {} in {}: {}", identifier, iterable, body)
+            NodeKind::While(label, condition, body, else_case) => {
+                write!(f, "{}while {}: {}", Label(label), condition, body)?;
+                match else_case {
+                    Some(case) => write!(f, " else: {}", case),
+                    None => Ok(()),
+                }
             }
-            Node::Fn(name, args, return_type, body) => {
+            NodeKind::For(label, identifier, iterable, body) => {
                 write!(
                     f,
-                    "fn {} ({}): {} -> {}",
+                    "{}for {} in {}: {}",
+                    Label(label),
+                    identifier,
+                    iterable,
+                    body
+                )
+            }
+            NodeKind::Loop(label, body) => write!(f, "{}loop: {}", Label(label), body),
+            NodeKind::Break(label, value) => {
+                write!(f, "(break")?;
+                if let Some(label) = label {
+                    write!(f, " '{}", label)?;
+                }
+                if let Some(value) = value {
+                    write!(f, " {}", value)?;
+                }
+                write!(f, ")")
+            }
+            NodeKind::Continue(Some(label)) => write!(f, "(continue '{})", label),
+            NodeKind::Continue(None) => write!(f, "(continue)"),
+            NodeKind::Fn(name, args, return_type, body) => {
+                write!(
+                    f,
+                    "fn {} ({}){} -> {}",
                     name,
                     args.iter()
                         .map(|(name, ty)| format!("{}: {}", name, ty))
                         .collect::<Vec<String>>()
                         .join(", "),
-                    return_type,
+                    match return_type {
+                        Some(ty) => format!(": {}", ty),
+                        None => String::new(),
+                    },
                     body
                 )
             }
-            Node::Return(node) => write!(f, "(return {})", node),
-            Node::Call(name, args) => write!(
+            NodeKind::Struct(name, fields) => write!(
+                f,
+                "struct {} {{ {} }}",
+                name,
+                fields
+                    .iter()
+                    .map(|(name, ty)| format!("{}: {}", name, ty))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+            ),
+            NodeKind::Impl(name, methods) => write!(
+                f,
+                "impl {} {{ {} }}",
+                name,
+                methods
+                    .iter()
+                    .map(|method| format!("{}", method))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+            ),
+            NodeKind::StructLiteral(name, fields) => write!(
+                f,
+                "{} {{ {} }}",
+                name,
+                fields
+                    .iter()
+                    .map(|(name, node)| format!("{}: {}", name, node))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+            ),
+            NodeKind::Field(node, field) => write!(f, "{}.{}", node, field),
+            NodeKind::Return(node) => write!(f, "(return {})", node),
+            NodeKind::Call(name, args) => write!(
                 f,
                 "{}({})",
                 name,
@@ -208,7 +489,17 @@ impl fmt::Display for Node {
                     .collect::<Vec<String>>()
                     .join(", ")
             ),
-            Node::Statements(nodes) => write!(
+            NodeKind::MethodCall(receiver, method, args) => write!(
+                f,
+                "{}.{}({})",
+                receiver,
+                method,
+                args.iter()
+                    .map(|arg| format!("{}", arg))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            NodeKind::Statements(nodes) => write!(
                 f,
                 "[\n  {}\n]",
                 nodes
@@ -217,7 +508,19 @@ impl fmt::Display for Node {
                     .collect::<Vec<String>>()
                     .join("\n  ")
             ),
-            Node::EOF => write!(f, "<eof>"),
+            NodeKind::Error => write!(f, "<error>"),
+        }
+    }
+}
+
+// Writes a loop's label, if it has one
+struct Label<'a>(&'a Option<String>);
+
+impl fmt::Display for Label<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(label) => write!(f, "'{}: ", label),
+            None => Ok(()),
         }
     }
 }
@@ -1,37 +1,99 @@
-use crate::{BinaryOp, IdentifierOp, Node, Token, Type, TypeLiteral, UnaryOp};
+use crate::{BinaryOp, Diagnostic, IdentifierOp, Node, NodeKind, Span, Token, Type, UnaryOp};
+
+#[cfg(test)]
+mod tests;
 
 pub struct Parser {
-    tokens: Vec<Token>,
+    tokens: Vec<(Token, Span)>,
     index: usize,
     token: Token,
+    span: Span,
+    last_span: Span,
+    errors: Vec<Diagnostic>,
+    // Off where a '{' starts a block, e.g. `if x { ... }`
+    struct_literals: bool,
 }
 
 use Token::*;
 
+// `not` takes in comparisons but not `and` or `or`
+const NOT_POWER: u8 = 3;
+const COMPARISON_POWER: u8 = 4;
+const RANGE_POWER: u8 = 5;
+
+enum Infix {
+    Binary(BinaryOp),
+    Range,
+}
+
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(tokens: Vec<(Token, Span)>) -> Self {
+        let (token, span) = tokens[0].clone();
         Self {
-            token: tokens[0].clone(),
+            token,
+            span,
+            last_span: span,
             tokens,
             index: 0,
+            errors: vec![],
+            struct_literals: true,
         }
     }
 
     fn advance(&mut self) {
         self.index += 1;
+        self.last_span = self.tokens[self.index - 1].1;
         let next = self.tokens.get(self.index);
-        self.token = match next {
-            Some(token) => token.clone(),
-            _ => EOF,
+        (self.token, self.span) = match next {
+            Some((token, span)) => (token.clone(), *span),
+            _ => (EOF, self.last_span),
         };
     }
 
+    fn peek(&self) -> &Token {
+        match self.tokens.get(self.index + 1) {
+            Some((token, _)) => token,
+            None => &EOF,
+        }
+    }
+
     fn back(&mut self) {
         self.index -= 2;
         self.advance();
     }
 
-    
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.last_span)
+    }
+
+    fn node(&self, kind: NodeKind, start: Span) -> Node {
+        Node::new(kind, self.span_from(start))
+    }
+
+    fn error(&self, expected: &str) -> Diagnostic {
+        Diagnostic::error(
+            format!("expected {}, found {}", expected, self.token),
+            self.span,
+        )
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), Diagnostic> {
+        if self.token != token {
+            return Err(self.error(&format!("{}", token)));
+        }
+        self.advance();
+        Ok(())
+    }
+
+    fn identifier(&mut self) -> Result<String, Diagnostic> {
+        match self.token.clone() {
+            Identifier(name) => {
+                self.advance();
+                Ok(name)
+            }
+            _ => Err(self.error("identifier")),
+        }
+    }
 
     fn skip_newlines(&mut self) -> u32 {
         let mut newlines = 0u32;
@@ -42,66 +104,131 @@ impl Parser {
         newlines
     }
 
-    pub fn parse(&mut self) -> Node {
-        self.statements()
+    fn with_struct_literals<T>(
+        &mut self,
+        allowed: bool,
+        parse: impl FnOnce(&mut Self) -> Result<T, Diagnostic>,
+    ) -> Result<T, Diagnostic> {
+        let struct_literals = std::mem::replace(&mut self.struct_literals, allowed);
+        let result = parse(self);
+        self.struct_literals = struct_literals;
+        result
+    }
+
+    pub fn parse(&mut self) -> (Node, Vec<Diagnostic>) {
+        let start = self.span;
+        let mut statements: Vec<Node> = vec![];
+
+        loop {
+            if let NodeKind::Statements(nodes) = self.statements().kind {
+                statements.extend(nodes);
+            }
+            if self.token == EOF {
+                break;
+            }
+
+            // A stray '}' ends the statement list early, so report it and carry on
+            self.errors.push(Diagnostic::error(
+                format!("unexpected {}", self.token),
+                self.span,
+            ));
+            self.advance();
+        }
+
+        let ast = self.node(NodeKind::Statements(statements), start);
+        (ast, std::mem::take(&mut self.errors))
     }
 
     fn statements(&mut self) -> Node {
         let mut statements: Vec<Node> = vec![];
         self.skip_newlines();
+        let start = self.span;
 
-        statements.push(self.statement());
-
-        let mut more_statements = true;
+        while self.token != RBrace && self.token != EOF {
+            statements.push(self.recover());
 
-        loop {
-            let newlines = self.skip_newlines();
-            if newlines == 0 {
-                more_statements = false;
+            if self.skip_newlines() == 0 && self.token != RBrace && self.token != EOF {
+                self.errors.push(self.error("newline"));
+                self.synchronize();
+                self.skip_newlines();
             }
+        }
 
-            if !more_statements || self.token == RBrace {
-                break;
-            }
+        self.node(NodeKind::Statements(statements), start)
+    }
 
-            let statement = self.statement();
-            if statement == Node::EOF {
-                more_statements = false;
-                continue;
+    fn recover(&mut self) -> Node {
+        let start = self.span;
+        match self.statement() {
+            Ok(node) => node,
+            Err(err) => {
+                self.errors.push(err);
+                self.synchronize();
+                self.node(NodeKind::Error, start)
             }
-            statements.push(statement);
         }
+    }
 
-        Node::Statements(statements)
+    fn synchronize(&mut self) {
+        let mut depth = 0u32;
+        loop {
+            match self.token {
+                EOF => break,
+                Newline | RBrace if depth == 0 => break,
+                LBrace => depth += 1,
+                RBrace => depth -= 1,
+                _ => {}
+            }
+            self.advance();
+        }
     }
 
-    pub fn statement(&mut self) -> Node {
+    pub fn statement(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.span;
         match self.token {
             Let => {
                 self.advance();
 
-                let name = match self.token.clone() {
-                    Identifier(name) => name,
-                    _ => panic!("Expected identifier"),
+                let name = self.identifier()?;
+                let ty = match self.token {
+                    Colon => {
+                        self.advance();
+                        Some(self.ty()?)
+                    }
+                    _ => None,
                 };
-                self.advance();
-
-                if self.token != Eq {
-                    panic!("Expected '='");
-                }self.advance();
+                self.expect(Eq)?;
 
-                Node::Let(name,Box::new(self.expr()))
+                let value = self.expr()?;
+                Ok(self.node(NodeKind::Let(name, ty, Box::new(value)), start))
             }
             Return => {
                 self.advance();
-                Node::Return(Box::new(self.expr()))
+                let value = self.expr()?;
+                Ok(self.node(NodeKind::Return(Box::new(value)), start))
+            }
+            Break => {
+                self.advance();
+                let label = self.label();
+                // Anything left on the line is the loop's value
+                let value = match self.token {
+                    Newline | RBrace | Else | EOF => None,
+                    _ => Some(Box::new(self.expr()?)),
+                };
+                Ok(self.node(NodeKind::Break(label, value), start))
+            }
+            Continue => {
+                self.advance();
+                let label = self.label();
+                Ok(self.node(NodeKind::Continue(label), start))
             }
             _ => self.expr(),
         }
     }
 
-    fn expr(&mut self) -> Node {
-        let expr = self.or_expr();
+    fn expr(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.span;
+        let expr = self.binary_expr(0)?;
 
         macro_rules! expr {
             ($(($token:tt, $op:tt)),*) => {
@@ -109,10 +236,14 @@ impl Parser {
                     $(
                         $token => {
                             self.advance();
-                            Node::IdentifierOp(Box::new(expr), IdentifierOp::$op, Box::new(self.or_expr()))
+                            let value = self.binary_expr(0)?;
+                            Ok(self.node(
+                                NodeKind::IdentifierOp(Box::new(expr), IdentifierOp::$op, Box::new(value)),
+                                start,
+                            ))
                         }
                     )*,
-                    _ => expr,
+                    _ => Ok(expr),
                 }
             };
         }
@@ -123,274 +254,386 @@ impl Parser {
             (SubEq, Sub),
             (MulEq, Mul),
             (DivEq, Div),
-            (RemEq, Rem)
+            (RemEq, Rem),
+            (CaretEq, Pow),
+            (AmpEq, BitAnd),
+            (PipeEq, BitOr),
+            (ShlEq, Shl),
+            (ShrEq, Shr)
         )
     }
 
-    fn or_expr(&mut self) -> Node {
-        let result = self.and_expr();
-
-        match self.token {
-            Or => {
-                self.advance();
-                Node::Binary(Box::new(result), BinaryOp::Or, Box::new(self.or_expr()))
-            }
-            _ => result,
-        }
+    fn binary(&self, left: Node, op: BinaryOp, right: Node) -> Node {
+        let span = left.span.to(right.span);
+        Node::new(NodeKind::Binary(Box::new(left), op, Box::new(right)), span)
     }
 
-    fn and_expr(&mut self) -> Node {
-        let result = self.not_expr();
-
-        match self.token {
-            And => {
+    // Parses binary operators that bind at least as tightly as `min_power`
+    fn binary_expr(&mut self, min_power: u8) -> Result<Node, Diagnostic> {
+        let start = self.span;
+        let mut result = match self.token {
+            Not => {
                 self.advance();
-                Node::Binary(Box::new(result), BinaryOp::And, Box::new(self.and_expr()))
+                let value = self.binary_expr(NOT_POWER)?;
+                self.node(NodeKind::Unary(UnaryOp::Not, Box::new(value)), start)
             }
-            _ => result,
-        }
-    }
+            _ => self.factor()?,
+        };
 
-    fn not_expr(&mut self) -> Node {
-        match self.token {
-            Not => {
+        let mut comparing = false;
+        while let Some((op, power)) = self.infix() {
+            if power < min_power {
+                break;
+            }
+            let chained = comparing && power == COMPARISON_POWER;
+            comparing = power == COMPARISON_POWER;
+            self.advance();
+            if matches!(op, Infix::Binary(BinaryOp::IsNot | BinaryOp::NotIn)) {
                 self.advance();
-                Node::Unary(UnaryOp::Not, Box::new(self.not_expr()))
             }
-            _ => self.comp_expr(),
-        }
-    }
-
-    fn comp_expr(&mut self) -> Node {
-        let result = self.arith_expr();
 
-        macro_rules! comp_expr {
-            ($($token:tt),*) => {
-                match self.token {
-                    $(
-                        $token => {
-                            self.advance();
-                            Node::Binary(Box::new(result), BinaryOp::$token, Box::new(self.comp_expr()))
-                        },
-                    )*
-                    _ => result,
+            // Operators with the same power group to the left
+            let right = self.binary_expr(power + 1)?;
+            result = match op {
+                // `a < b < c` means `a < b and b < c`, with b evaluated once
+                Infix::Binary(op) if chained => {
+                    let span = result.span.to(right.span);
+                    let (nodes, ops) = match result.kind {
+                        NodeKind::Binary(left, first, middle) => {
+                            (vec![*left, *middle, right], vec![first, op])
+                        }
+                        NodeKind::Compare(mut nodes, mut ops) => {
+                            nodes.push(right);
+                            ops.push(op);
+                            (nodes, ops)
+                        }
+                        _ => unreachable!(),
+                    };
+                    Node::new(NodeKind::Compare(nodes, ops), span)
+                }
+                Infix::Binary(op) => self.binary(result, op, right),
+                Infix::Range => {
+                    let span = result.span.to(right.span);
+                    Node::new(NodeKind::Range(Box::new(result), Box::new(right)), span)
                 }
             };
         }
 
-        comp_expr!(EqEq, Neq, Lt, Lte, Gt, Gte)
+        Ok(result)
     }
 
-    fn arith_expr(&mut self) -> Node {
-        let result = self.term();
+    // The operator at the current token and how tightly it binds
+    fn infix(&self) -> Option<(Infix, u8)> {
+        use BinaryOp::*;
+        let op = match self.token {
+            Token::Or => Or,
+            Token::And => And,
+            Token::EqEq => EqEq,
+            Token::Neq => Neq,
+            Token::Lt => Lt,
+            Token::Lte => Lte,
+            Token::Gt => Gt,
+            Token::Gte => Gte,
+            Token::In => In,
+            Token::Is if self.peek() == &Token::Not => IsNot,
+            Token::Is => Is,
+            // `not` only continues an expression as part of `not in`
+            Token::Not if self.peek() == &Token::In => NotIn,
+            Token::DotDot => return Some((Infix::Range, RANGE_POWER)),
+            Token::Pipe => BitOr,
+            Token::Amp => BitAnd,
+            Token::Shl => Shl,
+            Token::Shr => Shr,
+            Token::Add => Add,
+            Token::Sub => Sub,
+            Token::Mul => Mul,
+            Token::Div => Div,
+            Token::Rem => Rem,
+            _ => return None,
+        };
+        let power = match op {
+            Or => 1,
+            And => 2,
+            EqEq | Neq | Lt | Lte | Gt | Gte | Is | IsNot | In | NotIn => COMPARISON_POWER,
+            BitOr => 6,
+            BitAnd => 7,
+            Shl | Shr => 8,
+            Add | Sub => 9,
+            Mul | Div | Rem => 10,
+            // Parsed by `power`, above any binary operator here
+            Pow => unreachable!(),
+        };
+        Some((Infix::Binary(op), power))
+    }
 
+    fn factor(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.span;
         match self.token {
             Add => {
                 self.advance();
-                Node::Binary(Box::new(result), BinaryOp::Add, Box::new(self.arith_expr()))
+                let value = self.factor()?;
+                Ok(self.node(NodeKind::Unary(UnaryOp::Pos, Box::new(value)), start))
             }
             Sub => {
                 self.advance();
-                Node::Binary(Box::new(result), BinaryOp::Sub, Box::new(self.arith_expr()))
-            }
-            _ => result,
-        }
-    }
-
-    fn term(&mut self) -> Node {
-        let result = self.factor();
-
-        match self.token {
-            Mul => {
-                self.advance();
-                Node::Binary(Box::new(result), BinaryOp::Mul, Box::new(self.term()))
-            }
-            Div => {
-                self.advance();
-                Node::Binary(Box::new(result), BinaryOp::Div, Box::new(self.term()))
+                let value = self.factor()?;
+                Ok(self.node(NodeKind::Unary(UnaryOp::Neg, Box::new(value)), start))
             }
-            Rem => {
+            Tilde => {
                 self.advance();
-                Node::Binary(Box::new(result), BinaryOp::Rem, Box::new(self.term()))
+                let value = self.factor()?;
+                Ok(self.node(NodeKind::Unary(UnaryOp::BitNot, Box::new(value)), start))
             }
-            _ => result,
+            _ => self.power(),
         }
     }
 
-    fn factor(&mut self) -> Node {
+    // Binds tighter than a sign, so -2^2 is -4, and groups to the right
+    fn power(&mut self) -> Result<Node, Diagnostic> {
+        let result = self.call()?;
+
         match self.token {
-            Add => {
+            Caret => {
                 self.advance();
-                Node::Unary(UnaryOp::Pos, Box::new(self.factor()))
+                let right = self.factor()?;
+                Ok(self.binary(result, BinaryOp::Pow, right))
             }
-            Sub => {
-                self.advance();
-                Node::Unary(UnaryOp::Neg, Box::new(self.factor()))
-            }
-            _ => self.call(),
+            _ => Ok(result),
         }
     }
 
-    fn call(&mut self) -> Node {
-        let result = self.atom();
-
-        match self.token {
-            LParen => {
-                self.advance();
-
-                match result {
-                    Node::Identifier(name) => {
-                        let args = self.list(RParen);
-                        Node::Call(name, args)
-                    }
-                    Node::Type(literal) => {
-                        let expr = self.expr();
+    // Calls, indexes and field accesses after an atom, which chain in any order
+    fn call(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.span;
+        let mut result = self.atom()?;
 
-                        if self.token != RParen {
-                            panic!("expected ')'");
+        loop {
+            result = match self.token {
+                LParen => {
+                    self.advance();
+
+                    match result.kind {
+                        // Brackets end a condition's struct literal ban
+                        NodeKind::Identifier(name) => {
+                            let args =
+                                self.with_struct_literals(true, |parser| parser.list(RParen))?;
+                            self.node(NodeKind::Call(name, args), start)
                         }
-                        self.advance();
+                        NodeKind::Field(receiver, method) => {
+                            let args =
+                                self.with_struct_literals(true, |parser| parser.list(RParen))?;
+                            self.node(NodeKind::MethodCall(receiver, method, args), start)
+                        }
+                        NodeKind::Type(literal) => {
+                            let expr = self.with_struct_literals(true, Self::expr)?;
+                            self.expect(RParen)?;
 
-                        Node::Cast(literal, Box::new(expr))
+                            self.node(NodeKind::Cast(literal, Box::new(expr)), start)
+                        }
+                        _ => {
+                            return Err(Diagnostic::error(
+                                "expected identifier or type before '('",
+                                result.span,
+                            )
+                            .with_note("only functions and types can be called"))
+                        }
                     }
-                    _ => panic!("expected identifier or type"),
                 }
-            }
-            _ => result,
+                LBracket => {
+                    let index = self.index()?;
+                    self.node(NodeKind::Index(Box::new(result), Box::new(index)), start)
+                }
+                Dot => {
+                    self.advance();
+                    let field = self.identifier()?;
+                    self.node(NodeKind::Field(Box::new(result), field), start)
+                }
+                _ => return Ok(result),
+            };
         }
     }
 
-    fn atom(&mut self) -> Node {
-        let result= match self.token.clone() {
+    fn atom(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.span;
+        let kind = match self.token.clone() {
             Int(value) => {
                 self.advance();
-                Node::Int(value)
+                NodeKind::Int(value)
             }
             Float(value) => {
                 self.advance();
-                Node::Float(value)
+                NodeKind::Float(value)
             }
             Bool(value) => {
                 self.advance();
-                Node::Bool(value)
+                NodeKind::Bool(value)
             }
             Str(value) => {
                 self.advance();
-                Node::Str(value)
+                NodeKind::Str(value)
             }
             Char(value) => {
                 self.advance();
-                Node::Char(value)
+                NodeKind::Char(value)
             }
             Ty(literal) => {
                 self.advance();
-
-                let array_size = match self.token {
-                    LBracket => {
-                        self.advance();
-
-                        let size = match self.token {
-                            Int(size)  => size,
-                            _ => panic!("array size must be an int")
-                        };
-                        self.advance(); 
-
-                        if self.token != RBracket {
-                            panic!("expected ']'");
-                        }
-                        self.advance();
-
-                        Some(size)
-                    },
-                    _=>None
-                };
-
-               Node::Type(match array_size {
-                    Some(size) => Type::Array(literal,size),
-                    None => match literal {
-                        TypeLiteral::Int=>Type::Int,
-                        TypeLiteral::Float=>Type::Float,
-                        TypeLiteral::Bool=>Type::Bool,
-                        TypeLiteral::Str=>Type::Str,
-                        TypeLiteral::Char=>Type::Char,
-                        TypeLiteral::Void=>Type::Void
-                    }
-                })
+                NodeKind::Type(self.array_type(Type::from(literal))?)
             }
             Identifier(name) => {
                 self.advance();
-                Node::Identifier(name)
+                match self.token {
+                    LBrace if self.struct_literals => self.struct_literal(name, start)?.kind,
+                    _ => NodeKind::Identifier(name),
+                }
             }
             LParen => {
                 self.advance();
-                let result = self.expr();
+                let result = self.with_struct_literals(true, Self::expr)?;
+                self.expect(RParen)?;
 
-                if self.token != RParen {
-                    panic!("expected ')'");
+                result.kind
+            }
+            LBracket => self.with_struct_literals(true, Self::array_expr)?.kind,
+            If => self.if_expr()?.kind,
+            Label(label) => {
+                self.advance();
+                self.expect(Colon)?;
+                match self.token {
+                    While => self.while_expr(Some(label))?.kind,
+                    For => self.for_expr(Some(label))?.kind,
+                    Loop => self.loop_expr(Some(label))?.kind,
+                    _ => return Err(self.error("'while', 'for' or 'loop'")),
                 }
+            }
+            While => self.while_expr(None)?.kind,
+            For => self.for_expr(None)?.kind,
+            Loop => self.loop_expr(None)?.kind,
+            Fn => self.fn_expr()?.kind,
+            Struct => self.struct_expr()?.kind,
+            Impl => self.impl_expr()?.kind,
+            // The lexer already reported it
+            Error => {
                 self.advance();
-
-                result
+                NodeKind::Error
             }
-            LBracket => self.array_expr(),
-            If => self.if_expr(),
-            While => self.while_expr(),
-            For => self.for_expr(),
-            Fn => self.fn_expr(),
-            EOF => Node::EOF,
-            _ => panic!("expected int, float, bool, str, type, identifier, '(', 'if', 'while', 'for', or 'fn'"),
+            _ => return Err(self.error("expression")),
         };
+        Ok(self.node(kind, start))
+    }
+
+    fn field_separator(&mut self) -> Result<(), Diagnostic> {
         match self.token {
-            LBracket => Node::Index(Box::new(result), Box::new(self.index())),
-            _ => result,
-        }
+            Comma => self.advance(),
+            Newline | RBrace => {}
+            _ => return Err(self.error("',' or '}'")),
+        };
+        self.skip_newlines();
+        Ok(())
     }
 
-    fn array_expr(&mut self) -> Node {
-        if self.token != LBracket {
-            panic!("expected '['");
+    fn struct_literal(&mut self, name: String, start: Span) -> Result<Node, Diagnostic> {
+        self.expect(LBrace)?;
+        self.skip_newlines();
+
+        let mut fields: Vec<(String, Node)> = vec![];
+        while self.token != RBrace {
+            let field = self.identifier()?;
+            self.expect(Colon)?;
+            let value = self.with_struct_literals(true, Self::expr)?;
+            fields.push((field, value));
+            self.field_separator()?;
         }
-        self.advance();
+        self.expect(RBrace)?;
+
+        Ok(self.node(NodeKind::StructLiteral(name, fields), start))
+    }
+
+    fn struct_expr(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.span;
+        self.expect(Struct)?;
 
-        let nodes = self.list(RBracket);
+        let name = self.identifier()?;
+        self.expect(LBrace)?;
+        self.skip_newlines();
+
+        let mut fields: Vec<(String, Type)> = vec![];
+        while self.token != RBrace {
+            let field = self.identifier()?;
+            self.expect(Colon)?;
+            let ty = self.ty()?;
+            fields.push((field, ty));
+            self.field_separator()?;
+        }
+        self.expect(RBrace)?;
 
-        Node::Array(nodes)
+        Ok(self.node(NodeKind::Struct(name, fields), start))
     }
 
-    fn if_expr(&mut self) -> Node {
-        if self.token != If {
-            panic!("expected 'if'");
+    fn impl_expr(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.span;
+        self.expect(Impl)?;
+
+        let name = self.identifier()?;
+        self.expect(LBrace)?;
+        self.skip_newlines();
+
+        let mut methods = vec![];
+        while self.token != RBrace {
+            if self.token != Fn {
+                return Err(self.error("'fn' or '}'"));
+            }
+            methods.push(self.function(Some(&name))?);
+            self.skip_newlines();
         }
-        self.advance();
+        self.expect(RBrace)?;
 
-        let condition = self.expr();
+        Ok(self.node(NodeKind::Impl(name, methods), start))
+    }
 
-        let body = match self.token {
+    fn array_expr(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.span;
+        self.expect(LBracket)?;
+
+        let nodes = self.list(RBracket)?;
+
+        Ok(self.node(NodeKind::Array(nodes), start))
+    }
+
+    fn body(&mut self) -> Result<Node, Diagnostic> {
+        match self.token {
             Colon => {
                 self.advance();
                 self.statement()
             }
             LBrace => self.block(),
-            _ => panic!("{}", "expected ':' or '{'"),
-        };
+            _ => Err(self.error("':' or '{'")),
+        }
+    }
+
+    fn if_expr(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.span;
+        self.expect(If)?;
+
+        let condition = self.with_struct_literals(false, Self::expr)?;
+        let body = self.body()?;
 
         let mut else_case: Option<Box<Node>> = None;
         let newlines = self.skip_newlines();
         if self.token == Else {
-            else_case = Some(Box::new(self.else_expr()));
+            else_case = Some(Box::new(self.else_expr()?));
         } else if newlines > 0 {
             self.back();
         }
 
-        let node = Node::If(Box::new(condition), Box::new(body), else_case);
-        node
+        Ok(self.node(
+            NodeKind::If(Box::new(condition), Box::new(body), else_case),
+            start,
+        ))
     }
 
-    fn else_expr(&mut self) -> Node {
-        if self.token != Else {
-            panic!("expected 'else'");
-        }
-        self.advance();
+    fn else_expr(&mut self) -> Result<Node, Diagnostic> {
+        self.expect(Else)?;
 
         match self.token {
             Colon => {
@@ -399,183 +642,194 @@ impl Parser {
             }
             LBrace => self.block(),
             If => self.if_expr(),
-            _ => panic!("{}", "expected ':', '{', or 'if'"),
+            _ => Err(self.error("':', '{', or 'if'")),
         }
     }
 
-    fn while_expr(&mut self) -> Node {
-        if self.token != While {
-            panic!("expected 'while'");
+    fn label(&mut self) -> Option<String> {
+        match self.token.clone() {
+            Label(label) => {
+                self.advance();
+                Some(label)
+            }
+            _ => None,
         }
-        self.advance();
+    }
 
-        let condition = self.expr();
+    fn while_expr(&mut self, label: Option<String>) -> Result<Node, Diagnostic> {
+        let start = self.span;
+        self.expect(While)?;
 
-        let body = match self.token {
-            Colon => {
-                self.advance();
-                self.statement()
-            }
-            LBrace => self.block(),
-            _ => panic!("{}", "expected ':' or '{'"),
-        };
+        let condition = self.with_struct_literals(false, Self::expr)?;
+        let body = self.body()?;
+
+        let mut else_case: Option<Box<Node>> = None;
+        let newlines = self.skip_newlines();
+        if self.token == Else {
+            else_case = Some(Box::new(self.else_expr()?));
+        } else if newlines > 0 {
+            self.back();
+        }
 
-        Node::While(Box::new(condition), Box::new(body))
+        Ok(self.node(
+            NodeKind::While(label, Box::new(condition), Box::new(body), else_case),
+            start,
+        ))
     }
 
-    fn for_expr(&mut self) -> Node {
-        if self.token != For {
-            panic!("expected 'for'");
-        }
-        self.advance();
+    fn loop_expr(&mut self, label: Option<String>) -> Result<Node, Diagnostic> {
+        let start = self.span;
+        self.expect(Loop)?;
 
-        let identifier = match &self.token {
-            Identifier(name) => {
-                let n = name.clone();
-                self.advance();
-                n
-            }
-            _ => panic!("expected identifier"),
-        };
+        let body = self.body()?;
 
-        if self.token != In {
-            panic!("expected 'in'");
-        }
-        self.advance();
+        Ok(self.node(NodeKind::Loop(label, Box::new(body)), start))
+    }
 
-        let iterable = self.expr();
+    fn for_expr(&mut self, label: Option<String>) -> Result<Node, Diagnostic> {
+        let start = self.span;
+        self.expect(For)?;
 
-        let body = match self.token {
-            Colon => {
-                self.advance();
-                self.statement()
-            }
-            LBrace => self.block(),
-            _ => panic!("{}", "expected ':' or '{'"),
-        };
+        let identifier = self.identifier()?;
+        self.expect(In)?;
 
-        Node::For(identifier, Box::new(iterable), Box::new(body))
+        let iterable = self.with_struct_literals(false, Self::expr)?;
+        let body = self.body()?;
+
+        Ok(self.node(
+            NodeKind::For(label, identifier, Box::new(iterable), Box::new(body)),
+            start,
+        ))
     }
 
-    fn fn_expr(&mut self) -> Node {
-        if self.token != Fn {
-            panic!("expected 'fn'");
-        }
-        self.advance();
+    fn fn_expr(&mut self) -> Result<Node, Diagnostic> {
+        self.function(None)
+    }
 
-        let name = match &self.token {
-            Identifier(name) => name.clone(),
-            _ => panic!("expected identifier"),
-        };
-        self.advance();
+    // Methods of `receiver` start with a `self` argument of that struct's type
+    fn function(&mut self, receiver: Option<&str>) -> Result<Node, Diagnostic> {
+        let start = self.span;
+        self.expect(Fn)?;
 
-        if self.token != LParen {
-            panic!("expected '('");
-        }
-        self.advance();
+        let name = self.identifier()?;
+        self.expect(LParen)?;
 
         let mut args: Vec<(String, Type)> = vec![];
 
-        while self.token != RParen {
-            let name = match &self.token {
-                Identifier(name) => name.clone(),
-                _ => panic!("expected identifier"),
+        if let Some(receiver) = receiver {
+            match &self.token {
+                Identifier(name) if name == "self" => self.advance(),
+                _ => return Err(self.error("'self'")),
+            }
+            match &self.token {
+                Comma => self.advance(),
+                RParen => {}
+                _ => return Err(self.error("',' or ')'")),
             };
-            self.advance();
+            args.push(("self".to_string(), Type::Struct(receiver.to_string())));
+        }
 
-            if self.token != Colon {
-                panic!("expected ':'");
-            }
-            self.advance();
+        while self.token != RParen {
+            let name = self.identifier()?;
+            self.expect(Colon)?;
 
-            let ty = match self.atom() {
-                Node::Type(ty) => ty,
-                _ => panic!("expected a type"),
-            };
+            let ty = self.ty()?;
 
             match &self.token {
                 Comma => self.advance(),
                 RParen => {}
-                _ => panic!("expected ',' or ')'"),
+                _ => return Err(self.error("',' or ')'")),
             };
 
             args.push((name, ty));
         }
+        self.expect(RParen)?;
 
-        if self.token != RParen {
-            panic!("expected '{}'", RParen);
-        }
-        self.advance();
-
+        // Omitted return types are inferred by the type checker
         let return_type = match self.token {
             Colon => {
                 self.advance();
-
-                match self.atom() {
-                    Node::Type(ty) => ty,
-                    _ => panic!("expected type"),
-                }
+                Some(self.ty()?)
             }
-            _ => Type::Void,
+            _ => None,
         };
 
         let body = match self.token {
-            LBrace => self.block(),
-            _ => panic!("{}", "expected '{'"),
+            LBrace => self.block()?,
+            _ => return Err(self.error("'{'")),
         };
 
-        Node::Fn(name.to_string(), args, return_type, Box::new(body))
+        Ok(self.node(NodeKind::Fn(name, args, return_type, Box::new(body)), start))
+    }
+
+    fn ty(&mut self) -> Result<Type, Diagnostic> {
+        if let Identifier(name) = self.token.clone() {
+            self.advance();
+            return self.array_type(Type::Struct(name));
+        }
+
+        let node = self.atom()?;
+        match node.kind {
+            NodeKind::Type(ty) => Ok(ty),
+            _ => Err(Diagnostic::error("expected a type", node.span)),
+        }
     }
 
-    fn list(&mut self, end: Token) -> Vec<Node> {
+    // An array or list of `item` if brackets follow it, otherwise `item` itself
+    fn array_type(&mut self, item: Type) -> Result<Type, Diagnostic> {
+        if self.token != LBracket {
+            return Ok(item);
+        }
+        self.advance();
+
+        // A type without a size is a list
+        let ty = match self.token {
+            Int(size) => {
+                self.advance();
+                Type::Array(Box::new(item), size)
+            }
+            RBracket => Type::List(Box::new(item)),
+            _ => return Err(self.error("array size or ']'")),
+        };
+
+        self.expect(RBracket)?;
+
+        Ok(ty)
+    }
+
+    fn list(&mut self, end: Token) -> Result<Vec<Node>, Diagnostic> {
         let mut nodes: Vec<Node> = vec![];
 
         while self.token != end {
-            nodes.push(self.expr());
+            nodes.push(self.expr()?);
             match &self.token {
                 Comma => self.advance(),
                 t if *t == end => {}
-                _ => panic!("expected ',' or '{}'", end),
+                _ => return Err(self.error(&format!("',' or {}", end))),
             };
         }
+        self.expect(end)?;
 
-        if self.token != end {
-            panic!("expected '{}'", end);
-        }
-        self.advance();
-
-        nodes
+        Ok(nodes)
     }
 
-    fn block(&mut self) -> Node {
-        if self.token != LBrace {
-            panic!("{}", "expected '{'");
-        }
-        self.advance();
+    fn block(&mut self) -> Result<Node, Diagnostic> {
+        self.expect(LBrace)?;
 
         let statements = self.statements();
 
-        if self.token != RBrace {
-            panic!("{}", "expected '}'");
-        }
-        self.advance();
+        self.expect(RBrace)?;
 
-        statements
+        Ok(statements)
     }
 
-    fn index(&mut self) -> Node {
-        if self.token != LBracket {
-            panic!("{}", "expected '{'");
-        }
-        self.advance();
+    fn index(&mut self) -> Result<Node, Diagnostic> {
+        self.expect(LBracket)?;
 
-        let node = self.expr();
+        let node = self.with_struct_literals(true, Self::expr)?;
 
-        if self.token != RBracket {
-            panic!("expected ']'");
-        }
-        self.advance();
+        self.expect(RBracket)?;
 
-        node
+        Ok(node)
     }
 }
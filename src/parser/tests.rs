@@ -0,0 +1,55 @@
+use crate::{Lexer, Parser};
+
+// Lexes and parses `source`, returning the message and line of each error it has
+fn errors(source: &str) -> Vec<(String, usize)> {
+    let (tokens, mut errors) = Lexer::new(source.to_string()).lex();
+    let (_, parse_errors) = Parser::new(tokens).parse();
+    errors.extend(parse_errors);
+    errors.sort_by_key(|error| error.span.start);
+    errors
+        .into_iter()
+        .map(|error| (error.message, error.span.line))
+        .collect()
+}
+
+#[test]
+fn recovers_to_report_every_statement() {
+    assert_eq!(
+        errors("let = 1\nprint(1)\nlet b = (2\nfn f( {\n  1\n}\nlet c = 3 4\n}\nprint(c)"),
+        [
+            ("expected identifier, found '='", 1),
+            ("expected ')', found '\\n'", 3),
+            ("expected identifier, found '{'", 4),
+            ("expected newline, found 4", 7),
+            ("unexpected '}'", 8),
+        ]
+        .map(|(message, line)| (message.to_string(), line))
+    );
+}
+
+#[test]
+fn brackets_in_a_condition_allow_struct_literals() {
+    let source = "if near(P { x: 1 }): print(1)
+while ps[index(P { x: 2 })].x > 0 {
+  break
+}
+for p in [P { x: 3 }]: print(p.x)
+if origin.dist(P { x: 4 }) > float(P { x: 5 }.x) {}";
+    assert_eq!(errors(source), []);
+}
+
+#[test]
+fn reports_lexer_and_parse_errors_together() {
+    assert_eq!(
+        errors("let a = 1 $ 2\nlet = 3\nlet b = 1.2.3\nprint(b\nlet c = \"4"),
+        [
+            ("illegal character '$'", 1),
+            ("expected newline, found 2", 1),
+            ("expected identifier, found '='", 2),
+            ("invalid float literal '1.2.3'", 3),
+            ("expected ',' or ')', found '\\n'", 4),
+            ("unterminated string literal", 5),
+        ]
+        .map(|(message, line)| (message.to_string(), line))
+    );
+}
@@ -0,0 +1,203 @@
+use std::io::{self, BufRead, Write};
+use std::mem;
+
+use inkwell::context::Context;
+
+use crate::{
+    cli::Options, compiler::Session, Diagnostic, Lexer, Node, NodeKind, Parser, Token, Type,
+    TypeChecker,
+};
+
+const HELP: &str = "\
+:ast <code>    Print the syntax tree of <code>
+:ir <code>     Print the LLVM IR <code> compiles to, without running it
+:type <expr>   Print the type of <expr>
+:help          Print this message
+:quit          Exit the REPL
+";
+
+struct Repl<'ctx> {
+    session: Session<'ctx>,
+    // Only updated once an entry runs, so a failed one leaves no trace
+    checker: TypeChecker,
+    // How many entries have been read, which diagnostics name their entry by
+    entries: usize,
+}
+
+pub fn run(options: &Options) -> Result<(), String> {
+    let context = Context::create();
+    let mut session = Session::new(&context)?;
+    session.bounds_checks = options.bounds_checks;
+    session.overflow_checks = options.overflow_checks;
+    let mut repl = Repl {
+        session,
+        checker: TypeChecker::new(),
+        entries: 0,
+    };
+
+    println!(
+        "thor {}, type :help for commands",
+        env!("CARGO_PKG_VERSION")
+    );
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    while let Some(input) = read_entry(&mut lines).map_err(|err| err.to_string())? {
+        let input = input.trim();
+        repl.entries += 1;
+        let result = match input.strip_prefix(':') {
+            Some(command) => {
+                let (command, code) = command
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((command, ""));
+                match command {
+                    "quit" | "q" => return Ok(()),
+                    _ => repl.command(command, code.trim()),
+                }
+            }
+            None if input.is_empty() => Ok(()),
+            None => repl.evaluate(input),
+        };
+        if let Err(message) = result {
+            eprintln!("{}", message);
+        }
+    }
+    println!();
+    Ok(())
+}
+
+// Reads lines until the brackets in them balance, so a block can span several
+fn read_entry(lines: &mut impl Iterator<Item = io::Result<String>>) -> io::Result<Option<String>> {
+    let mut input = String::new();
+    let mut prompt = "> ";
+    loop {
+        print!("{}", prompt);
+        io::stdout().flush()?;
+        let line = match lines.next() {
+            Some(line) => line?,
+            None if input.is_empty() => return Ok(None),
+            None => return Ok(Some(input)),
+        };
+        input.push_str(&line);
+        input.push('\n');
+        if depth(&input) <= 0 {
+            return Ok(Some(input));
+        }
+        prompt = "... ";
+    }
+}
+
+// How many brackets are still open, ignoring any inside strings
+fn depth(text: &str) -> i32 {
+    // Errors are left for the parser to report
+    let (tokens, _) = Lexer::new(text.to_string()).lex();
+    tokens
+        .iter()
+        .map(|(token, _)| match token {
+            Token::LBrace | Token::LParen | Token::LBracket => 1,
+            Token::RBrace | Token::RParen | Token::RBracket => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+// A bare expression prints its value, unless it already printed something
+fn echo(node: &mut Node) {
+    let statement = matches!(
+        node.kind,
+        NodeKind::Let(..)
+            | NodeKind::IdentifierOp(..)
+            | NodeKind::Fn(..)
+            | NodeKind::Struct(..)
+            | NodeKind::Return(..)
+            | NodeKind::Break(..)
+            | NodeKind::Continue(..)
+    );
+    let print = matches!(&node.kind, NodeKind::Call(name, _) if name == "print");
+    if statement || print || node.ty == Type::Void {
+        return;
+    }
+
+    let span = node.span;
+    let value = mem::replace(node, Node::new(NodeKind::Error, span));
+    *node = Node::new(NodeKind::Call("print".to_string(), vec![value]), span);
+    node.ty = Type::Int;
+}
+
+impl<'ctx> Repl<'ctx> {
+    fn render(&self, diagnostics: Vec<Diagnostic>, text: &str) -> String {
+        let file = format!("<entry {}>", self.entries);
+        diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.in_file(&file).render(text))
+            .collect::<Vec<String>>()
+            .join("\n\n")
+    }
+
+    fn parse(&self, text: &str) -> Result<Node, String> {
+        let mut lexer = Lexer::new(text.to_string());
+        let (tokens, mut errors) = lexer.lex();
+        let mut parser = Parser::new(tokens);
+        let (ast, parse_errors) = parser.parse();
+        errors.extend(parse_errors);
+        if !errors.is_empty() {
+            errors.sort_by_key(|error| error.span.start);
+            return Err(self.render(errors, text));
+        }
+        Ok(ast)
+    }
+
+    // Checks the code with a copy of the checker, which is kept if the code runs
+    fn check(&self, text: &str) -> Result<(Node, TypeChecker), String> {
+        let ast = self.parse(text)?;
+        let mut checker = self.checker.clone();
+        let (ast, errors) = checker.check(ast);
+        if !errors.is_empty() {
+            return Err(self.render(errors, text));
+        }
+        Ok((ast, checker))
+    }
+
+    fn evaluate(&mut self, text: &str) -> Result<(), String> {
+        let (mut ast, checker) = self.check(text)?;
+        if let NodeKind::Statements(nodes) = &mut ast.kind {
+            for node in nodes {
+                echo(node);
+            }
+        }
+        let entry = self
+            .session
+            .compile(ast)
+            .map_err(|err| self.render(vec![err], text))?;
+        self.session
+            .run(entry)
+            .map_err(|err| format!("error: {}", err))?;
+        self.checker = checker;
+        Ok(())
+    }
+
+    fn command(&mut self, command: &str, code: &str) -> Result<(), String> {
+        match command {
+            "ast" => println!("{}", self.parse(code)?),
+            "ir" => {
+                let (ast, _) = self.check(code)?;
+                let entry = self
+                    .session
+                    .compile(ast)
+                    .map_err(|err| self.render(vec![err], code))?;
+                print!("{}", entry.module.print_to_string().to_string());
+            }
+            "type" => {
+                let (ast, _) = self.check(code)?;
+                println!("{}", ast.ty);
+            }
+            "help" => print!("{}", HELP),
+            _ => {
+                return Err(format!(
+                    "error: unknown command `:{}`, type :help for commands",
+                    command
+                ))
+            }
+        }
+        Ok(())
+    }
+}
@@ -33,6 +33,7 @@ pub enum Token {
     Char(char),
     Ty(TypeLiteral),
     Identifier(String),
+    Label(String),
     Eq,
     Add,
     AddEq,
@@ -44,6 +45,17 @@ pub enum Token {
     DivEq,
     Rem,
     RemEq,
+    Caret,
+    CaretEq,
+    Amp,
+    AmpEq,
+    Pipe,
+    PipeEq,
+    Shl,
+    ShlEq,
+    Shr,
+    ShrEq,
+    Tilde,
     LParen,
     RParen,
     LBrace,
@@ -51,6 +63,7 @@ pub enum Token {
     LBracket,
     RBracket,
     In,
+    Is,
     Not,
     And,
     Or,
@@ -62,14 +75,23 @@ pub enum Token {
     Gte,
     Comma,
     Colon,
+    Dot,
+    DotDot,
     Let,
     If,
     Else,
     While,
     For,
+    Loop,
+    Break,
+    Continue,
     Fn,
+    Struct,
+    Impl,
     Return,
     Newline,
+    // A literal the lexer couldn't read, which it has already reported
+    Error,
     EOF,
 }
 
@@ -84,6 +106,7 @@ impl fmt::Display for Token {
             Char(value) => write!(f, "'{}'", value),
             Ty(literal) => write!(f, "{}", literal),
             Identifier(name) => write!(f, "{}", name),
+            Label(name) => write!(f, "'{}", name),
             Eq => write!(f, "'='"),
             Add => write!(f, "'+'"),
             AddEq => write!(f, "'+='"),
@@ -95,6 +118,17 @@ impl fmt::Display for Token {
             DivEq => write!(f, "'/='"),
             Rem => write!(f, "'%'"),
             RemEq => write!(f, "'%='"),
+            Caret => write!(f, "'^'"),
+            CaretEq => write!(f, "'^='"),
+            Amp => write!(f, "'&'"),
+            AmpEq => write!(f, "'&='"),
+            Pipe => write!(f, "'|'"),
+            PipeEq => write!(f, "'|='"),
+            Shl => write!(f, "'<<'"),
+            ShlEq => write!(f, "'<<='"),
+            Shr => write!(f, "'>>'"),
+            ShrEq => write!(f, "'>>='"),
+            Tilde => write!(f, "'~'"),
             LParen => write!(f, "'('"),
             RParen => write!(f, "')'"),
             LBrace => write!(f, "'{{'"),
@@ -102,6 +136,7 @@ impl fmt::Display for Token {
             LBracket => write!(f, "'['"),
             RBracket => write!(f, "']'"),
             In => write!(f, "'in'"),
+            Is => write!(f, "'is'"),
             Not => write!(f, "'not'"),
             And => write!(f, "'and'"),
             Or => write!(f, "'or'"),
@@ -113,14 +148,22 @@ impl fmt::Display for Token {
             Gte => write!(f, "'>='"),
             Comma => write!(f, "','"),
             Colon => write!(f, "':'"),
+            Dot => write!(f, "'.'"),
+            DotDot => write!(f, "'..'"),
             Let => write!(f, "'let'"),
             If => write!(f, "'if'"),
             Else => write!(f, "'else'"),
             While => write!(f, "'while'"),
             For => write!(f, "'for'"),
+            Loop => write!(f, "'loop'"),
+            Break => write!(f, "'break'"),
+            Continue => write!(f, "'continue'"),
             Fn => write!(f, "'fn'"),
+            Struct => write!(f, "'struct'"),
+            Impl => write!(f, "'impl'"),
             Return => write!(f, "'return'"),
             Newline => write!(f, "'\\n'"),
+            Error => write!(f, "<error>"),
             EOF => write!(f, "<eof>"),
         }
     }
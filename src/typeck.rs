@@ -0,0 +1,1471 @@
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+};
+
+use crate::{BinaryOp, Diagnostic, IdentifierOp, Node, NodeKind, Span, Type, UnaryOp};
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Clone)]
+struct Signature {
+    arg_types: Vec<Type>,
+    var_args: bool,
+    // None until it's inferred from the function's return statements
+    return_type: Option<Type>,
+}
+
+type StructDefinition = (String, Vec<(String, Type)>, Span);
+
+// These work on any list, so they can't have a signature
+pub const LIST_BUILTINS: [&str; 3] = ["len", "push", "pop"];
+
+// The item type of an empty array, resolved by how the array is later used
+#[derive(Clone)]
+struct Slot {
+    span: Span,
+    name: Option<String>,
+    item: Option<Type>,
+    // Pushing to or popping from the array makes it a list
+    list: bool,
+    reported: bool,
+}
+
+impl Slot {
+    // The type of an array of `size` items that uses this slot
+    fn resolved(&self, size: u32) -> Option<Type> {
+        let item = Box::new(self.item.clone()?);
+        Some(if self.list {
+            Type::List(item)
+        } else {
+            Type::Array(item, size)
+        })
+    }
+}
+
+#[derive(Clone, Default)]
+struct Scope {
+    variables: HashMap<String, (Type, Option<usize>)>,
+    functions: HashMap<String, Signature>,
+    // Variables outside a function body live in another stack frame
+    function: bool,
+}
+
+// Cloned by the REPL to undo an entry that fails
+#[derive(Clone)]
+pub struct TypeChecker {
+    scopes: Vec<Scope>,
+    return_types: Vec<(String, Option<Type>)>,
+    // The label, keyword and value type of each loop `break` can leave
+    loops: Vec<(Option<String>, &'static str, Option<Type>)>,
+    // Only kept while checking, since every slot is resolved or reported by the end
+    slots: Vec<Slot>,
+    last_slot: Option<usize>,
+    // Struct types are visible everywhere, so their names must be unique
+    structs: HashMap<String, Vec<(String, Type)>>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        let mut checker = Self {
+            scopes: vec![Scope::default()],
+            return_types: vec![],
+            loops: vec![],
+            slots: vec![],
+            last_slot: None,
+            structs: HashMap::new(),
+            errors: vec![],
+        };
+        checker.builtins();
+        // User code gets its own scope so it can shadow builtins
+        checker.scopes.push(Scope::default());
+        checker
+    }
+
+    fn builtins(&mut self) {
+        self.declare_variable("PI", Type::Float);
+
+        // The format string is generated from the arguments, so print takes anything
+        self.declare_function("print", vec![], true, Some(Type::Int));
+        self.declare_function("abs", vec![Type::Int], false, Some(Type::Int));
+        for name in ["wrapping_add", "checked_add"] {
+            self.declare_function(name, vec![Type::Int, Type::Int], false, Some(Type::Int));
+        }
+        for name in ["round", "floor", "ceil", "sqrt", "sin", "cos", "tan"] {
+            self.declare_function(name, vec![Type::Float], false, Some(Type::Float));
+        }
+    }
+
+    pub fn check(&mut self, mut ast: Node) -> (Node, Vec<Diagnostic>) {
+        let mut structs = vec![];
+        Self::struct_definitions(&mut ast, &mut structs);
+        self.declare_structs(structs);
+
+        if let Err(error) = self.visit(&mut ast) {
+            self.errors.push(error);
+        }
+
+        for slot in 0..self.slots.len() {
+            if self.slots[slot].item.is_none() && !self.slots[slot].reported {
+                let error = self.annotations_needed(slot, self.slots[slot].span);
+                self.errors.push(error);
+            }
+        }
+        self.resolve(&mut ast);
+
+        // Variables left for later checks, like the REPL's, take their resolved types
+        for scope in &mut self.scopes {
+            for (ty, slot) in scope.variables.values_mut() {
+                if let (Type::Array(_, size), Some(slot)) = (&ty, slot.take()) {
+                    if let Some(resolved) = self.slots[slot].resolved(*size) {
+                        *ty = resolved;
+                    }
+                }
+            }
+        }
+        self.slots.clear();
+        self.last_slot = None;
+
+        (ast, mem::take(&mut self.errors))
+    }
+
+    fn resolve(&self, node: &mut Node) {
+        if let (Type::Array(_, size), Some(slot)) = (node.ty.clone(), node.slot) {
+            let slot = &self.slots[slot];
+            if let Some(item) = &slot.item {
+                node.ty = Type::Array(Box::new(item.clone()), size);
+                // The empty array a list starts as is copied into it
+                if slot.list && matches!(node.kind, NodeKind::Array(_)) {
+                    node.slot = None;
+                    Self::cast(node, &Type::List(Box::new(item.clone())));
+                } else if let Some(resolved) = slot.resolved(size) {
+                    node.ty = resolved;
+                }
+            }
+        }
+        for child in node.children_mut() {
+            self.resolve(child);
+        }
+    }
+
+    fn iterates_empty_array(&mut self, node: &Node) -> bool {
+        match &node.kind {
+            NodeKind::For(_, _, iterable, _) => match &iterable.kind {
+                NodeKind::Identifier(name) => matches!(
+                    self.get_variable(name),
+                    Some(Type::Array(item, _)) if *item == Type::Void
+                ),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn annotations_needed(&mut self, slot: usize, span: Span) -> Diagnostic {
+        let slot = &mut self.slots[slot];
+        slot.reported = true;
+        match &slot.name {
+            Some(name) => {
+                Diagnostic::error(format!("type annotations needed for `{}`", name), span)
+                    .with_note(format!(
+                        "consider giving `{}` a type, e.g. `let {}: int[] = []`",
+                        name, name
+                    ))
+            }
+            None => Diagnostic::error("type annotations needed for this array", span),
+        }
+    }
+
+    fn struct_definitions(node: &mut Node, structs: &mut Vec<StructDefinition>) {
+        if let NodeKind::Struct(name, fields) = &node.kind {
+            structs.push((name.clone(), fields.clone(), node.span));
+        }
+        for child in node.children_mut() {
+            Self::struct_definitions(child, structs);
+        }
+    }
+
+    fn declare_structs(&mut self, structs: Vec<StructDefinition>) {
+        let mut declared = vec![];
+        for (name, fields, span) in structs {
+            if self.structs.contains_key(&name) {
+                self.errors.push(Diagnostic::error(
+                    format!("the struct `{}` is defined multiple times", name),
+                    span,
+                ));
+                continue;
+            }
+            self.structs.insert(name.clone(), fields);
+            declared.push((name, span));
+        }
+
+        for (name, span) in declared {
+            let fields = &self.structs[&name];
+            let mut errors = vec![];
+            for (i, (field, ty)) in fields.iter().enumerate() {
+                if fields[..i].iter().any(|(other, _)| other == field) {
+                    errors.push(Diagnostic::error(
+                        format!("field `{}` is already declared in `{}`", field, name),
+                        span,
+                    ));
+                }
+                if let Err(error) = self.check_type(ty, span) {
+                    errors.push(error);
+                }
+                if let Type::List(_) = ty {
+                    errors.push(
+                        Diagnostic::error(
+                            format!("field `{}` of `{}` can't be a list", field, name),
+                            span,
+                        )
+                        .with_note("lists are owned by the scope they're created in"),
+                    );
+                }
+            }
+            if self.contains_struct(&Type::Struct(name.clone()), &name, &mut vec![]) {
+                errors.push(
+                    Diagnostic::error(format!("recursive type `{}` has infinite size", name), span)
+                        .with_note("a struct can't contain itself"),
+                );
+            }
+            self.errors.extend(errors);
+        }
+    }
+
+    // Whether any field of `ty`, however deeply nested, is the struct `target`
+    fn contains_struct(&self, ty: &Type, target: &str, seen: &mut Vec<String>) -> bool {
+        let name = match ty {
+            Type::Struct(name) => name,
+            _ => return false,
+        };
+        if seen.contains(name) {
+            return false;
+        }
+        seen.push(name.clone());
+        self.structs.get(name).is_some_and(|fields| {
+            fields.iter().any(|(_, ty)| {
+                matches!(ty, Type::Struct(name) if name == target)
+                    || self.contains_struct(ty, target, seen)
+            })
+        })
+    }
+
+    fn check_type(&self, ty: &Type, span: Span) -> Result<(), Diagnostic> {
+        match ty {
+            Type::Struct(name) if !self.structs.contains_key(name) => Err(Diagnostic::error(
+                format!("cannot find type `{}` in this scope", name),
+                span,
+            )),
+            Type::Array(item, _) | Type::List(item) => self.check_type(item, span),
+            _ => Ok(()),
+        }
+    }
+
+    fn field_type(&self, ty: &Type, field: &str, span: Span) -> Result<(usize, Type), Diagnostic> {
+        let name = match ty {
+            Type::Struct(name) => name,
+            _ => {
+                return Err(Diagnostic::error(
+                    format!("can't access field `{}` on a value of type {}", field, ty),
+                    span,
+                ))
+            }
+        };
+        self.structs[name]
+            .iter()
+            .enumerate()
+            .find(|(_, (other, _))| other == field)
+            .map(|(i, (_, ty))| (i, ty.clone()))
+            .ok_or_else(|| {
+                Diagnostic::error(format!("no field `{}` on type {}", field, name), span)
+            })
+    }
+
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn declare_variable(&mut self, name: &str, ty: Type) {
+        self.scope().variables.insert(name.to_string(), (ty, None));
+    }
+
+    fn declare_function(
+        &mut self,
+        name: &str,
+        arg_types: Vec<Type>,
+        var_args: bool,
+        return_type: Option<Type>,
+    ) {
+        self.scope().functions.insert(
+            name.to_string(),
+            Signature {
+                arg_types,
+                var_args,
+                return_type,
+            },
+        );
+    }
+
+    fn get_variable(&mut self, name: &str) -> Option<Type> {
+        let (ty, slot) = self
+            .scopes
+            .iter()
+            .rev()
+            .scan(false, |outside, scope| {
+                let visible = !*outside;
+                *outside |= scope.function;
+                Some((visible, scope))
+            })
+            .filter(|(visible, _)| *visible)
+            .find_map(|(_, scope)| scope.variables.get(name))
+            // Builtins are visible everywhere
+            .or_else(|| self.scopes[0].variables.get(name))?
+            .clone();
+
+        self.last_slot = slot;
+        match (ty, slot) {
+            (Type::Array(item, size), Some(slot)) => Some(
+                self.slots[slot]
+                    .resolved(size)
+                    .unwrap_or(Type::Array(item, size)),
+            ),
+            (ty, _) => Some(ty),
+        }
+    }
+
+    fn get_function(&self, name: &str) -> Option<&Signature> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.functions.get(name))
+    }
+
+    // Declares every function in a block before any bodies are checked, so
+    // functions can call each other regardless of the order they're defined in
+    fn declare_functions(&mut self, nodes: &[Node]) -> Result<(), Diagnostic> {
+        let mut names = vec![];
+        for node in nodes {
+            if let NodeKind::Fn(name, args, return_type, _) = &node.kind {
+                if names.contains(name) {
+                    return Err(Diagnostic::error(
+                        format!("the function `{}` is defined multiple times", name),
+                        node.span,
+                    ));
+                }
+                names.push(name.clone());
+                let arg_types = args.iter().map(|(_, ty)| ty.clone()).collect();
+                self.declare_function(name, arg_types, false, return_type.clone());
+            }
+        }
+        Ok(())
+    }
+
+    // Replaces each `impl` block with its methods, which are functions named
+    // after their struct, e.g. `Point.norm`
+    fn methods(&mut self, nodes: &mut Vec<Node>) {
+        for node in mem::take(nodes) {
+            let (name, methods) = match node.kind {
+                NodeKind::Impl(name, methods) => (name, methods),
+                _ => {
+                    nodes.push(node);
+                    continue;
+                }
+            };
+            if !self.structs.contains_key(&name) {
+                self.errors.push(Diagnostic::error(
+                    format!("cannot find struct `{}` in this scope", name),
+                    node.span,
+                ));
+                continue;
+            }
+            for mut method in methods {
+                if let NodeKind::Fn(method_name, ..) = &mut method.kind {
+                    *method_name = format!("{}.{}", name, method_name);
+                }
+                nodes.push(method);
+            }
+        }
+    }
+
+    // Whether every path through `node` returns, or never ends like a `loop`
+    // nothing breaks out of
+    fn always_returns(node: &mut Node) -> bool {
+        match &mut node.kind {
+            NodeKind::Return(_) => true,
+            NodeKind::Statements(nodes) => nodes.iter_mut().any(Self::always_returns),
+            NodeKind::If(_, body, Some(else_case)) => {
+                Self::always_returns(body) && Self::always_returns(else_case)
+            }
+            NodeKind::Loop(label, body) => {
+                let label = label.clone();
+                !Self::breaks_out(body, &label, 0)
+            }
+            _ => false,
+        }
+    }
+
+    // Whether `node` breaks out of the loop labelled `label`, which is `depth`
+    // loops out from it
+    fn breaks_out(node: &mut Node, label: &Option<String>, depth: usize) -> bool {
+        let depth = match &node.kind {
+            NodeKind::Break(None, _) if depth == 0 => return true,
+            NodeKind::Break(Some(target), _) if label.as_ref() == Some(target) => return true,
+            NodeKind::Fn(..) => return false,
+            NodeKind::While(..) | NodeKind::For(..) | NodeKind::Loop(..) => depth + 1,
+            _ => depth,
+        };
+        node.children_mut()
+            .into_iter()
+            .any(|child| Self::breaks_out(child, label, depth))
+    }
+
+    fn calls(node: &mut Node, names: &mut Vec<String>) {
+        match &node.kind {
+            NodeKind::Call(name, _) => names.push(name.clone()),
+            // The receiver's type isn't known yet, so this stands for every
+            // method with the name
+            NodeKind::MethodCall(_, method, _) => names.push(format!(".{}", method)),
+            _ => {}
+        }
+        for child in node.children_mut() {
+            Self::calls(child, names);
+        }
+    }
+
+    // Orders the functions in a block so callees are checked before their
+    // callers, letting return types be inferred regardless of definition order
+    fn definition_order(nodes: &mut [Node]) -> Vec<usize> {
+        let mut functions = vec![];
+        for (i, node) in nodes.iter_mut().enumerate() {
+            if let NodeKind::Fn(name, ..) = &node.kind {
+                let name = name.clone();
+                let mut calls = vec![];
+                Self::calls(node, &mut calls);
+                functions.push((i, name, calls));
+            }
+        }
+
+        fn visit(
+            i: usize,
+            functions: &[(usize, String, Vec<String>)],
+            visited: &mut HashSet<usize>,
+            order: &mut Vec<usize>,
+        ) {
+            if !visited.insert(i) {
+                return;
+            }
+            let (index, _, calls) = &functions[i];
+            for call in calls {
+                let callees = functions.iter().enumerate().filter(|(_, (_, name, _))| {
+                    name == call || (call.starts_with('.') && name.ends_with(call.as_str()))
+                });
+                for (callee, _) in callees {
+                    visit(callee, functions, visited, order);
+                }
+            }
+            order.push(*index);
+        }
+
+        let mut visited = HashSet::new();
+        let mut order = vec![];
+        for i in 0..functions.len() {
+            visit(i, &functions, &mut visited, &mut order);
+        }
+        order
+    }
+
+    // Checks a node against the type it's expected to have, converting ints to
+    // floats and arrays to lists where needed. Returns the type the node ends up with
+    fn coerce(&mut self, node: &mut Node, expected: &Type) -> Result<Type, Diagnostic> {
+        if let NodeKind::Array(nodes) = &node.kind {
+            if nodes.is_empty() {
+                match expected {
+                    Type::Array(_, 0) => {
+                        node.ty = expected.clone();
+                        return Ok(node.ty.clone());
+                    }
+                    Type::List(item) => {
+                        node.ty = Type::Array(item.clone(), 0);
+                        return Ok(Self::cast(node, expected));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let ty = self.visit_value(node)?;
+        match (&ty, expected) {
+            (Type::Int, Type::Float) => Ok(Self::cast(node, expected)),
+            (Type::Array(empty, size), Type::Array(item, expected_size))
+                if **empty == Type::Void && size == expected_size =>
+            {
+                self.resolve_slot(node, item);
+                Ok(expected.clone())
+            }
+            (Type::Array(empty, _), Type::List(item)) if **empty == Type::Void => {
+                self.resolve_slot(node, item);
+                Ok(Self::cast(node, expected))
+            }
+            (Type::Array(item, _), Type::List(expected_item)) if item == expected_item => {
+                Ok(Self::cast(node, expected))
+            }
+            _ => Ok(ty),
+        }
+    }
+
+    fn resolve_slot(&mut self, node: &Node, item: &Type) {
+        if let Some(slot) = node.slot {
+            self.slots[slot].item = Some(item.clone());
+        }
+    }
+
+    // Wraps an already checked node in a cast to `ty`
+    fn cast(node: &mut Node, ty: &Type) -> Type {
+        let span = node.span;
+        let value = mem::replace(node, Node::new(NodeKind::Error, span));
+        *node = Node::new(NodeKind::Cast(ty.clone(), Box::new(value)), span);
+        node.ty = ty.clone();
+        ty.clone()
+    }
+
+    fn visit_value(&mut self, node: &mut Node) -> Result<Type, Diagnostic> {
+        let span = node.span;
+        match self.visit(node)? {
+            Type::Void => Err(Diagnostic::error("expected a value, found void", span)),
+            ty => Ok(ty),
+        }
+    }
+
+    fn expect(&mut self, node: &mut Node, expected: Type, what: &str) -> Result<(), Diagnostic> {
+        let span = node.span;
+        let ty = self.visit(node)?;
+        if ty == expected {
+            Ok(())
+        } else {
+            Err(Diagnostic::error(
+                format!("expected {} {}, found {}", what, expected, ty),
+                span,
+            ))
+        }
+    }
+
+    // Variables declared in a body stop existing when it ends
+    fn block(&mut self, node: &mut Node) -> Result<Type, Diagnostic> {
+        self.scopes.push(Scope::default());
+        let result = self.visit(node);
+        self.scopes.pop();
+        result
+    }
+
+    // The innermost loop with the label, or the innermost loop without one
+    fn find_loop(
+        &self,
+        label: &Option<String>,
+        keyword: &str,
+        span: Span,
+    ) -> Result<usize, Diagnostic> {
+        let index = match label {
+            Some(label) => self
+                .loops
+                .iter()
+                .rposition(|(name, _, _)| name.as_ref() == Some(label))
+                .ok_or_else(|| {
+                    Diagnostic::error(format!("use of undeclared label `'{}`", label), span)
+                })?,
+            None => self.loops.len().checked_sub(1).ok_or_else(|| {
+                Diagnostic::error(format!("`{}` outside of a loop", keyword), span)
+            })?,
+        };
+        Ok(index)
+    }
+
+    fn condition(&mut self, node: &mut Node, context: &str) -> Result<(), Diagnostic> {
+        self.expect(node, Type::Bool, "a").map_err(|error| {
+            error.with_note(format!(
+                "{} can only have a bool as their condition",
+                context
+            ))
+        })
+    }
+
+    fn item_type(ty: &Type, span: Span) -> Result<Type, Diagnostic> {
+        match ty {
+            Type::Str => Ok(Type::Char),
+            Type::Array(ty, _) | Type::List(ty) => Ok(*ty.clone()),
+            _ => Err(Diagnostic::error(
+                format!("can't index into a value of type {}", ty),
+                span,
+            )),
+        }
+    }
+
+    fn assignment_type(&mut self, node: &mut Node) -> Result<Type, Diagnostic> {
+        let span = node.span;
+        let ty = match &mut node.kind {
+            NodeKind::Identifier(name) => match self.get_variable(name) {
+                Some(ty) => ty,
+                None => {
+                    return Err(Diagnostic::error(
+                        format!("use of undeclared variable `{}`", name),
+                        span,
+                    ))
+                }
+            },
+            NodeKind::Index(node, index) => {
+                let ty = self.assignment_type(node)?;
+                node.slot = match ty {
+                    Type::Array(ref item, _) if **item == Type::Void => self.last_slot,
+                    _ => None,
+                };
+                let item_ty = Self::item_type(&ty, span)?;
+                self.expect(index, Type::Int, "an")?;
+                item_ty
+            }
+            NodeKind::Field(node, field) => {
+                let ty = self.assignment_type(node)?;
+                self.field_type(&ty, field, span)?.1
+            }
+            _ => {
+                return Err(Diagnostic::error(
+                    "invalid left-hand side of assignment",
+                    span,
+                ))
+            }
+        };
+        node.ty = ty.clone();
+        Ok(ty)
+    }
+
+    fn unary_type(op: UnaryOp, ty: &Type) -> Option<Type> {
+        use Type::*;
+        match (op, ty) {
+            (UnaryOp::Pos | UnaryOp::Neg, Int | Float) => Some(ty.clone()),
+            (UnaryOp::Not, Int | Float | Bool) => Some(Bool),
+            (UnaryOp::BitNot, Int | Char) => Some(ty.clone()),
+            _ => None,
+        }
+    }
+
+    fn binary_type(op: BinaryOp, left: &Type, right: &Type) -> Option<Type> {
+        use BinaryOp::*;
+        use Type::*;
+        match op {
+            Add | Sub | Mul | Div | Rem => match (left, right) {
+                (Int, Int) => Some(Int),
+                (Int | Float, Int | Float) => Some(Float),
+                _ => None,
+            },
+            Pow => match (left, right) {
+                (Int, Int) => Some(Int),
+                (Int | Float, Int | Float) => Some(Float),
+                _ => None,
+            },
+            BitAnd | BitOr => match (left, right) {
+                (Int, Int) | (Char, Char) => Some(left.clone()),
+                _ => None,
+            },
+            // The shift amount can be any integer, the result is the type shifted
+            Shl | Shr => match (left, right) {
+                (Int | Char, Int | Char) => Some(left.clone()),
+                _ => None,
+            },
+            And | Or => match (left, right) {
+                (Bool, Bool) => Some(Bool),
+                _ => None,
+            },
+            EqEq | Neq => match (left, right) {
+                (Int | Char, Int | Char) | (Int | Float, Int | Float) | (Bool, Bool) => Some(Bool),
+                _ => None,
+            },
+            Lt | Lte | Gt | Gte => match (left, right) {
+                (Int | Char, Int | Char) | (Int | Float, Int | Float) => Some(Bool),
+                _ => None,
+            },
+            // Values are compared directly, while strings, arrays and lists
+            // are the same if they're the same object
+            Is | IsNot => match (left, right) {
+                (Struct(_) | Void, _) => None,
+                (left, right) if left == right => Some(Bool),
+                _ => None,
+            },
+            In | NotIn => match (left, right) {
+                (Char, Str) => Some(Bool),
+                // Strings can't be compared by value
+                (Str, _) => None,
+                // Neither can structs
+                (Struct(_), _) => None,
+                (left, Array(item, _) | List(item)) if left == &**item => Some(Bool),
+                _ => None,
+            },
+        }
+    }
+
+    fn checked_binary_type(
+        op: BinaryOp,
+        left: &Type,
+        right: &Type,
+        span: Span,
+    ) -> Result<Type, Diagnostic> {
+        Self::binary_type(op, left, right).ok_or_else(|| {
+            Diagnostic::error(
+                format!(
+                    "unsupported operand types for {}: {} and {}",
+                    op, left, right
+                ),
+                span,
+            )
+        })
+    }
+
+    // `in` and `not in` on a range of ints, e.g. `x in 0..10`
+    fn range_membership(
+        &mut self,
+        op: BinaryOp,
+        l_ty: &Type,
+        range: &mut Node,
+        span: Span,
+    ) -> Result<Type, Diagnostic> {
+        if *l_ty != Type::Int {
+            return Err(Diagnostic::error(
+                format!("unsupported operand types for {}: {} and a range", op, l_ty),
+                span,
+            ));
+        }
+        if let NodeKind::Range(start, end) = &mut range.kind {
+            self.expect(start, Type::Int, "an")?;
+            self.expect(end, Type::Int, "an")?;
+        }
+        Ok(Type::Bool)
+    }
+
+    fn can_cast(from: &Type, to: &Type) -> bool {
+        use Type::*;
+        matches!(
+            (from, to),
+            (Int | Float | Bool, Int | Float | Bool) | (Str, Str) | (Char, Char)
+        ) || matches!((from, to), (Array(from, _) | List(from), List(to)) if from == to)
+    }
+
+    fn list_builtin(
+        &mut self,
+        name: &str,
+        args: &mut [Node],
+        span: Span,
+    ) -> Result<Type, Diagnostic> {
+        let arg_count = if name == "push" { 2 } else { 1 };
+        if args.len() != arg_count {
+            return Err(Diagnostic::error(
+                format!(
+                    "`{}` takes {} argument{} but {} {} supplied",
+                    name,
+                    arg_count,
+                    if arg_count == 1 { "" } else { "s" },
+                    args.len(),
+                    if args.len() == 1 { "was" } else { "were" },
+                ),
+                span,
+            ));
+        }
+
+        let arg_span = args[0].span;
+        let ty = self.visit_value(&mut args[0])?;
+        let item = match (name, &ty) {
+            ("len", Type::Str | Type::Array(..)) => return Ok(Type::Int),
+            (_, Type::List(item)) => *item.clone(),
+            // An empty array that's pushed to is a list of what's pushed
+            (_, Type::Array(item, 0)) if **item == Type::Void => {
+                let slot = args[0].slot.expect("empty arrays have a slot");
+                self.slots[slot].list = true;
+                if name == "pop" {
+                    return Err(self.annotations_needed(slot, arg_span));
+                }
+                let item = self.visit_value(&mut args[1])?;
+                self.slots[slot].item = Some(item);
+                return Ok(Type::Void);
+            }
+            _ => {
+                return Err(
+                    Diagnostic::error(format!("expected a list, found {}", ty), arg_span)
+                        .with_note(format!("in an argument to `{}`", name)),
+                )
+            }
+        };
+
+        match name {
+            "len" => Ok(Type::Int),
+            "pop" => Ok(item),
+            _ => {
+                let value_ty = self.coerce(&mut args[1], &item)?;
+                if value_ty != item {
+                    return Err(Diagnostic::error(
+                        format!("mismatched types: expected {}, found {}", item, value_ty),
+                        args[1].span,
+                    )
+                    .with_note(format!("`{}` is a list of {}", args[0], item)));
+                }
+                Ok(Type::Void)
+            }
+        }
+    }
+
+    // Checks a call's arguments against the function's signature, apart from
+    // the first `checked` ones, and returns what the call evaluates to
+    fn call(
+        &mut self,
+        name: &str,
+        args: &mut [Node],
+        checked: usize,
+        span: Span,
+    ) -> Result<Type, Diagnostic> {
+        let (arg_types, var_args, return_type) = match self.get_function(name) {
+            Some(function) => (
+                function.arg_types.clone(),
+                function.var_args,
+                function.return_type.clone(),
+            ),
+            None => {
+                return Err(Diagnostic::error(
+                    format!("cannot find function `{}` in this scope", name),
+                    span,
+                ))
+            }
+        };
+
+        // A method's receiver doesn't count as one of the arguments it's given
+        let expected = arg_types.len() - checked;
+        let supplied = args.len() - checked;
+        if supplied < expected || (!var_args && supplied > expected) {
+            return Err(Diagnostic::error(
+                format!(
+                    "`{}` takes {}{} argument{} but {} {} supplied",
+                    name,
+                    if var_args { "at least " } else { "" },
+                    expected,
+                    if expected == 1 { "" } else { "s" },
+                    supplied,
+                    if supplied == 1 { "was" } else { "were" },
+                ),
+                span,
+            ));
+        }
+        for (i, arg) in args.iter_mut().enumerate().skip(checked) {
+            let expected = match arg_types.get(i) {
+                Some(expected) => expected,
+                None => {
+                    self.visit_value(arg)?;
+                    continue;
+                }
+            };
+            let ty = self.coerce(arg, expected)?;
+            if ty != *expected {
+                return Err(Diagnostic::error(
+                    format!("mismatched types: expected {}, found {}", expected, ty),
+                    arg.span,
+                )
+                .with_note(format!("in an argument to `{}`", name)));
+            }
+        }
+
+        // A recursive call can use the return type inferred so far
+        let inferred = self
+            .return_types
+            .iter()
+            .rev()
+            .find(|(function, _)| function == name)
+            .and_then(|(_, ty)| ty.clone());
+        return_type.or(inferred).ok_or_else(|| {
+            Diagnostic::error(
+                format!("can't infer the return type of `{}` here", name),
+                span,
+            )
+            .with_note(format!(
+                "consider giving `{}` a return type, e.g. `fn {}(...): int`",
+                name, name
+            ))
+        })
+    }
+
+    fn visit(&mut self, node: &mut Node) -> Result<Type, Diagnostic> {
+        let span = node.span;
+        let ty = match &mut node.kind {
+            NodeKind::Int(_) => Type::Int,
+            NodeKind::Float(_) => Type::Float,
+            NodeKind::Bool(_) => Type::Bool,
+            NodeKind::Str(_) => Type::Str,
+            NodeKind::Char(_) => Type::Char,
+            NodeKind::Array(nodes) => {
+                let mut ty = Type::Int;
+                for (i, node) in nodes.iter_mut().enumerate() {
+                    let node_span = node.span;
+                    let item_ty = match self.visit_value(node)? {
+                        item_ty @ (Type::Int
+                        | Type::Float
+                        | Type::Bool
+                        | Type::Str
+                        | Type::Char
+                        | Type::Struct(_)) => item_ty,
+                        item_ty => {
+                            return Err(Diagnostic::error(
+                                format!("arrays can't contain values of type {}", item_ty),
+                                node_span,
+                            ))
+                        }
+                    };
+                    if i > 0 && item_ty != ty {
+                        return Err(Diagnostic::error(
+                            format!("expected an array item of type {}, found {}", ty, item_ty),
+                            node_span,
+                        )
+                        .with_note("all items in an array must have the same type"));
+                    }
+                    ty = item_ty;
+                }
+                if nodes.is_empty() {
+                    self.last_slot = Some(self.slots.len());
+                    self.slots.push(Slot {
+                        span,
+                        name: None,
+                        item: None,
+                        list: false,
+                        reported: false,
+                    });
+                    ty = Type::Void;
+                }
+                Type::Array(Box::new(ty), nodes.len() as u32)
+            }
+            NodeKind::Type(ty) => {
+                return Err(Diagnostic::error(
+                    format!("expected a value, found type {}", ty),
+                    span,
+                ))
+            }
+            NodeKind::Cast(ty, node) => {
+                let value_ty = self.visit_value(node)?;
+                if !Self::can_cast(&value_ty, ty) {
+                    return Err(Diagnostic::error(
+                        format!("can't cast {} to {}", value_ty, ty),
+                        span,
+                    ));
+                }
+                ty.clone()
+            }
+            NodeKind::Identifier(name) => match self.get_variable(name) {
+                Some(ty) => ty,
+                None => {
+                    return Err(Diagnostic::error(
+                        format!("use of undeclared variable `{}`", name),
+                        span,
+                    ))
+                }
+            },
+            NodeKind::Unary(op, node) => {
+                let ty = self.visit_value(node)?;
+                match Self::unary_type(*op, &ty) {
+                    Some(ty) => ty,
+                    None => {
+                        return Err(Diagnostic::error(
+                            format!("can't apply unary {} to {}", op, ty),
+                            span,
+                        ))
+                    }
+                }
+            }
+            NodeKind::Binary(left, op @ (BinaryOp::In | BinaryOp::NotIn), right)
+                if matches!(right.kind, NodeKind::Range(..)) =>
+            {
+                let l_ty = self.visit_value(left)?;
+                self.range_membership(*op, &l_ty, right, span)?
+            }
+            NodeKind::Binary(left, op, right) => {
+                let l_ty = self.visit_value(left)?;
+                let r_ty = self.visit_value(right)?;
+                Self::checked_binary_type(*op, &l_ty, &r_ty, span)?
+            }
+            NodeKind::Compare(nodes, ops) => {
+                let last = ops.len() - 1;
+                let (first, rest) = nodes.split_first_mut().unwrap();
+                let mut l_ty = self.visit_value(first)?;
+                for (i, (op, right)) in ops.iter().zip(rest).enumerate() {
+                    // A range can only end the chain, since it isn't a value
+                    if matches!(op, BinaryOp::In | BinaryOp::NotIn)
+                        && matches!(right.kind, NodeKind::Range(..))
+                        && i == last
+                    {
+                        self.range_membership(*op, &l_ty, right, span)?;
+                        break;
+                    }
+                    let r_ty = self.visit_value(right)?;
+                    Self::checked_binary_type(*op, &l_ty, &r_ty, span)?;
+                    l_ty = r_ty;
+                }
+                Type::Bool
+            }
+            NodeKind::Range(..) => {
+                return Err(Diagnostic::error(
+                    "ranges can only be used to slice or as the iterable of a for loop",
+                    span,
+                ))
+            }
+            NodeKind::Let(name, annotation, node) => {
+                let ty = match annotation {
+                    Some(annotation) => {
+                        self.check_type(annotation, span)?;
+                        let ty = self.coerce(node, annotation)?;
+                        if ty != *annotation {
+                            return Err(Diagnostic::error(
+                                format!("mismatched types: expected {}, found {}", annotation, ty),
+                                node.span,
+                            )
+                            .with_note(format!("`{}` was declared as {}", name, annotation)));
+                        }
+                        ty
+                    }
+                    None => self.visit_value(node)?,
+                };
+
+                let slot = match ty {
+                    Type::Array(ref item, _) if **item == Type::Void => self.last_slot,
+                    _ => None,
+                };
+                if let Some(slot) = slot {
+                    self.slots[slot].name.get_or_insert_with(|| name.clone());
+                }
+                self.scope()
+                    .variables
+                    .insert(name.clone(), (ty.clone(), slot));
+                ty
+            }
+            NodeKind::IdentifierOp(name, op, node) => {
+                let mut ty = self.assignment_type(name)?;
+                let value_ty = match op {
+                    // Only an empty array's items are void, so assigning one says what it holds
+                    IdentifierOp::Eq if ty == Type::Void => {
+                        ty = self.visit_value(node)?;
+                        if let NodeKind::Index(array, _) = &name.kind {
+                            self.resolve_slot(array, &ty);
+                        }
+                        name.ty = ty.clone();
+                        ty.clone()
+                    }
+                    IdentifierOp::Eq => self.coerce(node, &ty)?,
+                    _ => self.visit_value(node)?,
+                };
+                let result_ty = match op {
+                    IdentifierOp::Eq => Some(value_ty.clone()),
+                    IdentifierOp::Add => Self::binary_type(BinaryOp::Add, &ty, &value_ty),
+                    IdentifierOp::Sub => Self::binary_type(BinaryOp::Sub, &ty, &value_ty),
+                    IdentifierOp::Mul => Self::binary_type(BinaryOp::Mul, &ty, &value_ty),
+                    IdentifierOp::Div => Self::binary_type(BinaryOp::Div, &ty, &value_ty),
+                    IdentifierOp::Rem => Self::binary_type(BinaryOp::Rem, &ty, &value_ty),
+                    IdentifierOp::Pow => Self::binary_type(BinaryOp::Pow, &ty, &value_ty),
+                    IdentifierOp::BitAnd => Self::binary_type(BinaryOp::BitAnd, &ty, &value_ty),
+                    IdentifierOp::BitOr => Self::binary_type(BinaryOp::BitOr, &ty, &value_ty),
+                    IdentifierOp::Shl => Self::binary_type(BinaryOp::Shl, &ty, &value_ty),
+                    IdentifierOp::Shr => Self::binary_type(BinaryOp::Shr, &ty, &value_ty),
+                };
+                match result_ty {
+                    Some(result_ty) if result_ty == ty => ty,
+                    Some(result_ty) => {
+                        return Err(Diagnostic::error(
+                            format!("can't assign {} to a variable of type {}", result_ty, ty),
+                            span,
+                        ))
+                    }
+                    None => {
+                        return Err(Diagnostic::error(
+                            format!(
+                                "unsupported operand types for {}: {} and {}",
+                                op, ty, value_ty
+                            ),
+                            span,
+                        ))
+                    }
+                }
+            }
+            NodeKind::Index(node, index) => {
+                let node_span = node.span;
+                let ty = self.visit_value(node)?;
+                let item_ty = Self::item_type(&ty, node_span)?;
+                match (&mut index.kind, &ty) {
+                    // Slicing copies the items into a new list
+                    (NodeKind::Range(start, end), Type::Array(item, _) | Type::List(item)) => {
+                        let item = item.clone();
+                        self.expect(start, Type::Int, "an")?;
+                        self.expect(end, Type::Int, "an")?;
+                        Type::List(item)
+                    }
+                    (NodeKind::Range(..), _) => {
+                        return Err(Diagnostic::error(
+                            format!("can't slice a value of type {}", ty),
+                            node_span,
+                        )
+                        .with_note("only arrays and lists can be sliced"))
+                    }
+                    _ => {
+                        self.expect(index, Type::Int, "an")?;
+                        item_ty
+                    }
+                }
+            }
+            NodeKind::If(condition, body, else_case) => {
+                self.condition(condition, "if statements")?;
+                let then_ty = self.block(body)?;
+                match else_case {
+                    Some(else_case) => {
+                        // Branches of different types are only valid as a statement
+                        let else_ty = self.block(else_case)?;
+                        if then_ty == else_ty {
+                            then_ty
+                        } else {
+                            Type::Void
+                        }
+                    }
+                    None => Type::Void,
+                }
+            }
+            NodeKind::While(label, condition, body, else_case) => {
+                self.condition(condition, "while loops")?;
+                let keyword = match else_case {
+                    Some(_) => "while else",
+                    None => "while",
+                };
+                self.loops.push((label.clone(), keyword, None));
+                let result = self.block(body);
+                let (_, _, ty) = self.loops.pop().unwrap();
+                result?;
+                match else_case {
+                    // Every way out of the loop has to leave the same type
+                    Some(else_case) => {
+                        let else_span = else_case.span;
+                        let else_ty = match &ty {
+                            Some(ty) if *ty != Type::Void => {
+                                self.scopes.push(Scope::default());
+                                let result = self.coerce(else_case, ty);
+                                self.scopes.pop();
+                                result?
+                            }
+                            _ => self.block(else_case)?,
+                        };
+                        match ty {
+                            Some(ty) if ty != else_ty => {
+                                return Err(Diagnostic::error(
+                                    format!("mismatched types: expected {}, found {}", ty, else_ty),
+                                    else_span,
+                                )
+                                .with_note("the loop's type was decided by a `break`"))
+                            }
+                            _ => else_ty,
+                        }
+                    }
+                    None => Type::Void,
+                }
+            }
+            NodeKind::Loop(label, body) => {
+                self.loops.push((label.clone(), "loop", None));
+                let result = self.block(body);
+                let (_, _, ty) = self.loops.pop().unwrap();
+                result?;
+                ty.unwrap_or(Type::Void)
+            }
+            NodeKind::Break(label, value) => {
+                let index = self.find_loop(label, "break", span)?;
+                let (_, keyword, expected) = self.loops[index].clone();
+                let ty =
+                    match value {
+                        Some(value) if keyword == "while" => return Err(Diagnostic::error(
+                            "can't break with a value from a `while` loop without an `else`",
+                            value.span,
+                        )
+                        .with_note(
+                            "the `else` is the loop's value when its condition fails, e.g. `while c { break 1 } else: 0`",
+                        )),
+                        Some(value) if keyword == "for" => return Err(Diagnostic::error(
+                            "can't break with a value from a `for` loop",
+                            value.span,
+                        )
+                        .with_note(
+                            "only `loop` and `while` with an `else` have a value, since a `for` loop can end without a `break`",
+                        )),
+                        Some(value) => match &expected {
+                            Some(expected) if *expected != Type::Void => {
+                                self.coerce(value, expected)?
+                            }
+                            _ => self.visit_value(value)?,
+                        },
+                        None => Type::Void,
+                    };
+                match expected {
+                    Some(expected) if expected != ty => {
+                        return Err(Diagnostic::error(
+                            format!("mismatched types: expected {}, found {}", expected, ty),
+                            span,
+                        )
+                        .with_note("the loop's type was decided by an earlier `break`"))
+                    }
+                    _ => self.loops[index].2 = Some(ty),
+                }
+                Type::Void
+            }
+            NodeKind::Continue(label) => {
+                self.find_loop(label, "continue", span)?;
+                Type::Void
+            }
+            NodeKind::For(label, identifier, iterable, body) => {
+                let iterable_span = iterable.span;
+                let item_ty = match &mut iterable.kind {
+                    NodeKind::Range(start, end) => {
+                        self.expect(start, Type::Int, "an")?;
+                        self.expect(end, Type::Int, "an")?;
+                        Type::Int
+                    }
+                    _ => match self.visit_value(iterable)? {
+                        Type::Array(item, _) if *item == Type::Void => {
+                            let slot = self.last_slot.expect("empty arrays have a slot");
+                            return Err(self.annotations_needed(slot, iterable_span));
+                        }
+                        ty @ (Type::Str | Type::Array(..) | Type::List(_)) => {
+                            Self::item_type(&ty, iterable_span)?
+                        }
+                        ty => {
+                            return Err(Diagnostic::error(
+                                format!("can't iterate over a value of type {}", ty),
+                                iterable_span,
+                            )
+                            .with_note(
+                                "only arrays, lists, strings and ranges can be iterated over",
+                            ))
+                        }
+                    },
+                };
+
+                self.scopes.push(Scope::default());
+                self.declare_variable(identifier, item_ty);
+                self.loops.push((label.clone(), "for", None));
+                let result = self.visit(body);
+                self.loops.pop();
+                self.scopes.pop();
+                result?;
+                Type::Void
+            }
+            NodeKind::Fn(name, args, return_type, body) => {
+                for ty in args.iter().map(|(_, ty)| ty).chain(return_type.iter()) {
+                    self.check_type(ty, span)?;
+                }
+                if !self.scope().functions.contains_key(name.as_str()) {
+                    let arg_types = args.iter().map(|(_, ty)| ty.clone()).collect();
+                    self.declare_function(name, arg_types, false, return_type.clone());
+                }
+
+                self.scopes.push(Scope {
+                    function: true,
+                    ..Scope::default()
+                });
+                for (arg_name, ty) in args.iter() {
+                    self.declare_variable(arg_name, ty.clone());
+                }
+                self.return_types.push((name.clone(), return_type.clone()));
+                // Loops outside the function can't be left from inside it
+                let loops = std::mem::take(&mut self.loops);
+                let result = self.visit(body);
+                self.loops = loops;
+                let (_, inferred) = self.return_types.pop().unwrap();
+                self.scopes.pop();
+
+                // Functions without a return statement return void
+                let inferred = inferred.unwrap_or(Type::Void);
+                if let Some(function) = self.scope().functions.get_mut(name.as_str()) {
+                    function.return_type = Some(inferred.clone());
+                }
+                *return_type = Some(inferred.clone());
+                result?;
+                if inferred != Type::Void && !Self::always_returns(body) {
+                    return Err(Diagnostic::error(
+                        format!("function `{}` may not return a value", name),
+                        span,
+                    )
+                    .with_note(format!("every path through it has to return {}", inferred)));
+                }
+                Type::Void
+            }
+            NodeKind::Return(node) => {
+                let node_span = node.span;
+                let expected = match self.return_types.last() {
+                    Some((_, expected)) => expected.clone(),
+                    None => {
+                        return Err(Diagnostic::error(
+                            "can't return outside of a function",
+                            span,
+                        ))
+                    }
+                };
+                match expected {
+                    Some(expected) => {
+                        let ty = self.coerce(node, &expected)?;
+                        if ty != expected {
+                            return Err(Diagnostic::error(
+                                format!("mismatched types: expected {}, found {}", expected, ty),
+                                node_span,
+                            )
+                            .with_note(format!("the function returns {}", expected)));
+                        }
+                    }
+                    // The first return statement decides an omitted return type
+                    None => {
+                        let ty = self.visit_value(node)?;
+                        self.return_types.last_mut().unwrap().1 = Some(ty);
+                    }
+                }
+                Type::Void
+            }
+            NodeKind::Call(name, args)
+                if LIST_BUILTINS.contains(&name.as_str()) && self.get_function(name).is_none() =>
+            {
+                self.list_builtin(name, args, span)?
+            }
+            NodeKind::Call(name, args) => self.call(name, args, 0, span)?,
+            NodeKind::MethodCall(receiver, method, args) => {
+                let ty = self.visit_value(receiver)?;
+                let name = match &ty {
+                    Type::Struct(name) => format!("{}.{}", name, method),
+                    _ => String::new(),
+                };
+                if self.get_function(&name).is_none() {
+                    return Err(Diagnostic::error(
+                        format!("no method `{}` on type {}", method, ty),
+                        span,
+                    ));
+                }
+
+                // The receiver is already checked, so the call skips it
+                let receiver = mem::replace(receiver.as_mut(), Node::new(NodeKind::Error, span));
+                let mut call_args = vec![receiver];
+                call_args.append(args);
+                let ty = self.call(&name, &mut call_args, 1, span);
+                node.kind = NodeKind::Call(name, call_args);
+                ty?
+            }
+            NodeKind::Statements(nodes) => {
+                self.methods(nodes);
+                self.declare_functions(nodes)?;
+
+                // Function bodies can't see the variables around them, so they're
+                // checked first to infer return types before any calls
+                for i in Self::definition_order(nodes) {
+                    if let Err(error) = self.visit(&mut nodes[i]) {
+                        self.errors.push(error);
+                    }
+                }
+
+                let mut ty = Type::Void;
+                // A loop over an empty array is checked after the rest of the block,
+                // which may say what the array holds, with the variables it saw
+                let mut deferred = vec![];
+                for (i, node) in nodes.iter_mut().enumerate() {
+                    if let NodeKind::Fn(..) = node.kind {
+                        ty = Type::Void;
+                        continue;
+                    }
+                    if self.iterates_empty_array(node) {
+                        deferred.push((i, self.scopes.clone()));
+                        ty = Type::Void;
+                        continue;
+                    }
+                    // Keep going so every statement's errors are reported
+                    ty = match self.visit(node) {
+                        Ok(ty) => ty,
+                        Err(error) => {
+                            self.errors.push(error);
+                            Type::Void
+                        }
+                    };
+                }
+                for (i, scopes) in deferred {
+                    let scopes = mem::replace(&mut self.scopes, scopes);
+                    if let Err(error) = self.visit(&mut nodes[i]) {
+                        self.errors.push(error);
+                    }
+                    self.scopes = scopes;
+                }
+                ty
+            }
+            // Declared up front by `declare_structs`
+            NodeKind::Struct(..) => Type::Void,
+            // Blocks turn these into functions before visiting their statements
+            NodeKind::Impl(..) => {
+                return Err(Diagnostic::error(
+                    "`impl` blocks can only be statements of a block",
+                    span,
+                ))
+            }
+            NodeKind::StructLiteral(name, fields) => {
+                let declared = match self.structs.get(name) {
+                    Some(declared) => declared.clone(),
+                    None => {
+                        return Err(Diagnostic::error(
+                            format!("cannot find struct `{}` in this scope", name),
+                            span,
+                        ))
+                    }
+                };
+
+                for (i, (field, node)) in fields.iter().enumerate() {
+                    if fields[..i].iter().any(|(other, _)| other == field) {
+                        return Err(Diagnostic::error(
+                            format!("field `{}` specified more than once", field),
+                            node.span,
+                        ));
+                    }
+                }
+                if let Some((missing, _)) = declared
+                    .iter()
+                    .find(|(field, _)| fields.iter().all(|(other, _)| other != field))
+                {
+                    return Err(Diagnostic::error(
+                        format!("missing field `{}` in initializer of `{}`", missing, name),
+                        span,
+                    ));
+                }
+
+                for (field, node) in fields.iter_mut() {
+                    let expected = match declared.iter().find(|(other, _)| other == field) {
+                        Some((_, expected)) => expected,
+                        None => {
+                            return Err(Diagnostic::error(
+                                format!("struct `{}` has no field named `{}`", name, field),
+                                node.span,
+                            ))
+                        }
+                    };
+                    let ty = self.coerce(node, expected)?;
+                    if ty != *expected {
+                        return Err(Diagnostic::error(
+                            format!("mismatched types: expected {}, found {}", expected, ty),
+                            node.span,
+                        )
+                        .with_note(format!("in the field `{}` of `{}`", field, name)));
+                    }
+                }
+                Type::Struct(name.clone())
+            }
+            NodeKind::Field(node, field) => {
+                let ty = self.visit_value(node)?;
+                self.field_type(&ty, field, span)?.1
+            }
+            NodeKind::Error => Type::Void,
+        };
+        node.slot = match ty {
+            Type::Array(ref item, _) if **item == Type::Void => self.last_slot,
+            _ => None,
+        };
+        node.ty = ty.clone();
+        Ok(ty)
+    }
+}
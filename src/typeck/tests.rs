@@ -0,0 +1,155 @@
+use crate::{Lexer, Parser, TypeChecker};
+
+// Checks `source`, returning the message of each error it has
+fn errors(source: &str) -> Vec<String> {
+    let tokens = Lexer::new(source.to_string()).lex().0;
+    let (ast, errors) = Parser::new(tokens).parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    let (_, errors) = TypeChecker::new().check(ast);
+    errors.into_iter().map(|error| error.message).collect()
+}
+
+#[test]
+fn functions_with_a_value_return_on_every_path() {
+    let cases = [
+        "fn origin(): int {\n  if false: return 1\n}",
+        "fn first() {\n  while true: return 1\n}",
+        "fn stop(): int {\n  loop: break\n}",
+        "fn outer(): int {\n  'outer: loop {\n    loop: break 'outer\n  }\n}",
+    ];
+    for source in cases {
+        let name = source[3..].split('(').next().unwrap();
+        assert_eq!(
+            errors(source),
+            [format!("function `{}` may not return a value", name)],
+            "{}",
+            source
+        );
+    }
+
+    assert_eq!(
+        errors(
+            "fn sign(n: int): int {
+  if n < 0 { return -1 } else { return 1 }
+}
+fn forever(): int {
+  loop {
+    for i in 0..3: break
+  }
+}
+fn greet() {
+  if true: print(\"hi\")
+}"
+        ),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn operands_have_to_fit_their_operator() {
+    assert_eq!(
+        errors("let a = 1 + true\nlet b = \"a\" - 1\nlet c = 1 < \"b\"\nlet d = 1 + 2.0"),
+        [
+            "unsupported operand types for +: int and bool",
+            "unsupported operand types for -: str and int",
+            "unsupported operand types for <: int and str",
+        ]
+    );
+}
+
+#[test]
+fn conditions_have_to_be_bools() {
+    assert_eq!(
+        errors("if 1: print(1)\nwhile \"yes\": break\nlet a = 1 and true\nif not \"no\": print(2)"),
+        [
+            "expected a bool, found int",
+            "expected a bool, found str",
+            "unsupported operand types for and: int and bool",
+            "can't apply unary not to str",
+        ]
+    );
+}
+
+#[test]
+fn casts_only_go_between_scalars() {
+    assert_eq!(
+        errors("let a = int(\"1\")\nlet b = float([1, 2])\nlet c = int(1.5)"),
+        ["can't cast str to int", "can't cast int[2] to float"]
+    );
+}
+
+#[test]
+fn returns_match_the_function_type() {
+    assert_eq!(
+        errors(
+            "fn half(n: int): float {\n  return n > 2\n}
+fn name(): int {\n  return \"thor\"\n}
+fn twice(n: int): int {\n  return n * 2\n}
+return 3"
+        ),
+        [
+            "mismatched types: expected float, found bool",
+            "mismatched types: expected int, found str",
+            "can't return outside of a function",
+        ]
+    );
+}
+
+#[test]
+fn empty_arrays_take_their_type_from_how_they_are_used() {
+    let cases = [
+        "let xs = []\npush(xs, 3)\nlet n: int = pop(xs)",
+        "let xs = []\nxs[0] = 1.5\nlet x: float = xs[0]",
+        "let xs = []\nfor x in xs {\n  let y: int = x\n}\npush(xs, 3)",
+        "let xs = []\nlet ys = xs\npush(ys, 'a')\nlet c: char = xs[0]",
+    ];
+    for source in cases {
+        assert_eq!(errors(source), Vec::<String>::new(), "{}", source);
+    }
+}
+
+#[test]
+fn empty_arrays_nothing_says_the_type_of_need_annotations() {
+    let cases = [
+        "let xs = []\nlet n = pop(xs)",
+        "let xs = []\nfor x in xs: print(x)",
+        "let xs = []\nprint(len(xs))",
+    ];
+    for source in cases {
+        assert_eq!(
+            errors(source),
+            ["type annotations needed for `xs`"],
+            "{}",
+            source
+        );
+    }
+    assert_eq!(
+        errors("let a: int[3] = []\nlet b = [] + 1"),
+        [
+            "mismatched types: expected int[3], found []",
+            "unsupported operand types for +: [] and int",
+            "type annotations needed for this array",
+            "type annotations needed for this array",
+        ]
+    );
+}
+
+#[test]
+fn only_loops_that_always_have_a_value_break_with_one() {
+    assert_eq!(
+        errors(
+            "let a = while true: break 1
+let b = for i in 0..3: break i
+let c = while true { break 1 } else: 2.5
+let d = while true { break } else: 2
+let e = while true { break 1 } else: 2
+let f = loop: break 'x'"
+        ),
+        [
+            "can't break with a value from a `while` loop without an `else`",
+            "can't break with a value from a `for` loop",
+            "mismatched types: expected int, found float",
+            "mismatched types: expected void, found int",
+        ]
+    );
+}
@@ -0,0 +1,209 @@
+// Runs each program in tests/programs every way thor can run one: through the
+// interpreter, and with LLVM also through the JIT and as a linked executable.
+// Every run has to print what the program's .stdout file says and exit with the
+// code in its .exit file. `cargo test --test programs -- --bless` rewrites those
+// files from what the programs do now, as long as every way agrees.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command, Output},
+};
+
+const THOR: &str = env!("CARGO_BIN_EXE_thor");
+
+// What a program printed and how it exited, as its expectation files spell it out
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    stdout: String,
+    exit: String,
+}
+
+impl Outcome {
+    fn new(output: &Output) -> Self {
+        Self {
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            exit: match output.status.code() {
+                Some(code) => code.to_string(),
+                None => output.status.to_string(),
+            },
+        }
+    }
+}
+
+type Backend = fn(&Path) -> Result<Output, String>;
+
+fn main() {
+    let mut bless = false;
+    let mut filters = vec![];
+    for arg in env::args().skip(1) {
+        if arg == "--bless" {
+            bless = true;
+        } else if !arg.starts_with('-') {
+            filters.push(arg);
+        }
+        // Other flags are libtest's, which don't mean anything here
+    }
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    let mut programs: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some("thor".as_ref()))
+        .filter(|path| filters.is_empty() || filters.iter().any(|f| name(path).contains(f)))
+        .collect();
+    programs.sort();
+
+    println!("\nrunning {} programs", programs.len());
+    let mut failures = vec![];
+    for program in &programs {
+        let result = if bless {
+            bless_program(program)
+        } else {
+            check(program)
+        };
+        match result {
+            Ok(()) => println!("program {} ... ok", name(program)),
+            Err(message) => {
+                println!("program {} ... FAILED", name(program));
+                failures.push((name(program), message));
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        println!("\nfailures:");
+        for (name, message) in &failures {
+            println!("\n---- {} ----\n{}", name, message);
+        }
+        println!("\nrerun with `-- --bless` if the new output is right");
+    }
+    println!(
+        "\nprogram result: {}. {} passed; {} failed\n",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        programs.len() - failures.len(),
+        failures.len()
+    );
+    if !failures.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn backends() -> Vec<(&'static str, Backend)> {
+    let mut backends: Vec<(&'static str, Backend)> = vec![("interpreter", interpret)];
+    if cfg!(feature = "llvm") {
+        backends.push(("JIT", jit));
+        backends.push(("executable", executable));
+    }
+    backends
+}
+
+fn interpret(program: &Path) -> Result<Output, String> {
+    thor(&["run", "--interp"], program)
+}
+
+fn jit(program: &Path) -> Result<Output, String> {
+    thor(&["run"], program)
+}
+
+// Builds the program ahead of time, links it and runs the result
+fn executable(program: &Path) -> Result<Output, String> {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name(program));
+    let build = thor(&["build", "-o", path.to_str().unwrap()], program)?;
+    if !build.status.success() {
+        return Err(format!(
+            "`thor build` failed: {}\n{}",
+            build.status,
+            String::from_utf8_lossy(&build.stderr).trim_end()
+        ));
+    }
+    let output = Command::new(&path)
+        .output()
+        .map_err(|err| format!("couldn't run {}: {}", path.display(), err));
+    let _ = fs::remove_file(&path);
+    output
+}
+
+fn thor(args: &[&str], program: &Path) -> Result<Output, String> {
+    Command::new(THOR)
+        .args(args)
+        .arg(program)
+        .output()
+        .map_err(|err| format!("couldn't run thor: {}", err))
+}
+
+fn check(program: &Path) -> Result<(), String> {
+    let expected = Outcome {
+        stdout: read(&program.with_extension("stdout"))?,
+        exit: read(&program.with_extension("exit"))?
+            .trim_end()
+            .to_string(),
+    };
+
+    let mut mismatches = vec![];
+    for (backend, run) in backends() {
+        let output = run(program)?;
+        let outcome = Outcome::new(&output);
+        if outcome != expected {
+            let mut mismatch = format!(
+                "the {} printed:\n{}and exited with {}",
+                backend, outcome.stdout, outcome.exit
+            );
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if !stderr.is_empty() {
+                mismatch.push_str(&format!(", writing to stderr:\n{}", stderr.trim_end()));
+            }
+            mismatches.push(mismatch);
+        }
+    }
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "expected:\n{}and exit code {}\n{}",
+            expected.stdout,
+            expected.exit,
+            mismatches.join("\n")
+        ))
+    }
+}
+
+fn bless_program(program: &Path) -> Result<(), String> {
+    let mut outcomes = vec![];
+    for (backend, run) in backends() {
+        outcomes.push((backend, Outcome::new(&run(program)?)));
+    }
+
+    // Only one way can be right when they disagree, so leave it for a person to pick
+    let (_, first) = &outcomes[0];
+    if outcomes.iter().any(|(_, outcome)| outcome != first) {
+        let outcomes: Vec<String> = outcomes
+            .iter()
+            .map(|(backend, outcome)| {
+                format!(
+                    "the {} printed:\n{}and exited with {}",
+                    backend, outcome.stdout, outcome.exit
+                )
+            })
+            .collect();
+        return Err(outcomes.join("\n"));
+    }
+
+    write(&program.with_extension("stdout"), &first.stdout)?;
+    write(
+        &program.with_extension("exit"),
+        &format!("{}\n", first.exit),
+    )
+}
+
+fn name(program: &Path) -> String {
+    program.file_stem().unwrap().to_string_lossy().into_owned()
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|err| format!("couldn't read {}: {}", path.display(), err))
+}
+
+fn write(path: &Path, contents: &str) -> Result<(), String> {
+    fs::write(path, contents).map_err(|err| format!("couldn't write {}: {}", path.display(), err))
+}